@@ -1,49 +1,95 @@
 // TODO
-// finish cornell box examples. Use rect instead of spheres for the walls.
-// use boxes and implement rotations
 // Make a nice reference picture for the portfolio
 // why SDF rendering in the default renderer has no shadows,
 // see the comment in the renderer.
-// TODO SCENE LOADING:
-// camera should be set depending on the dimension of the object
-// load gltf
-// if there is no light a light is added
-// command line version
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")] // hide console window on Windows in release
 
 use crate::tuot::color::Color;
+use crate::tuot::framebuffer::Framebuffer;
 use crate::tuot::render_error::RenderError;
 use eframe::egui;
 use egui::CollapsingHeader;
 use egui::Color32;
 use egui_extras::RetainedImage;
-use glam::Vec3A;
 use image::ImageBuffer;
 use image::Rgba;
-use tuot::camera::Camera;
-use tuot::material::Material;
-use tuot::sphere::Sphere;
-use tuot::texture::Texture;
 use std::path::Path;
-use std::time::Instant;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, Receiver, Sender, TryRecvError};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Instant, SystemTime};
 use tuot;
+use tuot::aabb::Aabb;
+use tuot::animation::{render_sequence, Animation, Interpolation, SequenceOpt};
 use tuot::camera_effects::camera_distorter_opt::CameraDistorterOpt;
 use tuot::camera_effects::CameraEffects;
-use tuot::examples::get_world_and_camera;
+use tuot::examples::{example_camera, example_world};
 use tuot::examples::Worlds;
+use tuot::framing::{add_key_light, frame_camera_description};
 use tuot::montecarlo_pimped::deflection_opt::DeflectionForce;
 use tuot::montecarlo_pimped::deflection_opt::DeflectionOpt;
-use tuot::montecarlo_pimped::renderer::render_montecarlo_pimped;
 use tuot::montecarlo_pimped::tint_opt::BandOp;
 use tuot::montecarlo_pimped::tint_opt::TintOpt;
-use tuot::renderer::render_montecarlo;
-use tuot::utils::load_obj_to_hitable;
+use tuot::progressive::{ProgressiveRenderer, RenderProgress};
+use tuot::render_settings::{ProgressiveMode, RenderEngine, RenderSettings};
+use tuot::hitable::Hitable;
+use tuot::gltf_import::{is_gltf, load_gltf, GltfOpt};
+use tuot::mesh_import::load_mesh_to_hitable;
 use tuot::scene::Scene;
+use tuot::scene_description::{CameraDescription, SceneDescription};
+use tuot::tone_mapping::ToneMapOperator;
+use tuot::tone_mapping::ToneMappingOpt;
+use tuot::tone_mapping::TransferCurve;
 
-#[derive(PartialEq)]
-enum RendererEngine {
-    MonteCarlo,
-    MonteCarloPimped,
+// What the render thread sends back to the GUI
+enum RenderMessage {
+    Progress(RenderProgress, Framebuffer),
+    Done(Result<Framebuffer, RenderError>),
+    // a frame of the sequence has been saved
+    SequenceFrame(u32, Framebuffer),
+    SequenceDone(Result<(), RenderError>),
+    // the world has been built, it is kept for the next renders
+    World(CachedWorld),
+}
+
+// A render running in its own thread
+struct RenderJob {
+    receiver: Receiver<RenderMessage>,
+    cancel: Arc<AtomicBool>,
+    started: Instant,
+    progress: f32,
+}
+
+// The world to render, built by the render thread when it is not cached
+struct WorldOpt {
+    key: WorldKey,
+    camera_fov: f32,
+    camera_aperture: f32,
+    cached: Option<CachedWorld>,
+}
+
+// Everything the geometry of a world depends on
+#[derive(Clone, PartialEq)]
+struct WorldKey {
+    worlds: Worlds,
+    picked_path: Option<String>,
+    // a file changed on disk is loaded again
+    modified: Option<SystemTime>,
+    seed: u64,
+}
+
+// A world already built. Its scene is reused by the next renders, changing only the
+// camera, until the key changes.
+#[derive(Clone)]
+struct CachedWorld {
+    key: WorldKey,
+    scene: Scene,
+    animation: Animation,
+    // the camera of a scene file, the other worlds use the camera options of the GUI
+    file_camera: Option<CameraDescription>,
+    // the bounding box of a model opened without a scene file, framed by the camera
+    model_bounds: Option<Aabb>,
 }
 
 fn main() {
@@ -61,59 +107,335 @@ fn main() {
 }
 
 struct MyApp {
-    render_engine: RendererEngine,
+    render_engine: RenderEngine,
+    progressive_mode: ProgressiveMode,
+    render_job: Option<RenderJob>,
     camera_effects: CameraEffects,
     worlds: Worlds,
     max_depth: usize,
     last_frame_rendered: Option<RetainedImage>,
     image_buffer: Option<ImageBuffer<Rgba<u8>, Vec<u8>>>,
+    framebuffer: Option<Framebuffer>,
     frame_width: u32,
     frame_height: u32,
     camera_fov: f32,
     camera_aperture: f32,
     samples: usize,
+    seed: u64,
     n_current_frame: u32,
     tot_frames: u32,
-    _renderings_folder: String,
+    renderings_folder: String,
+    animation: Animation,
     elapsed_time: String,
     camera_distorter_opt: CameraDistorterOpt,
     deflection_opt: DeflectionOpt,
     background_color: Color32,
     color_normal: Color32,
     tint_opt: TintOpt,
+    tone_mapping_opt: ToneMappingOpt,
     picked_path: Option<String>,
+    world_cache: Option<CachedWorld>,
 }
 
 impl Default for MyApp {
     fn default() -> Self {
         Self {
-            render_engine: RendererEngine::MonteCarlo,
+            render_engine: RenderEngine::MonteCarlo,
+            progressive_mode: ProgressiveMode::Passes,
+            render_job: None,
             camera_effects: CameraEffects::NoEffects,
             worlds: Worlds::SdfWall,
             last_frame_rendered: None,
             max_depth: 4, //
             image_buffer: None,
+            framebuffer: None,
             frame_width: 640,
             frame_height: 400,
             camera_fov: 40.0,
             camera_aperture: 0.1,
             samples: 4,
+            seed: 0,
             n_current_frame: 0,
             tot_frames: 25,
-            _renderings_folder: "renderings".to_string(),
+            renderings_folder: "renderings".to_string(),
+            animation: Animation::default(),
             elapsed_time: "0".to_string(),
             camera_distorter_opt: CameraDistorterOpt::default(),
             deflection_opt: DeflectionOpt::default(),
             color_normal: Color32::YELLOW,
             background_color: Color32::from_rgb(209, 193, 89),
             tint_opt: TintOpt::default(),
+            tone_mapping_opt: ToneMappingOpt::default(),
             picked_path: None,
+            world_cache: None,
+        }
+    }
+}
+
+impl MyApp {
+    fn update_displayed_frame(&mut self) {
+        if let Some(framebuffer) = &self.framebuffer {
+            let image_buffer = framebuffer.to_rgba_tone_mapped(&self.tone_mapping_opt);
+            let color_image = egui::ColorImage::from_rgba_unmultiplied(
+                [framebuffer.width() as usize, framebuffer.height() as usize],
+                &image_buffer,
+            );
+            self.last_frame_rendered = Some(RetainedImage::from_color_image("0.png", color_image));
+            self.image_buffer = Some(image_buffer);
+        }
+    }
+
+    fn render_settings(&mut self) -> RenderSettings {
+        self.tint_opt.normal_color = Color::from_array(self.color_normal.to_array());
+        self.tint_opt.background_color = Color::from_array(self.background_color.to_array());
+        RenderSettings {
+            width: self.frame_width,
+            height: self.frame_height,
+            max_depth: self.max_depth,
+            samples: self.samples,
+            seed: self.seed,
+            current_frame: self.n_current_frame,
+            engine: self.render_engine,
+            camera_effects: self.camera_effects,
+            camera_distorter_opt: self.camera_distorter_opt,
+            deflection_opt: self.deflection_opt,
+            tint_opt: self.tint_opt,
+            mode: self.progressive_mode,
+            ..RenderSettings::default()
+        }
+    }
+
+    fn world_opt(&self) -> WorldOpt {
+        let key = WorldKey {
+            worlds: self.worlds,
+            picked_path: self.picked_path.clone(),
+            modified: self
+                .picked_path
+                .as_ref()
+                .and_then(|path| std::fs::metadata(path).ok())
+                .and_then(|metadata| metadata.modified().ok()),
+            seed: self.seed,
+        };
+        let cached = self
+            .world_cache
+            .clone()
+            .filter(|cached| cached.key == key);
+        WorldOpt {
+            key,
+            camera_fov: self.camera_fov,
+            camera_aperture: self.camera_aperture,
+            cached,
+        }
+    }
+
+    fn start_render(&mut self, ctx: &egui::Context) {
+        let settings = self.render_settings();
+        let world_opt = self.world_opt();
+        let renderer = ProgressiveRenderer::new(settings);
+        let (sender, receiver) = channel();
+        self.render_job = Some(RenderJob {
+            receiver,
+            cancel: renderer.cancel_flag(),
+            started: Instant::now(),
+            progress: 0.0,
+        });
+        let ctx = ctx.clone();
+        thread::spawn(move || {
+            let result = render(&world_opt, renderer, &sender, &ctx);
+            // the GUI may be gone already, nothing to do in that case
+            let _ = sender.send(RenderMessage::Done(result));
+            ctx.request_repaint();
+        });
+    }
+
+    // Renders all the frames of the timeline into the renderings folder
+    fn start_sequence(&mut self, ctx: &egui::Context) {
+        let settings = self.render_settings();
+        let world_opt = self.world_opt();
+        let gui_animation = self.animation.clone();
+        let sequence = SequenceOpt {
+            first_frame: 0,
+            last_frame: self.tot_frames,
+            folder: self.renderings_folder.clone().into(),
+            tone_mapping: self.tone_mapping_opt.clone(),
+            ..SequenceOpt::default()
+        };
+        let (sender, receiver) = channel();
+        let cancel = Arc::new(AtomicBool::new(false));
+        self.render_job = Some(RenderJob {
+            receiver,
+            cancel: Arc::clone(&cancel),
+            started: Instant::now(),
+            progress: 0.0,
+        });
+        let ctx = ctx.clone();
+        thread::spawn(move || {
+            let result = cached_world(&world_opt, &settings, &sender).and_then(|world| {
+                // the keyframes of a scene file win over the ones added in the GUI
+                let animation = if world.animation.is_empty() {
+                    gui_animation
+                } else {
+                    world.animation.clone()
+                };
+                // a stop request is checked between the frames
+                render_sequence(
+                    &sequence,
+                    &settings,
+                    &animation,
+                    &world.scene,
+                    &camera(&world, &world_opt, &settings),
+                    |frame, framebuffer| {
+                        let _ = sender
+                            .send(RenderMessage::SequenceFrame(frame, framebuffer.clone()));
+                        ctx.request_repaint();
+                        !cancel.load(Ordering::Relaxed)
+                    },
+                )
+            });
+            let _ = sender.send(RenderMessage::SequenceDone(result));
+            ctx.request_repaint();
+        });
+    }
+
+    // Sets the values in the GUI to the ones of the current frame
+    fn apply_animation(&mut self) {
+        let frame = self.n_current_frame;
+        let animation = &self.animation;
+        animation.fov.apply(frame, &mut self.camera_fov);
+        animation.aperture.apply(frame, &mut self.camera_aperture);
+        animation
+            .deflection_amplitude
+            .apply(frame, &mut self.deflection_opt.amplitude);
+        animation
+            .deflection_randomness
+            .apply(frame, &mut self.deflection_opt.randomness);
+        if let Some(col) = animation.tint_normal_color.value_at(frame as f32) {
+            self.color_normal = to_color32(col);
+        }
+        if let Some(col) = animation.tint_background_color.value_at(frame as f32) {
+            self.background_color = to_color32(col);
+        }
+        animation.tint_mix.apply(frame, &mut self.tint_opt.mix);
+        animation.tint_freq.apply(frame, &mut self.tint_opt.freq);
+        animation
+            .tint_amplitude
+            .apply(frame, &mut self.tint_opt.amplitude);
+        animation
+            .distorter_noise_scale
+            .apply(frame, &mut self.camera_distorter_opt.noise_scale);
+        animation
+            .distorter_amplitude
+            .apply(frame, &mut self.camera_distorter_opt.amplitude);
+        animation
+            .distorter_easing
+            .apply(frame, &mut self.camera_distorter_opt.easing);
+    }
+
+    // Keys all the values that can be changed in the GUI at the current frame
+    fn add_keyframe(&mut self) {
+        let frame = self.n_current_frame;
+        let key = Interpolation::Linear;
+        let animation = &mut self.animation;
+        animation.fov.add_key(frame, self.camera_fov, key);
+        animation.aperture.add_key(frame, self.camera_aperture, key);
+        animation
+            .deflection_amplitude
+            .add_key(frame, self.deflection_opt.amplitude, key);
+        animation
+            .deflection_randomness
+            .add_key(frame, self.deflection_opt.randomness, key);
+        animation
+            .tint_normal_color
+            .add_key(frame, to_array(self.color_normal), key);
+        animation
+            .tint_background_color
+            .add_key(frame, to_array(self.background_color), key);
+        animation.tint_mix.add_key(frame, self.tint_opt.mix, key);
+        animation.tint_freq.add_key(frame, self.tint_opt.freq, key);
+        animation
+            .tint_amplitude
+            .add_key(frame, self.tint_opt.amplitude, key);
+        animation
+            .distorter_noise_scale
+            .add_key(frame, self.camera_distorter_opt.noise_scale, key);
+        animation
+            .distorter_amplitude
+            .add_key(frame, self.camera_distorter_opt.amplitude, key);
+        animation
+            .distorter_easing
+            .add_key(frame, self.camera_distorter_opt.easing, key);
+    }
+
+    fn poll_render(&mut self) {
+        let job = match &mut self.render_job {
+            Some(job) => job,
+            None => return,
+        };
+        let mut received = false;
+        let mut finished = None;
+        loop {
+            match job.receiver.try_recv() {
+                Ok(RenderMessage::Progress(progress, framebuffer)) => {
+                    job.progress = progress.fraction();
+                    self.framebuffer = Some(framebuffer);
+                    received = true;
+                }
+                Ok(RenderMessage::Done(result)) => {
+                    finished = Some(result);
+                    break;
+                }
+                Ok(RenderMessage::World(world)) => {
+                    self.world_cache = Some(world);
+                }
+                Ok(RenderMessage::SequenceFrame(frame, framebuffer)) => {
+                    job.progress = (frame + 1) as f32 / (self.tot_frames + 1) as f32;
+                    self.n_current_frame = frame;
+                    self.framebuffer = Some(framebuffer);
+                    received = true;
+                }
+                Ok(RenderMessage::SequenceDone(result)) => {
+                    if let Err(e) = result {
+                        println!("{:?}", e);
+                    }
+                    self.render_job = None;
+                    self.apply_animation();
+                    self.update_displayed_frame();
+                    return;
+                }
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    finished = Some(Err(RenderError {
+                        kind: String::from("thread"),
+                        message: String::from("the render thread stopped unexpectedly"),
+                    }));
+                    break;
+                }
+            }
+        }
+        let elapsed = job.started.elapsed();
+        if let Some(result) = finished {
+            received = true;
+            self.render_job = None;
+            match result {
+                Ok(framebuffer) => {
+                    self.elapsed_time =
+                        format!("{}.{}", elapsed.as_secs(), elapsed.subsec_millis());
+                    self.framebuffer = Some(framebuffer);
+                    self.n_current_frame += 1;
+                    self.apply_animation();
+                }
+                Err(e) => println!("{:?}", e),
+            }
+        }
+        if received {
+            self.update_displayed_frame();
         }
     }
 }
 
 impl eframe::App for MyApp {
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        self.poll_render();
         egui::SidePanel::left("scene settings").show(ctx, |ui| {
             ui.heading("Scene");
             if ui.button("Open file…").clicked() {
@@ -144,31 +466,15 @@ impl eframe::App for MyApp {
         });
         egui::CentralPanel::default().show(ctx, |ui| {
             ui.heading("An imaginary renderer");
-            if ui.button("Render").clicked() {
-                let now = Instant::now();
-
-                let render_buffer = render(self);
-                match render_buffer {
-                    Ok(render_buffer) => {
-                        self.elapsed_time = format!(
-                            "{}.{}",
-                            now.elapsed().as_secs(),
-                            now.elapsed().subsec_millis()
-                        );
-
-                        self.image_buffer = Some(render_buffer);
-                        let color_image = egui::ColorImage::from_rgba_unmultiplied(
-                            [self.frame_width as usize, self.frame_height as usize],
-                            &self.image_buffer.as_ref().unwrap(),
-                        );
-                        let last_frame_rendered =
-                            RetainedImage::from_color_image("0.png", color_image);
-
-                        self.last_frame_rendered = Some(last_frame_rendered);
-                        self.n_current_frame += 1;
+            if let Some(job) = &self.render_job {
+                ui.horizontal(|ui| {
+                    if ui.button("Stop").clicked() {
+                        job.cancel.store(true, Ordering::Relaxed);
                     }
-                    Err(e) => println!("{:?}", e),
-                };
+                    ui.add(egui::ProgressBar::new(job.progress).show_percentage());
+                });
+            } else if ui.button("Render").clicked() {
+                self.start_render(ctx);
             }
             if let Some(img_buffer) = &self.image_buffer {
                 if ui.button("Save Frame").clicked() {
@@ -176,6 +482,14 @@ impl eframe::App for MyApp {
                     img_buffer.save(&Path::new(&filename)).unwrap();
                 }
             }
+            if let Some(framebuffer) = &self.framebuffer {
+                if ui.button("Save HDR Frame").clicked() {
+                    let filename = format!("{}.exr", self.n_current_frame);
+                    if let Err(e) = framebuffer.save_exr(Path::new(&filename)) {
+                        println!("{:?}", e);
+                    }
+                }
+            }
             if let Some(img) = &self.last_frame_rendered {
                 img.show(ui);
             }
@@ -187,12 +501,12 @@ impl eframe::App for MyApp {
                 .show(ui, |ui| {
                     ui.radio_value(
                         &mut self.render_engine,
-                        RendererEngine::MonteCarlo,
+                        RenderEngine::MonteCarlo,
                         "MonteCarlo",
                     );
                     ui.radio_value(
                         &mut self.render_engine,
-                        RendererEngine::MonteCarloPimped,
+                        RenderEngine::MonteCarloPimped,
                         "T.U.O.T.",
                     );
                 });
@@ -213,9 +527,39 @@ impl eframe::App for MyApp {
                     ui.add(egui::Slider::new(&mut self.max_depth, 1..=70));
                     ui.label("Antialiasing Samples: ");
                     ui.add(egui::Slider::new(&mut self.samples, 1..=150));
+                    ui.label("Seed: ");
+                    ui.add(egui::DragValue::new(&mut self.seed));
+                    ui.label("Refinement: ");
+                    ui.radio_value(&mut self.progressive_mode, ProgressiveMode::Passes, "Passes");
+                    ui.radio_value(&mut self.progressive_mode, ProgressiveMode::Tiles, "Tiles");
                 });
+
+            let previous_tone_mapping = self.tone_mapping_opt.clone();
+            CollapsingHeader::new("Tone Mapping")
+                .default_open(true)
+                .show(ui, |ui| {
+                    ui.label("Exposure (stops)");
+                    ui.add(egui::Slider::new(&mut self.tone_mapping_opt.exposure, -5.0..=5.0));
+                    ui.label("White Balance (K)");
+                    ui.add(egui::Slider::new(
+                        &mut self.tone_mapping_opt.temperature,
+                        2000.0..=12000.0,
+                    ));
+                    ui.label("Operator");
+                    ui.radio_value(&mut self.tone_mapping_opt.operator, ToneMapOperator::Clamp, "Clamp");
+                    ui.radio_value(&mut self.tone_mapping_opt.operator, ToneMapOperator::Reinhard, "Reinhard");
+                    ui.radio_value(&mut self.tone_mapping_opt.operator, ToneMapOperator::Aces, "ACES Filmic");
+                    ui.label("Transfer");
+                    ui.radio_value(&mut self.tone_mapping_opt.transfer, TransferCurve::Gamma(2.2), "Gamma 2.2");
+                    ui.radio_value(&mut self.tone_mapping_opt.transfer, TransferCurve::Srgb, "sRGB");
+                    ui.radio_value(&mut self.tone_mapping_opt.transfer, TransferCurve::Linear, "Linear");
+                });
+            // the tone mapping does not need a new render, only a new conversion of the last frame
+            if previous_tone_mapping != self.tone_mapping_opt {
+                self.update_displayed_frame();
+            }
             match self.render_engine {
-                RendererEngine::MonteCarloPimped => {
+                RenderEngine::MonteCarloPimped => {
                     CollapsingHeader::new("Camera Effects")
                         .default_open(true)
                         .show(ui, |ui| {
@@ -316,100 +660,138 @@ impl eframe::App for MyApp {
         });
         egui::TopBottomPanel::bottom("timeline").show(ctx, |ui| {
             ui.heading("timeline");
-            ui.add(
+            let frame_slider = ui.add(
                 egui::Slider::new(&mut self.n_current_frame, 0..=self.tot_frames)
                     .text("current_frame"),
             );
+            if frame_slider.changed() {
+                self.apply_animation();
+            }
+            ui.horizontal(|ui| {
+                if ui.button("Add Keyframe").clicked() {
+                    self.add_keyframe();
+                }
+                if ui.button("Clear Keyframes").clicked() {
+                    self.animation = Animation::default();
+                }
+                ui.label(format!("{} keyframes", self.animation.fov.keys().len()));
+                if self.render_job.is_none() && ui.button("Render Sequence").clicked() {
+                    self.start_sequence(ctx);
+                }
+            });
             ui.label(format!("Rendered in: {} seconds", self.elapsed_time));
         });
     }
 }
 
-fn render(a: &mut MyApp) ->  Result<ImageBuffer<Rgba<u8>, Vec<u8>>, RenderError> {
-    let scene;
-    let mut world;
-    let camera;
-    let background: Color;
-    if let Some(path) = &a.picked_path {
-        let look_at = Vec3A::new(0.0, 0.0, -1.0);
-        let look_from = Vec3A::new(1.1, 0.9, 1.0);
-        let l = Texture::constant_color(Color {
-            red: 1.0,
-            green: 1.0,
-            blue: 1.0,
-        });
-        let light = Sphere {
-            position:Vec3A::new(1.5, 0.5, 0.5),
-            radius: 0.3,
-            mat: Material::diffuse_light(l),
+// Builds the world, or reuses the one of the last render when nothing changed since
+fn cached_world(
+    w: &WorldOpt,
+    settings: &RenderSettings,
+    sender: &Sender<RenderMessage>,
+) -> Result<CachedWorld, RenderError> {
+    if let Some(cached) = &w.cached {
+        return Ok(cached.clone());
+    }
+    let world = build_world(w, settings)?;
+    let _ = sender.send(RenderMessage::World(world.clone()));
+    Ok(world)
+}
+
+// Only scene files have their own camera and animation. The models opened without a
+// scene file are framed by the camera and lit by a key light when they have no lights.
+fn build_world(w: &WorldOpt, settings: &RenderSettings) -> Result<CachedWorld, RenderError> {
+    let key = &w.key;
+    let is_scene_file = |path: &Path| {
+        matches!(path.extension().and_then(|ext| ext.to_str()), Some("ron") | Some("json"))
+    };
+    let mut model_bounds = None;
+    let (world, background, animation, file_camera) = if let Some(path) =
+        key.picked_path.as_deref().map(Path::new).filter(|path| is_scene_file(path))
+    {
+        let description = SceneDescription::load(path)?;
+        let base_dir = path.parent().unwrap_or_else(|| Path::new(""));
+        let (world, background) = description.build_world(base_dir)?;
+        let animation = description.animation.unwrap_or_default();
+        (world, background, animation, Some(description.camera))
+    } else if let Some(path) = key.picked_path.as_deref().map(Path::new) {
+        let (mut world, file_camera) = if is_gltf(path) {
+            let gltf = load_gltf(path, &GltfOpt::default())?;
+            for warning in &gltf.warnings {
+                println!("{}", warning);
+            }
+            (gltf.hitables, gltf.camera)
+        } else {
+            (load_mesh_to_hitable(path)?, None)
         };
-        let camera = Camera::new(
-            look_from,
-            look_at,
-            a.camera_fov,
-            (a.frame_width as f32) / (a.frame_height as f32),
-            a.camera_aperture,
-        );
-        world = load_obj_to_hitable(&Path::new(path))?;
-        world.push(light);
-        scene = Scene::new(&mut world, camera, Color::new(0.0, 0.0, 0.0));
+        model_bounds = world.bounding_box();
+        add_key_light(&mut world);
+        (world, Color::new(0.0, 0.0, 0.0), Animation::default(), file_camera)
     } else {
-        (world, camera, background) = get_world_and_camera(
-            &a.worlds,
-            a.camera_fov,
-            a.frame_width,
-            a.frame_height,
-            a.camera_aperture,
-        );
-        scene = Scene::new(&mut world, camera, background);
+        let (world, background) = example_world(&key.worlds, key.seed);
+        (world, background, Animation::default(), None)
+    };
+    let camera = file_camera
+        .clone()
+        .unwrap_or_else(|| gui_camera(key, model_bounds.as_ref(), w, settings))
+        .to_camera(settings.width, settings.height);
+    Ok(CachedWorld {
+        key: key.clone(),
+        scene: Scene::new(world, camera, background),
+        animation,
+        file_camera,
+        model_bounds,
+    })
+}
 
-    }
-    match a.render_engine {
-        RendererEngine::MonteCarlo => {
-            return render_montecarlo(
-                a.frame_width,
-                a.frame_height,
-                a.max_depth,
-                a.samples,
-                a.n_current_frame,
-                a.tot_frames,
-                //&camera, // in the future, it could be a pool of cameras.
-                &scene,
-            );
-        }
-        RendererEngine::MonteCarloPimped => {
-            a.tint_opt.normal_color = Color::from_array(a.color_normal.to_array());
-            a.tint_opt.background_color = Color::from_array(a.background_color.to_array());
-            return render_montecarlo_pimped(
-                a.frame_width,
-                a.frame_height,
-                a.max_depth,
-                a.samples,
-                a.n_current_frame,
-                a.tot_frames,
-                &a.camera_effects,
-                &a.camera_distorter_opt,
-                &a.deflection_opt,
-                &a.tint_opt,
-                &scene,
-            );
-        }
-        
+// The camera of the world before the animation, the renders replace the one of the
+// cached scene with it
+fn camera(world: &CachedWorld, w: &WorldOpt, settings: &RenderSettings) -> CameraDescription {
+    match &world.file_camera {
+        Some(camera) => camera.clone(),
+        None => gui_camera(&world.key, world.model_bounds.as_ref(), w, settings),
     }
 }
 
-//fn get_save_frame_path() {
-// let frame = current_frame.to_string();
-// // an io::Error is called if the dir can not be created
-// use std::fs::DirBuilder;
-// DirBuilder::new()
-//     .recursive(true)
-//     .create(renderings_folder)?;
+fn gui_camera(
+    key: &WorldKey,
+    model_bounds: Option<&Aabb>,
+    w: &WorldOpt,
+    settings: &RenderSettings,
+) -> CameraDescription {
+    if let Some(aabb) = model_bounds {
+        let aspect = settings.width as f32 / settings.height as f32;
+        return frame_camera_description(aabb, w.camera_fov, aspect, w.camera_aperture);
+    }
+    example_camera(&key.worlds, w.camera_fov, w.camera_aperture)
+}
 
-// //let filename = renderings_folder.join(format!("{}_color.png", frame));
-// let filename = format!("{}/{}.png", renderings_folder, frame);
+fn render(
+    w: &WorldOpt,
+    mut renderer: ProgressiveRenderer,
+    sender: &Sender<RenderMessage>,
+    ctx: &egui::Context,
+) -> Result<Framebuffer, RenderError> {
+    let settings = *renderer.settings();
+    let world = cached_world(w, &settings, sender)?;
+    let mut scene = world.scene.clone();
+    scene.camera = world
+        .animation
+        .camera_at(settings.current_frame, &camera(&world, w, &settings))
+        .to_camera(settings.width, settings.height);
+    renderer.render(&scene, |progress, framebuffer| {
+        let _ = sender.send(RenderMessage::Progress(*progress, framebuffer.clone()));
+        ctx.request_repaint();
+    })
+}
 
-// // an ImageError is called if the image can not be saved
+// the tint colors are keyed as linear values, like in the scene files
+fn to_array(col: Color32) -> [f32; 3] {
+    let col = Color::from_array(col.to_array());
+    [col.red, col.green, col.blue]
+}
 
-// return filename;
-//}
+fn to_color32(col: [f32; 3]) -> Color32 {
+    let rgba = Color::new(col[0], col[1], col[2]).to_rgba();
+    Color32::from_rgb(rgba[0], rgba[1], rgba[2])
+}
@@ -0,0 +1,53 @@
+// Loads susanne.obj in a triangle mesh, building its BVH, and traces camera rays
+// through it.
+//
+//   cargo bench --bench bvh
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use glam::Vec3A;
+use rand::rngs::SmallRng;
+use rand::SeedableRng;
+use std::path::Path;
+use tuot::bvh::BvhTree;
+use tuot::camera::Camera;
+use tuot::hitable::Hitable;
+use tuot::utils::load_obj_to_hitable;
+
+const WIDTH: u32 = 160;
+const HEIGHT: u32 = 100;
+
+fn susanne(c: &mut Criterion) {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("app/assets/susanne.obj");
+    c.bench_function("bvh build susanne", |b| {
+        b.iter(|| load_obj_to_hitable(&path).unwrap())
+    });
+
+    let camera = Camera::new(
+        Vec3A::new(1.1, 0.9, 3.0),
+        Vec3A::new(0.0, 0.0, 0.0),
+        40.0,
+        WIDTH as f32 / HEIGHT as f32,
+        0.0,
+    );
+    let bvh = BvhTree::new(load_obj_to_hitable(&path).unwrap());
+    c.bench_function("bvh hit susanne", |b| {
+        b.iter(|| {
+            let mut rng = SmallRng::seed_from_u64(0);
+            let mut hits = 0;
+            for y in 0..HEIGHT {
+                for x in 0..WIDTH {
+                    let u = x as f32 / WIDTH as f32;
+                    let v = y as f32 / HEIGHT as f32;
+                    let ray = camera.get_ray(u, v, &mut rng);
+                    if bvh.hit(&ray, 0.001, f32::MAX).is_some() {
+                        hits += 1;
+                    }
+                }
+            }
+            black_box(hits)
+        })
+    });
+}
+
+criterion_group!(benches, susanne);
+criterion_main!(benches);
@@ -18,13 +18,28 @@ impl Aabb {
             tmin = ffmax(t0, tmin);
             tmax = ffmin(t1, tmax);
 
-            if tmax <= tmin {
+            // equal when the box is flat, like the one of an axis aligned triangle
+            if tmax < tmin {
                 return false;
             }
         }
 
         true
     }
+
+    pub fn center(&self) -> Vec3A {
+        (self.min + self.max) * 0.5
+    }
+
+    pub fn surface_area(&self) -> f32 {
+        let size = self.max - self.min;
+        2.0 * (size.x * size.y + size.y * size.z + size.z * size.x)
+    }
+
+    // radius of the sphere enclosing the box
+    pub fn radius(&self) -> f32 {
+        (self.max - self.min).length() * 0.5
+    }
 }
 
 pub fn surrounding_box(box0: &Aabb, box1: &Aabb) -> Aabb {
@@ -0,0 +1,325 @@
+use crate::color::Color;
+use crate::framebuffer::Framebuffer;
+use crate::progressive::ProgressiveRenderer;
+use crate::render_error::RenderError;
+use crate::render_settings::RenderSettings;
+use crate::scene::Scene;
+use crate::scene_description::CameraDescription;
+use crate::tone_mapping::ToneMappingOpt;
+use serde::{Deserialize, Deserializer, Serialize};
+use std::fs::DirBuilder;
+use std::path::PathBuf;
+
+// How a keyframe moves towards the next one
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub enum Interpolation {
+    // keeps the value until the next keyframe
+    Step,
+    #[default]
+    Linear,
+    // eases in and out of the keyframes
+    Smooth,
+    // smooth curve passing through all the keyframes, good for camera paths
+    CatmullRom,
+}
+
+// Values that can be keyframed
+pub trait Animatable: Copy {
+    fn lerp(a: Self, b: Self, t: f32) -> Self;
+    fn catmull_rom(p0: Self, p1: Self, p2: Self, p3: Self, t: f32) -> Self;
+}
+
+impl Animatable for f32 {
+    fn lerp(a: f32, b: f32, t: f32) -> f32 {
+        a + (b - a) * t
+    }
+
+    fn catmull_rom(p0: f32, p1: f32, p2: f32, p3: f32, t: f32) -> f32 {
+        let t2 = t * t;
+        let t3 = t2 * t;
+        0.5 * ((2.0 * p1)
+            + (-p0 + p2) * t
+            + (2.0 * p0 - 5.0 * p1 + 4.0 * p2 - p3) * t2
+            + (-p0 + 3.0 * p1 - 3.0 * p2 + p3) * t3)
+    }
+}
+
+// positions and colors
+impl Animatable for [f32; 3] {
+    fn lerp(a: [f32; 3], b: [f32; 3], t: f32) -> [f32; 3] {
+        [0, 1, 2].map(|i| f32::lerp(a[i], b[i], t))
+    }
+
+    fn catmull_rom(p0: [f32; 3], p1: [f32; 3], p2: [f32; 3], p3: [f32; 3], t: f32) -> [f32; 3] {
+        [0, 1, 2].map(|i| f32::catmull_rom(p0[i], p1[i], p2[i], p3[i], t))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Keyframe<T> {
+    pub frame: u32,
+    pub value: T,
+    #[serde(default)]
+    pub interpolation: Interpolation,
+}
+
+// The keyframes of a single value, sorted by frame. The files can list them in any
+// order, they are sorted when loaded.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(transparent)]
+pub struct Track<T> {
+    keys: Vec<Keyframe<T>>,
+}
+
+impl<'de, T: Animatable + Deserialize<'de>> Deserialize<'de> for Track<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let mut track = Track::new();
+        for key in Vec::<Keyframe<T>>::deserialize(deserializer)? {
+            track.add_key(key.frame, key.value, key.interpolation);
+        }
+        Ok(track)
+    }
+}
+
+impl<T> Default for Track<T> {
+    fn default() -> Self {
+        Track { keys: Vec::new() }
+    }
+}
+
+impl<T: Animatable> Track<T> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Adds a keyframe, replacing the one already at the same frame
+    pub fn add_key(&mut self, frame: u32, value: T, interpolation: Interpolation) {
+        let key = Keyframe {
+            frame,
+            value,
+            interpolation,
+        };
+        match self.keys.binary_search_by_key(&frame, |key| key.frame) {
+            Ok(index) => self.keys[index] = key,
+            Err(index) => self.keys.insert(index, key),
+        }
+    }
+
+    pub fn keys(&self) -> &[Keyframe<T>] {
+        &self.keys
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.keys.clear();
+    }
+
+    // The value at `frame`, None if the track has no keyframes. Before the first and
+    // after the last keyframe the value stays the same.
+    pub fn value_at(&self, frame: f32) -> Option<T> {
+        let first = self.keys.first()?;
+        let last = self.keys.last()?;
+        if frame <= first.frame as f32 {
+            return Some(first.value);
+        }
+        if frame >= last.frame as f32 {
+            return Some(last.value);
+        }
+        // the keyframe that starts the segment containing `frame`
+        let i = self.keys.partition_point(|key| key.frame as f32 <= frame) - 1;
+        let (from, to) = (&self.keys[i], &self.keys[i + 1]);
+        let t = (frame - from.frame as f32) / (to.frame - from.frame) as f32;
+        Some(match from.interpolation {
+            Interpolation::Step => from.value,
+            Interpolation::Linear => T::lerp(from.value, to.value, t),
+            Interpolation::Smooth => T::lerp(from.value, to.value, t * t * (3.0 - 2.0 * t)),
+            Interpolation::CatmullRom => {
+                let before = &self.keys[i.saturating_sub(1)];
+                let after = &self.keys[(i + 2).min(self.keys.len() - 1)];
+                T::catmull_rom(before.value, from.value, to.value, after.value, t)
+            }
+        })
+    }
+
+    // Replaces `value` with the one at `frame`, when the track has keyframes
+    pub fn apply(&self, frame: u32, value: &mut T) {
+        if let Some(animated) = self.value_at(frame as f32) {
+            *value = animated;
+        }
+    }
+}
+
+// The animated values of the camera and of the effects of the pimped renderer.
+// Empty tracks leave the values as they are.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Animation {
+    #[serde(skip_serializing_if = "Track::is_empty")]
+    pub look_from: Track<[f32; 3]>,
+    #[serde(skip_serializing_if = "Track::is_empty")]
+    pub look_at: Track<[f32; 3]>,
+    #[serde(skip_serializing_if = "Track::is_empty")]
+    pub fov: Track<f32>,
+    #[serde(skip_serializing_if = "Track::is_empty")]
+    pub aperture: Track<f32>,
+
+    #[serde(skip_serializing_if = "Track::is_empty")]
+    pub deflection_amplitude: Track<f32>,
+    #[serde(skip_serializing_if = "Track::is_empty")]
+    pub deflection_randomness: Track<f32>,
+
+    #[serde(skip_serializing_if = "Track::is_empty")]
+    pub tint_normal_color: Track<[f32; 3]>,
+    #[serde(skip_serializing_if = "Track::is_empty")]
+    pub tint_background_color: Track<[f32; 3]>,
+    #[serde(skip_serializing_if = "Track::is_empty")]
+    pub tint_mix: Track<f32>,
+    #[serde(skip_serializing_if = "Track::is_empty")]
+    pub tint_freq: Track<f32>,
+    #[serde(skip_serializing_if = "Track::is_empty")]
+    pub tint_amplitude: Track<f32>,
+
+    #[serde(skip_serializing_if = "Track::is_empty")]
+    pub distorter_noise_scale: Track<f32>,
+    #[serde(skip_serializing_if = "Track::is_empty")]
+    pub distorter_amplitude: Track<f32>,
+    #[serde(skip_serializing_if = "Track::is_empty")]
+    pub distorter_easing: Track<f32>,
+}
+
+impl Animation {
+    pub fn is_empty(&self) -> bool {
+        *self == Animation::default()
+    }
+
+    pub fn camera_at(&self, frame: u32, camera: &CameraDescription) -> CameraDescription {
+        let mut camera = camera.clone();
+        self.look_from.apply(frame, &mut camera.look_from);
+        self.look_at.apply(frame, &mut camera.look_at);
+        self.fov.apply(frame, &mut camera.fov);
+        self.aperture.apply(frame, &mut camera.aperture);
+        camera
+    }
+
+    // The settings to render `frame`, the seed of every frame is different
+    pub fn settings_at(&self, frame: u32, settings: &RenderSettings) -> RenderSettings {
+        let mut settings = *settings;
+        settings.current_frame = frame;
+
+        let deflection = &mut settings.deflection_opt;
+        self.deflection_amplitude
+            .apply(frame, &mut deflection.amplitude);
+        self.deflection_randomness
+            .apply(frame, &mut deflection.randomness);
+
+        let tint = &mut settings.tint_opt;
+        apply_color(&self.tint_normal_color, frame, &mut tint.normal_color);
+        apply_color(
+            &self.tint_background_color,
+            frame,
+            &mut tint.background_color,
+        );
+        self.tint_mix.apply(frame, &mut tint.mix);
+        self.tint_freq.apply(frame, &mut tint.freq);
+        self.tint_amplitude.apply(frame, &mut tint.amplitude);
+
+        let distorter = &mut settings.camera_distorter_opt;
+        self.distorter_noise_scale
+            .apply(frame, &mut distorter.noise_scale);
+        self.distorter_amplitude
+            .apply(frame, &mut distorter.amplitude);
+        self.distorter_easing.apply(frame, &mut distorter.easing);
+        settings
+    }
+}
+
+fn apply_color(track: &Track<[f32; 3]>, frame: u32, color: &mut Color) {
+    if let Some([red, green, blue]) = track.value_at(frame as f32) {
+        *color = Color::new(red, green, blue);
+    }
+}
+
+pub struct SequenceOpt {
+    pub first_frame: u32,
+    pub last_frame: u32,
+    pub folder: PathBuf,
+    // format of the images, exr and hdr keep the linear values
+    pub extension: String,
+    pub tone_mapping: ToneMappingOpt,
+}
+
+impl Default for SequenceOpt {
+    fn default() -> SequenceOpt {
+        SequenceOpt {
+            first_frame: 0,
+            last_frame: 0,
+            folder: PathBuf::from("renderings"),
+            extension: String::from("png"),
+            tone_mapping: ToneMappingOpt::default(),
+        }
+    }
+}
+
+// Renders the frames from first_frame to last_frame, included, into numbered images
+// in the folder of the sequence. The scene is built once, its camera is replaced by
+// `camera` animated, and the settings are animated too. `on_frame` is called after
+// every frame is saved, returning false stops the sequence.
+pub fn render_sequence<P>(
+    sequence: &SequenceOpt,
+    settings: &RenderSettings,
+    animation: &Animation,
+    scene: &Scene,
+    camera: &CameraDescription,
+    mut on_frame: P,
+) -> Result<(), RenderError>
+where
+    P: FnMut(u32, &Framebuffer) -> bool,
+{
+    DirBuilder::new().recursive(true).create(&sequence.folder)?;
+    let mut scene = scene.clone();
+    for frame in sequence.first_frame..=sequence.last_frame {
+        let frame_settings = animation.settings_at(frame, settings);
+        scene.camera = animation
+            .camera_at(frame, camera)
+            .to_camera(settings.width, settings.height);
+        let framebuffer = ProgressiveRenderer::new(frame_settings).render(&scene, |_, _| {})?;
+
+        let filename = sequence
+            .folder
+            .join(format!("{:04}.{}", frame, sequence.extension));
+        framebuffer.save_tone_mapped(&filename, &sequence.tone_mapping)?;
+        if !on_frame(frame, &framebuffer) {
+            break;
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn test_track_interpolation() {
+    let mut track = Track::new();
+    track.add_key(10, 1.0, Interpolation::Linear);
+    track.add_key(0, 0.0, Interpolation::Step);
+    track.add_key(20, 3.0, Interpolation::Linear);
+    assert_eq!(track.value_at(5.0), Some(0.0));
+    assert_eq!(track.value_at(15.0), Some(2.0));
+    assert_eq!(track.value_at(30.0), Some(3.0));
+}
+
+#[test]
+fn test_track_loads_unsorted_keys() {
+    let track: Track<f32> = ron::from_str(
+        "[(frame: 0, value: 0.0), (frame: 30, value: 1.0), (frame: 10, value: 2.0), \
+         (frame: 40, value: 3.0), (frame: 10, value: 4.0)]",
+    )
+    .unwrap();
+    let frames: Vec<u32> = track.keys().iter().map(|key| key.frame).collect();
+    assert_eq!(frames, [0, 10, 30, 40]);
+    // the last of the keys at the same frame wins
+    assert_eq!(track.value_at(10.0), Some(4.0));
+    assert_eq!(track.value_at(5.0), Some(2.0));
+    assert_eq!(track.value_at(20.0), Some(2.5));
+}
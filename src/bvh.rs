@@ -1,240 +1,459 @@
-extern crate rand;
-use rand::thread_rng;
-use rand::Rng;
-use std::cmp::Ordering;
 use std::fmt;
+use std::ops::Range;
 
 use crate::aabb::{surrounding_box, Aabb};
 use crate::hitable::HitRecord;
 use crate::hitable::{Hitable, HitableStore};
 use crate::ray::Ray;
+use glam::Vec3A;
+
+// number of buckets used to estimate the cost of the splits along an axis
+const SAH_BUCKETS: usize = 12;
+// cost of visiting a node, relative to the cost of intersecting a hitable
+const TRAVERSAL_COST: f32 = 0.5;
+const MAX_LEAF_SIZE: usize = 4;
+const STACK_SIZE: usize = 64;
+// below this depth the nodes are split in the middle, so the tree fits in the stack
+const MAX_SAH_DEPTH: usize = 32;
+
+// Bounding volume hierarchy of hitables, built with the surface area heuristic.
+pub struct BvhTree {
+    nodes: BvhNodes,
+    // the hitables in the order of the leaves, every leaf owns a contiguous range.
+    // The ones without a bounding box come last and are tested by every ray.
+    hitables: Vec<Box<dyn Hitable + Send + Sync>>,
+    n_bounded: usize,
+}
 
-//#[derive(Debug)]
-pub struct BvhTree<'a> {
-    nodes: Vec<BvhNode<'a>>,
-    root: NodeId,
+// The nodes of a hierarchy over primitives known only by their bounding boxes, shared by
+// BvhTree and TriangleMesh. The nodes are stored depth first in a flat vector: the first
+// child of a node is the node right after it, so only the index of the second child is
+// stored.
+pub(crate) struct BvhNodes {
+    nodes: Vec<BvhNode>,
 }
 
-//#[derive(Debug)]
-struct BvhNode<'a> {
-    left: Option<NodeId>,
-    right: Option<NodeId>,
-    aabb: Option<Aabb>,
-    hitable: Option<&'a Box<dyn Hitable + Send + Sync>>,
+#[derive(Clone, Copy, Debug)]
+struct BvhNode {
+    aabb: Aabb,
+    // first primitive for the leaves, second child for the interior nodes
+    offset: u32,
+    // number of primitives, 0 for the interior nodes
+    count: u16,
+    // axis of the split, used to visit the nearest child first
+    axis: u8,
+}
+
+impl BvhNode {
+    fn is_leaf(&self) -> bool {
+        self.count > 0
+    }
 }
 
-#[derive(Copy, Clone, Debug)]
-pub struct NodeId {
+// A primitive waiting to be placed in the tree
+#[derive(Clone, Copy)]
+struct BuildItem {
     index: usize,
+    aabb: Aabb,
+    centroid: Vec3A,
 }
 
-impl<'a> BvhTree<'a> {
-    fn hit(&self, id: NodeId, r: &Ray, tmin: f32, tmax: f32) -> Option<HitRecord> {
-        let node = &self.nodes[id.index];
+#[derive(Clone, Copy)]
+struct Bucket {
+    count: usize,
+    aabb: Option<Aabb>,
+}
 
-        if node.aabb.is_none() || node.aabb.is_some() && node.aabb.unwrap().hit(r, tmin, tmax) {
-            match node.hitable {
-                Some(ref hitable) => return hitable.hit(r, tmin, tmax),
-                None => {}
+impl BvhTree {
+    pub fn new(mut store: HitableStore) -> BvhTree {
+        let mut bounded = Vec::with_capacity(store.len());
+        let mut unbounded = Vec::new();
+        for (index, hitable) in store.iter().enumerate() {
+            match hitable.bounding_box() {
+                Some(aabb) => bounded.push((index, aabb)),
+                None => unbounded.push(index),
             }
+        }
+        let n_bounded = bounded.len();
+        let (nodes, mut order) = BvhNodes::new(bounded);
+        order.extend(unbounded);
+
+        // moves the hitables in the order of the leaves
+        let mut rank = vec![0; store.len()];
+        for (position, &index) in order.iter().enumerate() {
+            rank[index] = position;
+        }
+        let mut ranked: Vec<_> = rank.into_iter().zip(store.drain(..)).collect();
+        ranked.sort_unstable_by_key(|(position, _)| *position);
+        BvhTree {
+            nodes,
+            hitables: ranked.into_iter().map(|(_, hitable)| hitable).collect(),
+            n_bounded,
+        }
+    }
 
-            let mut hit_left: Option<HitRecord> = None;
-            let mut hit_right: Option<HitRecord> = None;
-
-            if let Some(ref left_index) = node.left {
-                hit_left = self.hit(*left_index, r, tmin, tmax);
-            }
+    // All the hitables in the tree, the index of a hitable is its position here
+    pub fn hitables(&self) -> impl Iterator<Item = &(dyn Hitable + Send + Sync)> {
+        self.hitables.iter().map(|hitable| hitable.as_ref())
+    }
 
-            if let Some(ref right_index) = node.right {
-                hit_right = self.hit(*right_index, r, tmin, tmax);
-            }
+    pub fn hitable(&self, index: usize) -> &(dyn Hitable + Send + Sync) {
+        self.hitables[index].as_ref()
+    }
 
-            match hit_left {
-                Some(left) => match hit_right {
-                    Some(right) => {
-                        if left.t < right.t {
-                            return hit_left;
-                        } else {
-                            return hit_right;
-                        }
-                    }
-                    None => return hit_left,
-                },
-                None => {}
+    // The closest hit and the index of the hitable that was hit
+    pub fn hit_with_index(&self, r: &Ray, tmin: f32, tmax: f32) -> Option<(usize, HitRecord<'_>)> {
+        let mut hit = self.nodes.hit(r, tmin, tmax, |leaf, mut tmax| {
+            let mut hit: Option<(usize, HitRecord)> = None;
+            for index in leaf {
+                if let Some(candidate) = self.hitables[index].hit(r, tmin, tmax) {
+                    tmax = candidate.t;
+                    hit = Some((index, candidate));
+                }
             }
-
-            match hit_right {
-                Some(_right) => return hit_right,
-                None => {}
+            hit.map(|hit| (hit.1.t, hit))
+        });
+        for index in self.n_bounded..self.hitables.len() {
+            let tmax = hit.map_or(tmax, |(_, hit)| hit.t);
+            if let Some(candidate) = self.hitables[index].hit(r, tmin, tmax) {
+                hit = Some((index, candidate));
             }
         }
-
-        None
+        hit
     }
 }
 
-impl<'a> Hitable for BvhTree<'a> {
-    fn bounding_box(&self) -> Option<Aabb> {
-        self.nodes[self.root.index].aabb
+impl BvhNodes {
+    // The tree over the primitives with the given indices and bounding boxes, and the
+    // indices in the order of the leaves
+    pub(crate) fn new(primitives: Vec<(usize, Aabb)>) -> (BvhNodes, Vec<usize>) {
+        let mut items: Vec<BuildItem> = primitives
+            .into_iter()
+            .map(|(index, aabb)| BuildItem {
+                index,
+                aabb,
+                centroid: aabb.center(),
+            })
+            .collect();
+        let mut tree = BvhNodes {
+            nodes: Vec::with_capacity(2 * items.len()),
+        };
+        let mut order = Vec::with_capacity(items.len());
+        if !items.is_empty() {
+            tree.build(&mut order, &mut items, 0);
+        }
+        (tree, order)
     }
 
-    fn hit(&self, r: &Ray, tmin: f32, tmax: f32) -> Option<HitRecord> {
-        self.hit(self.root, r, tmin, tmax)
+    pub(crate) fn aabb(&self) -> Option<Aabb> {
+        self.nodes.first().map(|node| node.aabb)
     }
-}
 
-impl<'a> BvhTree<'a> {
-    pub fn new(l: &'a mut HitableStore) -> BvhTree<'a> {
-        let mut tree = BvhTree {
-            nodes: Vec::new(),
-            root: NodeId { index: 0 },
-        };
-        tree.root = tree.build(l);
-
-        tree
+    pub(crate) fn len(&self) -> usize {
+        self.nodes.len()
     }
 
-    fn build(&mut self, l: &'a mut [Box<dyn Hitable + Send + Sync>]) -> NodeId {
-        let axis = thread_rng().gen_range(0..3);
-
-        match axis {
-            0 => l.sort_by(|a, b| box_x_compare(a, b)),
-            1 => l.sort_by(|a, b| box_y_compare(a, b)),
-            2 => l.sort_by(|a, b| box_z_compare(a, b)),
-            _ => panic!("Unexpected axis"),
-        }
-
-        let left: NodeId;
-        let right: NodeId;
+    // Adds the node containing `items` and its children, returns the index of the node
+    fn build(&mut self, order: &mut Vec<usize>, items: &mut [BuildItem], depth: usize) -> usize {
+        let aabb = items[1..].iter().fold(items[0].aabb, |aabb, item| {
+            surrounding_box(&aabb, &item.aabb)
+        });
+        let node_index = self.nodes.len();
 
-        if l.len() == 1 {
-            return self.new_leaf(&l[0]);
-        } else if l.len() == 2 {
-            left = self.new_leaf(&l[0]);
-            right = self.new_leaf(&l[1]);
+        let split = if items.len() <= 1 {
+            None
+        } else if depth >= MAX_SAH_DEPTH {
+            Some(median_split(items))
         } else {
-            let half_len = l.len() / 2;
-            let (left_hitables, right_hitables) = l.split_at_mut(half_len);
-
-            left = self.build(left_hitables);
-            right = self.build(right_hitables);
-        }
-
-        if let Some(left_box) = self.nodes[left.index].aabb {
-            if let Some(right_box) = self.nodes[right.index].aabb {
-                return self.new_node(
-                    surrounding_box(&left_box, &right_box),
-                    Some(left),
-                    Some(right),
-                );
+            find_split(items, &aabb)
+        };
+        let (axis, mid) = match split {
+            Some(split) => split,
+            None => {
+                self.nodes.push(BvhNode {
+                    aabb,
+                    offset: order.len() as u32,
+                    count: items.len() as u16,
+                    axis: 0,
+                });
+                order.extend(items.iter().map(|item| item.index));
+                return node_index;
             }
-        }
-
-        panic!("No bounding box in BvhNode::build");
-    }
-
-    fn new_leaf(&mut self, hitable: &'a Box<dyn Hitable + Send + Sync>) -> NodeId {
-        let next_index = self.nodes.len();
+        };
 
         self.nodes.push(BvhNode {
-            left: None,
-            right: None,
-            aabb: hitable.bounding_box(),
-            hitable: Some(hitable),
+            aabb,
+            offset: 0,
+            count: 0,
+            axis: axis as u8,
         });
-
-        return NodeId { index: next_index };
+        let (left, right) = items.split_at_mut(mid);
+        self.build(order, left, depth + 1);
+        let second = self.build(order, right, depth + 1);
+        self.nodes[node_index].offset = second as u32;
+        node_index
     }
 
-    fn new_node(&mut self, aabb: Aabb, left: Option<NodeId>, right: Option<NodeId>) -> NodeId {
-        let next_index = self.nodes.len();
-
-        self.nodes.push(BvhNode {
-            left,
-            right,
-            aabb: Some(aabb),
-            hitable: None,
-        });
-
-        return NodeId { index: next_index };
+    // Visits the leaves crossed by the ray, the nearest ones first. `hit_leaf` gets the
+    // range of the primitives of a leaf and the current tmax, and returns the distance
+    // of the closest hit it found.
+    pub(crate) fn hit<T, F>(&self, r: &Ray, tmin: f32, mut tmax: f32, mut hit_leaf: F) -> Option<T>
+    where
+        F: FnMut(Range<usize>, f32) -> Option<(f32, T)>,
+    {
+        if self.nodes.is_empty() {
+            return None;
+        }
+        let dir_is_negative = [
+            r.direction.x < 0.0,
+            r.direction.y < 0.0,
+            r.direction.z < 0.0,
+        ];
+        let mut hit = None;
+        let mut stack = [0usize; STACK_SIZE];
+        let mut stack_len = 0;
+        let mut current = 0;
+        loop {
+            let node = &self.nodes[current];
+            if node.aabb.hit(r, tmin, tmax) {
+                if node.is_leaf() {
+                    let start = node.offset as usize;
+                    if let Some((t, candidate)) = hit_leaf(start..start + node.count as usize, tmax)
+                    {
+                        tmax = t;
+                        hit = Some(candidate);
+                    }
+                } else {
+                    // visit the child on the side the ray comes from first, the other later
+                    let (near, far) = if dir_is_negative[node.axis as usize] {
+                        (node.offset as usize, current + 1)
+                    } else {
+                        (current + 1, node.offset as usize)
+                    };
+                    stack[stack_len] = far;
+                    stack_len += 1;
+                    current = near;
+                    continue;
+                }
+            }
+            if stack_len == 0 {
+                break;
+            }
+            stack_len -= 1;
+            current = stack[stack_len];
+        }
+        hit
     }
+}
 
-    fn number_hittables(&self, id: NodeId) -> usize {
-        let node = &self.nodes[id.index];
-        let local_hitable = if node.hitable.is_some() { 1 } else { 0 };
-        let count_left = if let Some(left_index) = node.left {
-            self.number_hittables(left_index)
-        } else {
-            0
-        };
-        let count_right = if let Some(right_index) = node.right {
-            self.number_hittables(right_index)
-        } else {
-            0
-        };
+impl Hitable for BvhTree {
+    fn bounding_box(&self) -> Option<Aabb> {
+        if self.n_bounded < self.hitables.len() {
+            return None;
+        }
+        self.nodes.aabb()
+    }
 
-        local_hitable + count_left + count_right
+    fn hit(&self, r: &Ray, tmin: f32, tmax: f32) -> Option<HitRecord> {
+        self.hit_with_index(r, tmin, tmax).map(|(_, hit)| hit)
     }
 }
 
-impl<'a> fmt::Display for BvhTree<'a> {
+impl fmt::Display for BvhTree {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(
             f,
             "BVH with {:?} hitables and {:?} nodes",
-            self.number_hittables(self.root),
+            self.hitables.len(),
             self.nodes.len()
         )
     }
 }
 
-fn box_x_compare(
-    a: &Box<dyn Hitable + Send + Sync>,
-    b: &Box<dyn Hitable + Send + Sync>,
-) -> Ordering {
-    if let Some(box_left) = a.bounding_box() {
-        if let Some(box_right) = b.bounding_box() {
-            if let Some(cmp) = box_left.min.x.partial_cmp(&box_right.min.x) {
-                return cmp;
-            } else {
-                panic!("Can't compare");
-            }
+// The axis and the position of the cheapest split, sorting `items` along that axis.
+// None when a leaf is cheaper than any split.
+fn find_split(items: &mut [BuildItem], aabb: &Aabb) -> Option<(usize, usize)> {
+    let (centroid_min, centroid_max) = items.iter().fold(
+        (Vec3A::splat(f32::MAX), Vec3A::splat(f32::MIN)),
+        |(min, max), item| (min.min(item.centroid), max.max(item.centroid)),
+    );
+    let extent = centroid_max - centroid_min;
+    let axis = widest_axis(extent);
+
+    // all the centroids in the same point, there is no way to separate them
+    if extent[axis] <= 0.0 {
+        if items.len() <= MAX_LEAF_SIZE {
+            return None;
         }
+        return Some(median_split(items));
     }
 
-    panic!("No bounding box in BvhNode::new");
+    let bucket_of = |item: &BuildItem| {
+        let b = SAH_BUCKETS as f32 * (item.centroid[axis] - centroid_min[axis]) / extent[axis];
+        (b as usize).min(SAH_BUCKETS - 1)
+    };
+    let mut buckets = [Bucket {
+        count: 0,
+        aabb: None,
+    }; SAH_BUCKETS];
+    for item in items.iter() {
+        let bucket = &mut buckets[bucket_of(item)];
+        bucket.count += 1;
+        bucket.aabb = Some(match bucket.aabb {
+            Some(aabb) => surrounding_box(&aabb, &item.aabb),
+            None => item.aabb,
+        });
+    }
+
+    // cost of splitting after every bucket but the last one, sweeping from both sides
+    let mut costs = [0.0; SAH_BUCKETS - 1];
+    let mut left = Bucket {
+        count: 0,
+        aabb: None,
+    };
+    for (i, bucket) in buckets[..SAH_BUCKETS - 1].iter().enumerate() {
+        left = merge(&left, bucket);
+        costs[i] = left.count as f32 * area(&left.aabb);
+    }
+    let mut right = Bucket {
+        count: 0,
+        aabb: None,
+    };
+    for (i, bucket) in buckets[1..].iter().enumerate().rev() {
+        right = merge(&right, bucket);
+        costs[i] += right.count as f32 * area(&right.aabb);
+    }
+
+    let (best, best_cost) =
+        costs.iter().enumerate().fold(
+            (0, f32::MAX),
+            |best, (i, &cost)| {
+                if cost < best.1 {
+                    (i, cost)
+                } else {
+                    best
+                }
+            },
+        );
+    let split_cost = TRAVERSAL_COST + best_cost / aabb.surface_area();
+    let leaf_cost = items.len() as f32;
+    if items.len() <= MAX_LEAF_SIZE && leaf_cost <= split_cost {
+        return None;
+    }
+
+    items.sort_unstable_by(|a, b| a.centroid[axis].total_cmp(&b.centroid[axis]));
+    let mid = items.partition_point(|item| bucket_of(item) <= best);
+    Some((axis, mid.clamp(1, items.len() - 1)))
 }
 
-fn box_y_compare(
-    a: &Box<dyn Hitable + Send + Sync>,
-    b: &Box<dyn Hitable + Send + Sync>,
-) -> Ordering {
-    if let Some(box_left) = a.bounding_box() {
-        if let Some(box_right) = b.bounding_box() {
-            if let Some(cmp) = box_left.min.y.partial_cmp(&box_right.min.y) {
-                return cmp;
-            } else {
-                panic!("Can't compare");
-            }
-        }
+// Splits the items in two halves along the axis where their centroids are most spread
+fn median_split(items: &mut [BuildItem]) -> (usize, usize) {
+    let (min, max) = items.iter().fold(
+        (Vec3A::splat(f32::MAX), Vec3A::splat(f32::MIN)),
+        |(min, max), item| (min.min(item.centroid), max.max(item.centroid)),
+    );
+    let axis = widest_axis(max - min);
+    let mid = items.len() / 2;
+    items.select_nth_unstable_by(mid, |a, b| a.centroid[axis].total_cmp(&b.centroid[axis]));
+    (axis, mid)
+}
+
+fn widest_axis(extent: Vec3A) -> usize {
+    if extent.x >= extent.y && extent.x >= extent.z {
+        0
+    } else if extent.y >= extent.z {
+        1
+    } else {
+        2
     }
+}
 
-    panic!("No bounding box in BvhNode::new");
+fn merge(a: &Bucket, b: &Bucket) -> Bucket {
+    Bucket {
+        count: a.count + b.count,
+        aabb: match (a.aabb, b.aabb) {
+            (Some(a), Some(b)) => Some(surrounding_box(&a, &b)),
+            (a, b) => a.or(b),
+        },
+    }
 }
 
-fn box_z_compare(
-    a: &Box<dyn Hitable + Send + Sync>,
-    b: &Box<dyn Hitable + Send + Sync>,
-) -> Ordering {
-    if let Some(box_left) = a.bounding_box() {
-        if let Some(box_right) = b.bounding_box() {
-            if let Some(cmp) = box_left.min.z.partial_cmp(&box_right.min.z) {
-                return cmp;
-            } else {
-                panic!("Can't compare");
-            }
+fn area(aabb: &Option<Aabb>) -> f32 {
+    aabb.map_or(0.0, |aabb| aabb.surface_area())
+}
+
+#[test]
+fn test_traversal_finds_the_closest_hit() {
+    use crate::sphere::Sphere;
+    use crate::test_utils::white_material;
+    use rand::rngs::SmallRng;
+    use rand::{Rng, SeedableRng};
+
+    // a sphere the tree can not bound, like an infinite plane
+    struct Unbounded(Sphere);
+    impl Hitable for Unbounded {
+        fn hit(&self, r: &Ray, tmin: f32, tmax: f32) -> Option<HitRecord> {
+            self.0.hit(r, tmin, tmax)
+        }
+        fn bounding_box(&self) -> Option<Aabb> {
+            None
         }
     }
 
-    panic!("No bounding box in BvhNode::new");
+    let mut rng = SmallRng::seed_from_u64(11);
+    let mut random_point = |size: f32| {
+        Vec3A::new(
+            rng.gen_range(-size..size),
+            rng.gen_range(-size..size),
+            rng.gen_range(-size..size),
+        )
+    };
+    let sphere = |position: Vec3A, radius: f32| Sphere {
+        position,
+        radius,
+        mat: white_material(),
+    };
+    let mut store = HitableStore::new();
+    for _ in 0..200 {
+        store.push(sphere(random_point(10.0), 0.5));
+    }
+    // spheres with the same centroid can not be told apart by the SAH. Four of them fill
+    // a leaf, the nine ones are split in the middle
+    let (four, nine) = (random_point(10.0), random_point(10.0));
+    for i in 0..4 {
+        store.push(sphere(four, 0.3 + 0.1 * i as f32));
+    }
+    for i in 0..9 {
+        store.push(sphere(nine, 0.1 + 0.1 * i as f32));
+    }
+    for _ in 0..3 {
+        store.push(Unbounded(sphere(random_point(10.0), 1.0)));
+    }
+    let tree = BvhTree::new(store);
+
+    let leaves = tree.nodes.nodes.iter().filter(|node| node.is_leaf());
+    let largest_leaf = leaves.map(|leaf| leaf.count as usize).max();
+    assert_eq!(largest_leaf, Some(MAX_LEAF_SIZE));
+    assert!(tree.bounding_box().is_none());
+
+    let mut hits = 0;
+    for _ in 0..2000 {
+        // from around the spheres towards their middle
+        let origin = random_point(20.0);
+        let ray = Ray::new(origin, random_point(10.0) - origin);
+        let mut expected: Option<(usize, f32)> = None;
+        for (index, hitable) in tree.hitables().enumerate() {
+            let tmax = expected.map_or(f32::MAX, |(_, t)| t);
+            if let Some(hit) = hitable.hit(&ray, 0.001, tmax) {
+                expected = Some((index, hit.t));
+            }
+        }
+        let found = tree
+            .hit_with_index(&ray, 0.001, f32::MAX)
+            .map(|(index, hit)| (index, hit.t));
+        assert_eq!(found, expected, "{} {}", ray.origin, ray.direction);
+        hits += found.is_some() as usize;
+    }
+    // most of the rays hit something, and some miss everything
+    assert!(hits > 200 && hits < 1800, "{hits}");
 }
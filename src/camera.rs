@@ -1,8 +1,13 @@
 use crate::ray::Ray;
 use crate::utils::random_in_unit_sphere;
-use glam::Vec3A;
+use glam::{Quat, Vec2, Vec3A};
+use rand::rngs::SmallRng;
+
+// width and height in millimeters of a full frame sensor
+pub const FULL_FRAME_SENSOR: Vec2 = Vec2::new(36.0, 24.0);
 
 //#[derive(Debug)]
+#[derive(Clone, Copy)]
 pub struct Camera {
     pub origin: Vec3A,
     pub lower_left_corner: Vec3A,
@@ -12,42 +17,225 @@ pub struct Camera {
     pub u: Vec3A,
     pub v: Vec3A,
     pub w: Vec3A,
+    // the plane in focus, it faces the camera unless the lens is tilted
+    pub focus_point: Vec3A,
+    pub focus_normal: Vec3A,
+}
+
+// How wide the camera sees
+#[derive(Debug, Clone, Copy)]
+enum Lens {
+    // the vertical field of view in degrees
+    Fov(f32),
+    // in millimeters, with the size of the sensor
+    FocalLength(f32),
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Focus {
+    LookAt,
+    Distance(f32),
+    Point(Vec3A),
+}
+
+// A camera built step by step. By default it has a square frame, a vertical field of view
+// of 90 degrees and no depth of field.
+#[derive(Debug, Clone, Copy)]
+pub struct CameraBuilder {
+    look_from: Vec3A,
+    look_at: Vec3A,
+    up: Vec3A,
+    lens: Lens,
+    sensor: Vec2,
+    aspect: f32,
+    aperture: f32,
+    focus: Focus,
+    shift: Vec2,
+    tilt: Vec2,
 }
 
 impl Camera {
     pub fn new(lookfrom: Vec3A, lookat: Vec3A, vfov: f32, aspect: f32, aperture: f32) -> Camera {
-        let vup = Vec3A::new(0.0, 1.0, 0.0);
-        let theta = vfov * std::f32::consts::PI / 180.0;
-        let half_height = f32::tan(theta / 2.0);
-        let half_width = aspect * half_height;
-        let focus_dist = lookfrom.distance(lookat);
-
-        let w = (lookfrom - lookat).normalize();
-        let u = (vup.cross(w)).normalize();
+        Camera::builder(lookfrom, lookat)
+            .with_fov(vfov)
+            .with_aspect(aspect)
+            .with_aperture(aperture)
+            .build()
+    }
+
+    pub fn builder(look_from: Vec3A, look_at: Vec3A) -> CameraBuilder {
+        CameraBuilder {
+            look_from,
+            look_at,
+            up: Vec3A::Y,
+            lens: Lens::Fov(90.0),
+            sensor: FULL_FRAME_SENSOR,
+            aspect: 1.0,
+            aperture: 0.0,
+            focus: Focus::LookAt,
+            shift: Vec2::ZERO,
+            tilt: Vec2::ZERO,
+        }
+    }
+
+    pub fn get_ray(&self, s: f32, t: f32, rng: &mut SmallRng) -> Ray {
+        let target = self.lower_left_corner + s * self.horizontal + t * self.vertical;
+        self.ray_through(target, rng)
+    }
+
+    // A ray from a random point of the lens towards `target`, a point of the image plane.
+    // The rays through the center of the lens are not bent, the other ones meet them on
+    // the focus plane.
+    pub fn ray_through(&self, target: Vec3A, rng: &mut SmallRng) -> Ray {
+        let rd = self.lens_radius * random_in_unit_sphere(rng);
+        let offset = rd.x * self.u + rd.y * self.v;
+
+        let direction = target - self.origin;
+        let along_normal = direction.dot(self.focus_normal);
+        let k = (self.focus_point - self.origin).dot(self.focus_normal) / along_normal;
+        // a tilted focus plane can be parallel to the ray, or behind the camera
+        let focus = if along_normal.abs() > f32::EPSILON && k > 0.0 {
+            self.origin + direction * k
+        } else {
+            target
+        };
+        Ray::new(self.origin + offset, focus - self.origin - offset)
+    }
+}
+
+impl CameraBuilder {
+    // The up direction of the frame. When it is parallel to the direction the camera looks
+    // at, like looking straight down with the default one, any perpendicular one is used.
+    pub fn with_up(mut self, up: Vec3A) -> Self {
+        self.up = up;
+        self
+    }
+
+    // The vertical field of view in degrees, it ignores the sensor
+    pub fn with_fov(mut self, fov: f32) -> Self {
+        self.lens = Lens::Fov(fov);
+        self
+    }
+
+    // The focal length in millimeters, the field of view is the one of the sensor
+    pub fn with_focal_length(mut self, focal_length: f32) -> Self {
+        self.lens = Lens::FocalLength(focal_length);
+        self
+    }
+
+    // Width and height of the sensor in millimeters, 36 x 24 by default. A frame with a
+    // different aspect ratio is cropped from it.
+    pub fn with_sensor(mut self, width: f32, height: f32) -> Self {
+        self.sensor = Vec2::new(width, height);
+        self
+    }
+
+    // The width of the frame divided by its height
+    pub fn with_aspect(mut self, aspect: f32) -> Self {
+        self.aspect = aspect;
+        self
+    }
+
+    // The diameter of the lens, 0 keeps everything in focus
+    pub fn with_aperture(mut self, aperture: f32) -> Self {
+        self.aperture = aperture;
+        self
+    }
+
+    // The distance of the focus plane, along the direction the camera looks at. By
+    // default it passes through the look at point.
+    pub fn with_focus_distance(mut self, distance: f32) -> Self {
+        self.focus = Focus::Distance(distance);
+        self
+    }
+
+    // A point on the focus plane
+    pub fn with_focus_point(mut self, point: Vec3A) -> Self {
+        self.focus = Focus::Point(point);
+        self
+    }
+
+    // Moves the lens parallel to the sensor, in millimeters of the sensor, to frame
+    // something off center without converging lines
+    pub fn with_shift(mut self, x: f32, y: f32) -> Self {
+        self.shift = Vec2::new(x, y);
+        self
+    }
+
+    // Tilts the focus plane, in degrees around the horizontal axis of the frame and
+    // around the vertical one
+    pub fn with_tilt(mut self, x: f32, y: f32) -> Self {
+        self.tilt = Vec2::new(x, y);
+        self
+    }
+
+    pub fn build(&self) -> Camera {
+        // the camera can not look nowhere, nor towards the up direction
+        let w = (self.look_from - self.look_at)
+            .try_normalize()
+            .unwrap_or(Vec3A::Z);
+        let u = self
+            .up
+            .cross(w)
+            .try_normalize()
+            .unwrap_or_else(|| w.any_orthonormal_vector());
         let v = w.cross(u);
 
+        let (half_height, focal_length) = match self.lens {
+            Lens::Fov(fov) => {
+                let half_height = (fov.to_radians() * 0.5).tan();
+                (half_height, self.sensor.y * 0.5 / half_height)
+            }
+            Lens::FocalLength(focal_length) => {
+                let height = self.sensor.y.min(self.sensor.x / self.aspect);
+                (height * 0.5 / focal_length, focal_length)
+            }
+        };
+        let half_width = self.aspect * half_height;
+        let focus_dist = match self.focus {
+            Focus::LookAt => self.look_from.distance(self.look_at),
+            Focus::Distance(distance) => distance,
+            Focus::Point(point) => (self.look_from - point).dot(w),
+        }
+        .max(f32::EPSILON);
+
+        // the image plane is at the focus distance, there millimeters on the sensor are
+        // focus_dist / focal_length long
+        let shift = (self.shift.x * u + self.shift.y * v) * focus_dist / focal_length;
+        let tilt = Quat::from_axis_angle(u.into(), self.tilt.x.to_radians())
+            * Quat::from_axis_angle(v.into(), self.tilt.y.to_radians());
         Camera {
-            lower_left_corner: lookfrom
+            lower_left_corner: self.look_from + shift
                 - half_width * focus_dist * u
                 - half_height * focus_dist * v
                 - focus_dist * w,
             horizontal: 2.0 * half_width * focus_dist * u,
             vertical: 2.0 * half_height * focus_dist * v,
-            origin: lookfrom,
-            lens_radius: aperture / 2.0,
+            origin: self.look_from,
+            lens_radius: self.aperture / 2.0,
             u,
             v,
             w,
+            focus_point: self.look_from - focus_dist * w,
+            focus_normal: tilt * w,
         }
     }
+}
 
-    pub fn get_ray(&self, s: f32, t: f32) -> Ray {
-        let rd = self.lens_radius * random_in_unit_sphere();
-        let offset = rd.x * self.u + rd.y * self.v;
+#[test]
+fn test_camera_looking_down_and_focal_length() {
+    use rand::SeedableRng;
 
-        Ray::new(
-            self.origin + offset,
-            self.lower_left_corner + s * self.horizontal + t * self.vertical - self.origin - offset,
-        )
-    }
+    let mut rng = SmallRng::seed_from_u64(0);
+    let down = Camera::new(Vec3A::new(0.0, 5.0, 0.0), Vec3A::ZERO, 40.0, 1.5, 0.1);
+    assert!(down.get_ray(0.2, 0.9, &mut rng).direction.is_finite());
+
+    // 24 mm of sensor height at 50 mm
+    let camera = Camera::builder(Vec3A::ZERO, -Vec3A::Z)
+        .with_focal_length(50.0)
+        .with_aspect(1.5)
+        .build();
+    let top = camera.get_ray(0.5, 1.0, &mut rng).direction;
+    let fov = 2.0 * top.angle_between(-Vec3A::Z);
+    assert!((fov - 2.0 * (12.0f32 / 50.0).atan()).abs() < 1e-5);
 }
@@ -1,8 +1,8 @@
 use crate::camera::Camera;
 use crate::ray::Ray;
 use crate::texture::Texture;
-use crate::utils::random_in_unit_sphere;
 use glam::Vec3A;
+use rand::rngs::SmallRng;
 
 pub fn get_distorted_ray(
     cam: &Camera,
@@ -11,21 +11,18 @@ pub fn get_distorted_ray(
     noise: &Texture,
     amp: f32,
     easing: f32,
+    rng: &mut SmallRng,
 ) -> Ray {
-    let rd = cam.lens_radius * random_in_unit_sphere();
     let noised = noise.value(s, t, Vec3A::new(s, t, 0.0));
 
-    let offset = rd.x * cam.u + rd.y * cam.v;
-
     // use t because the distortsion grows on the y axis
     let smooth_t = exponential_easing(t, easing);
     let ns = s + noised.red * (amp * smooth_t);
     let nt = t + noised.green * (amp * smooth_t);
 
-    Ray::new(
-        cam.origin + offset,
-        cam.lower_left_corner + ns * cam.horizontal + nt * cam.vertical - cam.origin - offset,
-        //Vec3A::new(ns, nt, 0.0),
+    cam.ray_through(
+        cam.lower_left_corner + ns * cam.horizontal + nt * cam.vertical,
+        rng,
     )
 }
 
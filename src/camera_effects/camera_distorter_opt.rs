@@ -1,3 +1,4 @@
+#[derive(Clone, Copy)]
 pub struct CameraDistorterOpt {
     pub noise_scale: f32,
     pub amplitude: f32, // 0.0 to 2.0
@@ -1,7 +1,7 @@
 pub mod camera_distorter;
 pub mod camera_distorter_opt;
 
-#[derive(PartialEq)]
+#[derive(PartialEq, Clone, Copy)]
 pub enum CameraEffects {
     Distorter,
     NoEffects,
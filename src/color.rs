@@ -1,5 +1,6 @@
 use core::fmt;
 use image::{Pixel, Rgba};
+use rand::rngs::SmallRng;
 use rand::Rng;
 use std::ops::{Add, AddAssign, Div, DivAssign, Mul};
 
@@ -46,8 +47,7 @@ impl Color {
         }
     }
 
-    pub fn random() -> Color {
-        let mut rng = rand::thread_rng();
+    pub fn random(rng: &mut SmallRng) -> Color {
         Color {
             red: rng.gen::<f32>(),
             green: rng.gen::<f32>(),
@@ -0,0 +1,127 @@
+use crate::aabb::Aabb;
+use crate::cylinder::{cap_hit, side_uv};
+use crate::hitable::{HitRecord, Hitable};
+use crate::material::Material;
+use crate::ray::Ray;
+use glam::Vec3A;
+
+// Cone with the base on the xz plane at `position` and the apex `height` above it.
+// The base is closed when capped. Wrap it in a Transformed to orient it.
+#[derive(Debug)]
+pub struct Cone {
+    pub position: Vec3A,
+    pub radius: f32,
+    pub height: f32,
+    pub capped: bool,
+    pub mat: Material,
+}
+
+impl Hitable for Cone {
+    fn hit(&self, r: &Ray, tmin: f32, tmax: f32) -> Option<HitRecord<'_>> {
+        let o = r.origin - self.position;
+        let d = r.direction;
+        let mut closest = tmax;
+        let mut hit = None;
+
+        // side, x² + z² = (k (height - y))², with k the slope of the side
+        let k = self.radius / self.height;
+        let k2 = k * k;
+        let w = self.height - o.y;
+        let a = d.x * d.x + d.z * d.z - k2 * d.y * d.y;
+        let half_b = o.x * d.x + o.z * d.z + k2 * w * d.y;
+        let c = o.x * o.x + o.z * o.z - k2 * w * w;
+        let roots = if a.abs() > f32::EPSILON {
+            let discriminant = half_b * half_b - a * c;
+            if discriminant >= 0.0 {
+                let sqrtd = discriminant.sqrt();
+                let (t0, t1) = ((-half_b - sqrtd) / a, (-half_b + sqrtd) / a);
+                [t0.min(t1), t0.max(t1)]
+            } else {
+                [f32::MAX; 2]
+            }
+        } else {
+            // the ray is parallel to the side
+            [-c / (2.0 * half_b), f32::MAX]
+        };
+        for t in roots {
+            let y = o.y + t * d.y;
+            if t > tmin && t < closest && y >= 0.0 && y <= self.height {
+                let p = o + t * d;
+                // the apex has no normal, it looks up
+                let normal = Vec3A::new(p.x, k2 * (self.height - p.y), p.z)
+                    .try_normalize()
+                    .unwrap_or(Vec3A::Y);
+                closest = t;
+                hit = Some((t, normal, side_uv(p, self.height)));
+                break;
+            }
+        }
+
+        if self.capped {
+            if let Some(cap) = cap_hit(o, d, 0.0, self.radius, -1.0, tmin, closest) {
+                hit = Some(cap);
+            }
+        }
+
+        hit.map(|(t, normal, (u, v))| HitRecord {
+            t,
+            pos: r.point_at_parameter(t),
+            normal,
+            geometric_normal: normal,
+            mat: &self.mat,
+            u,
+            v,
+            vertex_color: None,
+        })
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(Aabb {
+            min: self.position - Vec3A::new(self.radius, 0.0, self.radius),
+            max: self.position + Vec3A::new(self.radius, self.height, self.radius),
+        })
+    }
+
+    fn material(&self) -> Option<&Material> {
+        Some(&self.mat)
+    }
+}
+
+#[test]
+fn test_cone_hits() {
+    use crate::test_utils::{cast, close, white_material};
+
+    let cone = Cone {
+        position: Vec3A::ZERO,
+        radius: 1.0,
+        height: 2.0,
+        capped: true,
+        mat: white_material(),
+    };
+    let hit = |origin, direction| cast(&cone, origin, direction);
+
+    // straight down on the apex
+    let (t, normal) = hit(Vec3A::new(0.0, 5.0, 0.0), -Vec3A::Y).unwrap();
+    assert!(
+        (t - 3.0).abs() < 1e-4 && close(normal, Vec3A::Y),
+        "{t} {normal}"
+    );
+    // halfway up the side the radius is 0.5
+    let (t, normal) = hit(Vec3A::new(5.0, 1.0, 0.0), -Vec3A::X).unwrap();
+    assert!((t - 4.5).abs() < 1e-4, "{t}");
+    assert!(
+        close(normal, Vec3A::new(2.0, 1.0, 0.0).normalize()),
+        "{normal}"
+    );
+    // the base cap from below, and past the side of the base
+    let (t, normal) = hit(Vec3A::new(0.2, -3.0, 0.1), Vec3A::Y).unwrap();
+    assert!(
+        (t - 3.0).abs() < 1e-4 && close(normal, -Vec3A::Y),
+        "{t} {normal}"
+    );
+    assert!(hit(Vec3A::new(1.1, -3.0, 0.0), Vec3A::Y).is_none());
+
+    let aabb = cone.bounding_box().unwrap();
+    assert!(close(aabb.min, Vec3A::new(-1.0, 0.0, -1.0)));
+    assert!(close(aabb.max, Vec3A::new(1.0, 2.0, 1.0)));
+}
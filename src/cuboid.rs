@@ -0,0 +1,103 @@
+use crate::aabb::Aabb;
+use crate::hitable::{HitRecord, Hitable};
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::rect::intersect;
+use glam::Vec3A;
+
+// the axes of the rects of the sides, like the ones of Rect: the normal is along the
+// last one
+const SIDE_AXES: [[usize; 3]; 3] = [[0, 1, 2], [1, 2, 0], [0, 2, 1]];
+
+// Axis aligned box made of six rects with the normals pointing outside. Wrap it in a
+// Transformed to rotate it.
+#[derive(Debug)]
+pub struct Cuboid {
+    min: Vec3A,
+    max: Vec3A,
+    mat: Material,
+}
+
+impl Cuboid {
+    pub fn new(min: Vec3A, max: Vec3A, mat: Material) -> Self {
+        Cuboid { min, max, mat }
+    }
+}
+
+impl Hitable for Cuboid {
+    fn hit(&self, r: &Ray, tmin: f32, tmax: f32) -> Option<HitRecord<'_>> {
+        let bound = |idx: usize| (self.min[idx], self.max[idx]);
+        let mut closest: Option<(f32, f32, f32, Vec3A)> = None;
+        for axes @ [a_idx, b_idx, k_idx] in SIDE_AXES {
+            for (k, sign) in [(self.max[k_idx], 1.0), (self.min[k_idx], -1.0)] {
+                let tmax = closest.map_or(tmax, |(t, ..)| t);
+                let hit = intersect(bound(a_idx), bound(b_idx), axes, k, r, tmin, tmax);
+                if let Some((t, u, v)) = hit {
+                    let mut normal = Vec3A::ZERO;
+                    normal[k_idx] = sign;
+                    closest = Some((t, u, v, normal));
+                }
+            }
+        }
+        let (t, u, v, normal) = closest?;
+        Some(HitRecord {
+            t,
+            pos: r.point_at_parameter(t),
+            normal,
+            geometric_normal: normal,
+            mat: &self.mat,
+            u,
+            v,
+            vertex_color: None,
+        })
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(Aabb {
+            min: self.min,
+            max: self.max,
+        })
+    }
+
+    fn material(&self) -> Option<&Material> {
+        Some(&self.mat)
+    }
+}
+
+#[test]
+fn test_cuboid_hits() {
+    use crate::test_utils::{cast, white_material};
+
+    let cuboid = Cuboid::new(
+        Vec3A::new(-1.0, -2.0, -3.0),
+        Vec3A::new(1.0, 2.0, 3.0),
+        white_material(),
+    );
+    let hit = |origin, direction| cast(&cuboid, origin, direction);
+    // the closest of the sides, with the normals pointing outside
+    for (origin, direction, t, normal) in [
+        (Vec3A::new(5.0, 0.0, 0.0), -Vec3A::X, 4.0, Vec3A::X),
+        (Vec3A::new(-5.0, 0.5, 0.5), Vec3A::X, 4.0, -Vec3A::X),
+        (Vec3A::new(0.0, -5.0, 0.0), Vec3A::Y, 3.0, -Vec3A::Y),
+        (Vec3A::new(0.0, 0.0, 10.0), -Vec3A::Z, 7.0, Vec3A::Z),
+        // from inside
+        (Vec3A::ZERO, Vec3A::Y, 2.0, Vec3A::Y),
+    ] {
+        let (hit_t, hit_normal) = hit(origin, direction).unwrap();
+        assert!(
+            (hit_t - t).abs() < 1e-4 && hit_normal == normal,
+            "{origin} {direction}"
+        );
+    }
+    assert!(hit(Vec3A::new(5.0, 2.5, 0.0), -Vec3A::X).is_none());
+    // every side hits with the one material of the box
+    let ray = Ray::new(Vec3A::new(0.0, 5.0, 0.0), -Vec3A::Y);
+    let record = cuboid.hit(&ray, 0.001, f32::MAX).unwrap();
+    assert!(std::ptr::eq(record.mat, cuboid.material().unwrap()));
+
+    let aabb = cuboid.bounding_box().unwrap();
+    assert_eq!(
+        (aabb.min, aabb.max),
+        (Vec3A::new(-1.0, -2.0, -3.0), Vec3A::new(1.0, 2.0, 3.0))
+    );
+}
@@ -0,0 +1,150 @@
+use crate::aabb::Aabb;
+use crate::hitable::{HitRecord, Hitable};
+use crate::material::Material;
+use crate::ray::Ray;
+use glam::Vec3A;
+use std::f32::consts::PI;
+
+// Cylinder standing on the xz plane, from `position` up to `position.y + height`.
+// Without caps it is an open tube. Wrap it in a Transformed to orient it.
+#[derive(Debug)]
+pub struct Cylinder {
+    pub position: Vec3A,
+    pub radius: f32,
+    pub height: f32,
+    pub capped: bool,
+    pub mat: Material,
+}
+
+impl Hitable for Cylinder {
+    fn hit(&self, r: &Ray, tmin: f32, tmax: f32) -> Option<HitRecord<'_>> {
+        let o = r.origin - self.position;
+        let d = r.direction;
+        let mut closest = tmax;
+        let mut hit = None;
+
+        // side, x² + z² = radius²
+        let a = d.x * d.x + d.z * d.z;
+        let half_b = o.x * d.x + o.z * d.z;
+        let c = o.x * o.x + o.z * o.z - self.radius * self.radius;
+        let discriminant = half_b * half_b - a * c;
+        if a > f32::EPSILON && discriminant >= 0.0 {
+            let sqrtd = discriminant.sqrt();
+            for t in [(-half_b - sqrtd) / a, (-half_b + sqrtd) / a] {
+                let y = o.y + t * d.y;
+                if t > tmin && t < closest && y >= 0.0 && y <= self.height {
+                    let p = o + t * d;
+                    closest = t;
+                    hit = Some((
+                        t,
+                        Vec3A::new(p.x, 0.0, p.z) / self.radius,
+                        side_uv(p, self.height),
+                    ));
+                    break;
+                }
+            }
+        }
+
+        if self.capped {
+            for (y, normal_y) in [(0.0, -1.0), (self.height, 1.0)] {
+                if let Some(cap) = cap_hit(o, d, y, self.radius, normal_y, tmin, closest) {
+                    closest = cap.0;
+                    hit = Some(cap);
+                }
+            }
+        }
+
+        hit.map(|(t, normal, (u, v))| HitRecord {
+            t,
+            pos: r.point_at_parameter(t),
+            normal,
+            geometric_normal: normal,
+            mat: &self.mat,
+            u,
+            v,
+            vertex_color: None,
+        })
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(Aabb {
+            min: self.position - Vec3A::new(self.radius, 0.0, self.radius),
+            max: self.position + Vec3A::new(self.radius, self.height, self.radius),
+        })
+    }
+
+    fn material(&self) -> Option<&Material> {
+        Some(&self.mat)
+    }
+}
+
+// u goes around the y axis, v goes up
+pub(crate) fn side_uv(p: Vec3A, height: f32) -> (f32, f32) {
+    let phi = p.z.atan2(p.x);
+    (phi / (2.0 * PI) + 0.5, p.y / height)
+}
+
+// The hit with the disc of the given radius at height `y`, in the space of the
+// primitive. The caps are mapped in the unit square of the uvs.
+pub(crate) fn cap_hit(
+    o: Vec3A,
+    d: Vec3A,
+    y: f32,
+    radius: f32,
+    normal_y: f32,
+    tmin: f32,
+    tmax: f32,
+) -> Option<(f32, Vec3A, (f32, f32))> {
+    if d.y.abs() < f32::EPSILON {
+        return None;
+    }
+    let t = (y - o.y) / d.y;
+    if t <= tmin || t >= tmax {
+        return None;
+    }
+    let p = o + t * d;
+    if p.x * p.x + p.z * p.z > radius * radius {
+        return None;
+    }
+    let uv = ((p.x / radius + 1.0) * 0.5, (p.z / radius + 1.0) * 0.5);
+    Some((t, Vec3A::new(0.0, normal_y, 0.0), uv))
+}
+
+#[test]
+fn test_cylinder_hits() {
+    use crate::test_utils::{cast, close, white_material};
+
+    let cylinder = |capped: bool| Cylinder {
+        position: Vec3A::ZERO,
+        radius: 1.0,
+        height: 2.0,
+        capped,
+        mat: white_material(),
+    };
+
+    let capped = cylinder(true);
+    let (t, normal) = cast(&capped, Vec3A::new(5.0, 1.0, 0.0), -Vec3A::X).unwrap();
+    assert!(
+        (t - 4.0).abs() < 1e-4 && close(normal, Vec3A::X),
+        "{t} {normal}"
+    );
+    let (t, normal) = cast(&capped, Vec3A::new(0.3, 5.0, 0.0), -Vec3A::Y).unwrap();
+    assert!(
+        (t - 3.0).abs() < 1e-4 && close(normal, Vec3A::Y),
+        "{t} {normal}"
+    );
+    // from inside, the side is hit from its back
+    let (t, normal) = cast(&capped, Vec3A::new(0.0, 1.0, 0.0), Vec3A::Z).unwrap();
+    assert!(
+        (t - 1.0).abs() < 1e-4 && close(normal, Vec3A::Z),
+        "{t} {normal}"
+    );
+    // above the top
+    assert!(cast(&capped, Vec3A::new(5.0, 2.5, 0.0), -Vec3A::X).is_none());
+    // the open tube lets the rays along its axis through
+    assert!(cast(&cylinder(false), Vec3A::new(0.3, 5.0, 0.0), -Vec3A::Y).is_none());
+
+    let aabb = capped.bounding_box().unwrap();
+    assert!(close(aabb.min, Vec3A::new(-1.0, 0.0, -1.0)));
+    assert!(close(aabb.max, Vec3A::new(1.0, 2.0, 1.0)));
+}
@@ -3,6 +3,8 @@ use crate::hitable::{HitRecord, Hitable};
 use crate::material::Material;
 use crate::ray::Ray;
 use glam::Vec3A;
+use rand::rngs::SmallRng;
+use rand::Rng;
 
 #[derive(Debug)]
 pub struct Disc {
@@ -40,9 +42,11 @@ impl Hitable for Disc {
                     t: distance,
                     pos: ray.point_at_parameter(distance),
                     normal: self.normal,
+                    geometric_normal: self.normal,
                     mat: &self.mat,
                     u: 0.0,
                     v: 0.0,
+                    vertex_color: None,
                 });
             } else {
                 None
@@ -51,4 +55,27 @@ impl Hitable for Disc {
             None
         }
     }
+
+    fn material(&self) -> Option<&Material> {
+        Some(&self.mat)
+    }
+
+    fn pdf_value(&self, origin: Vec3A, direction: Vec3A) -> f32 {
+        match self.hit(&Ray::new(origin, direction), 0.001, f32::MAX) {
+            Some(hit) => {
+                let area = std::f32::consts::PI * self.radius * self.radius;
+                let cosine = self.normal.normalize().dot(direction.normalize()).abs();
+                hit.t * hit.t / (cosine * area)
+            }
+            None => 0.0,
+        }
+    }
+
+    fn random(&self, origin: Vec3A, rng: &mut SmallRng) -> Vec3A {
+        let (a, b) = self.normal.normalize().any_orthonormal_pair();
+        let r = self.radius * rng.gen::<f32>().sqrt();
+        let phi = 2.0 * std::f32::consts::PI * rng.gen::<f32>();
+        let point = self.position + a * (r * phi.cos()) + b * (r * phi.sin());
+        point - origin
+    }
 }
@@ -1,18 +1,22 @@
 use crate::camera::Camera;
 use crate::color::Color;
+use crate::cuboid::Cuboid;
 use crate::hitable::HitableStore;
 use crate::material::{Dielectric, Material};
 use crate::sdf::TracedSDF;
 use crate::setup::BLACK;
 use crate::sphere::Sphere;
 use crate::rect::Rect;
+use crate::scene_description::CameraDescription;
 use crate::texture::Texture;
+use crate::transform::{affine, Transformed};
 use crate::utils::random_in_unit_sphere;
 use glam::Vec3A;
-use rand::Rng;
+use rand::rngs::SmallRng;
+use rand::{Rng, SeedableRng};
 use sdfu::SDF;
 
-#[derive(PartialEq)]
+#[derive(PartialEq, Clone, Copy)]
 pub enum Worlds {
     Random,
     RandomGlass,
@@ -24,7 +28,7 @@ pub enum Worlds {
     SimpleAreaLight,
 }
 
-pub fn simple_area_light() -> HitableStore {
+pub fn simple_area_light(seed: u64) -> HitableStore {
     let white = Texture::constant_color(Color {
         red: 1.0,
         green: 1.0,
@@ -36,7 +40,7 @@ pub fn simple_area_light() -> HitableStore {
         green: 0.0,
         blue: 0.0,
     });
-    let noise = Material::lambertian(Texture::noise(8.0));
+    let noise = Material::lambertian(Texture::noise(8.0, seed));
 
     let area_light = Rect::new_yz((-2.0,2.0), (-2.0,2.0), 4.0, Material::diffuse_light(white));
     let sphere = Sphere {
@@ -124,7 +128,8 @@ pub fn world_default() -> HitableStore {
     hitables
 }
 
-pub fn world_random_vertical_wall() -> HitableStore {
+pub fn world_random_vertical_wall(seed: u64) -> HitableStore {
+    let mut rng = SmallRng::seed_from_u64(seed);
     let blu = Texture::constant_color(Color {
         red: 0.1,
         green: 0.0,
@@ -203,10 +208,9 @@ pub fn world_random_vertical_wall() -> HitableStore {
         },
     ];
 
-    fn random_material() -> Box<Material> {
-        let mut rng = rand::thread_rng();
-        let rand_col_a = Texture::constant_color(Color::random());
-        let rand_col_b = Texture::constant_color(Color::random());
+    fn random_material(rng: &mut SmallRng) -> Box<Material> {
+        let rand_col_a = Texture::constant_color(Color::random(rng));
+        let rand_col_b = Texture::constant_color(Color::random(rng));
 
         let coin = rng.gen::<f32>();
         if coin >= 0.0 && coin < 0.5 {
@@ -222,8 +226,6 @@ pub fn world_random_vertical_wall() -> HitableStore {
         }
     }
 
-    let mut rng = rand::thread_rng();
-
     for _ in 0..500 {
         let r = rng.gen_range(0.2..0.35);
         let random_x = rng.gen_range(-7.0..7.0);
@@ -239,7 +241,7 @@ pub fn world_random_vertical_wall() -> HitableStore {
             spheres.push(Sphere {
                 position: pos,
                 radius: r,
-                mat: *random_material(),
+                mat: *random_material(&mut rng),
             });
         }
     }
@@ -252,7 +254,8 @@ pub fn world_random_vertical_wall() -> HitableStore {
     hitables
 }
 
-pub fn world_random() -> HitableStore {
+pub fn world_random(seed: u64) -> HitableStore {
+    let mut rng = SmallRng::seed_from_u64(seed);
     let orange = Texture::constant_color(Color {
         red: 1.0,
         green: 0.6,
@@ -344,10 +347,9 @@ pub fn world_random() -> HitableStore {
         },
     ];
 
-    fn random_material() -> Box<Material> {
-        let mut rng = rand::thread_rng();
-        let rand_col_a = Texture::constant_color(Color::random());
-        let rand_col_b = Texture::constant_color(Color::random());
+    fn random_material(rng: &mut SmallRng) -> Box<Material> {
+        let rand_col_a = Texture::constant_color(Color::random(rng));
+        let rand_col_b = Texture::constant_color(Color::random(rng));
 
         let coin = rng.gen::<f32>();
         if coin >= 0.0 && coin < 0.5 {
@@ -365,7 +367,7 @@ pub fn world_random() -> HitableStore {
 
     for _ in 0..500 {
         let r = 0.4;
-        let rv = random_in_unit_sphere();
+        let rv = random_in_unit_sphere(&mut rng);
         let pos = 20.0 * Vec3A::new(rv.x, 0.0, rv.y) + Vec3A::new(0.0, r, 0.0);
         if spheres
             .iter()
@@ -374,7 +376,7 @@ pub fn world_random() -> HitableStore {
             spheres.push(Sphere {
                 position: pos,
                 radius: r,
-                mat: *random_material(),
+                mat: *random_material(&mut rng),
             });
         }
     }
@@ -386,7 +388,8 @@ pub fn world_random() -> HitableStore {
     hitables
 }
 
-pub fn world_random_glass() -> HitableStore {
+pub fn world_random_glass(seed: u64) -> HitableStore {
+    let mut rng = SmallRng::seed_from_u64(seed);
     let mut spheres: Vec<Sphere> = vec![
         // sfere di vetro concentriche
         Sphere {
@@ -472,22 +475,31 @@ pub fn world_random_glass() -> HitableStore {
         Sphere {
             position: Vec3A::new(8.0, 1.6, -1.3),
             radius: 1.6,
-            mat: Material::Dielectric(Dielectric::new(1.5)),
+            mat: Material::Dielectric(Dielectric::new(1.5).with_roughness(0.2)),
         },
     ];
 
+    // the colors have their own generator, so that the spheres stay where they were
+    let mut color_rng = SmallRng::seed_from_u64(seed.wrapping_add(1));
     for _ in 0..20 {
         let r = 0.4;
-        let rv = random_in_unit_sphere();
+        let rv = random_in_unit_sphere(&mut rng);
         let pos = Vec3A::new(rv.x * -5.0, 0.0, rv.y * 20.0) + Vec3A::new(0.0, r, 0.0);
         if spheres
             .iter()
             .all(|s| (s.position - pos).length() >= s.radius + r)
         {
+            // the light that goes through the middle keeps about the random color
+            let color = Color::random(&mut color_rng);
+            let absorption = Color::new(
+                -color.red.max(0.05).ln(),
+                -color.green.max(0.05).ln(),
+                -color.blue.max(0.05).ln(),
+            ) * (1.0 / (2.0 * r));
             spheres.push(Sphere {
                 position: pos,
                 radius: r,
-                mat: Material::Dielectric(Dielectric::new(1.5)),
+                mat: Material::Dielectric(Dielectric::new(1.5).with_absorption(absorption)),
             });
         }
     }
@@ -505,9 +517,10 @@ pub fn world_cornell_box() -> HitableStore {
     let green = Color::new(0.12, 0.45, 0.15);
     let light = Color::new(1.0, 1.0, 1.0) * 15.0;
 
+    // just below the ceiling, the rays would hit either of the two on the same plane
     let mut area_light = Rect::new_xz(
         (213.0,343.0), (227.0,332.0),
-        550.0,
+        549.0,
         Material::diffuse_light(Texture::constant_color(Color::new(15.0, 15.0, 15.0))));
     area_light.flip_normals();
     let floor = Rect::new_xz((0.0, 555.0), (0.0, 555.0), 0.0, Material::lambertian(Texture::constant_color(white)));
@@ -518,7 +531,14 @@ pub fn world_cornell_box() -> HitableStore {
     right_w.flip_normals();
     let back_w = Rect::new_xy((0.0, 555.0), (0.0, 555.0), 0.0, Material::lambertian(Texture::constant_color(white)));
     //back_w.flip_normals();
-        
+
+    // the two blocks of the original cornell box, mirrored as the back wall is at z = 0
+    let tall_block = Transformed::new(
+        Cuboid::new(Vec3A::ZERO, Vec3A::new(165.0, 330.0, 165.0), Material::lambertian(Texture::constant_color(white))),
+        affine(Vec3A::new(265.0, 0.0, 100.0), Vec3A::Y, -15.0, Vec3A::ONE));
+    let short_block = Transformed::new(
+        Cuboid::new(Vec3A::ZERO, Vec3A::splat(165.0), Material::lambertian(Texture::constant_color(white))),
+        affine(Vec3A::new(130.0, 0.0, 330.0), Vec3A::Y, 18.0, Vec3A::ONE));
 
     let mut hitables = HitableStore::new();
     hitables.push(floor);
@@ -527,11 +547,13 @@ pub fn world_cornell_box() -> HitableStore {
     hitables.push(right_w);
     hitables.push(back_w);
     hitables.push(area_light);
+    hitables.push(tall_block);
+    hitables.push(short_block);
     hitables
 
 }
 
-pub fn world_wall_sdf() -> HitableStore {
+pub fn world_wall_sdf(seed: u64) -> HitableStore {
     let w = 4;
     let h = 4;
     let step = 6.2;
@@ -539,7 +561,7 @@ pub fn world_wall_sdf() -> HitableStore {
     let min_z = 0.5;
     let max_z = 2.4;
     let mut hitables = HitableStore::new();
-    let mut rng = rand::thread_rng();
+    let mut rng = SmallRng::seed_from_u64(seed);
     for x in 0..w {
         let mut xf = x as f32 / w as f32;
         xf -= 0.5;
@@ -677,114 +699,55 @@ pub fn get_world_and_camera(
     frame_width: u32,
     frame_height: u32,
     camera_aperture: f32,
+    seed: u64,
 ) -> (HitableStore, Camera, Color) {
-    match w {
-        Worlds::CornellBox => {
-            let look_from = Vec3A::new(278.0, 278.0, 800.0);
-            let look_at = Vec3A::new(278.0, 278.0, 0.0);
-
-            let camera = Camera::new(
-                look_from,
-                look_at,
-                camera_fov,
-                (frame_width as f32) / (frame_height as f32),
-                camera_aperture,
-            );
-            return (world_cornell_box(), camera, Color::new(0.70, 0.80, 1.00));
-        }
-        Worlds::SimpleAreaLight => {
-            let look_from = Vec3A::new(0.0, 0.0, 9.0);
-            let look_at = Vec3A::new(0.0, 0.0, 0.0);
-
-            let camera = Camera::new(
-                look_from,
-                look_at,
-                camera_fov,
-                (frame_width as f32) / (frame_height as f32),
-                camera_aperture,
-            );
-            //return (simple_area_light(), camera, Color::new(0.0, 0.0, 0.0));
-            return (simple_area_light(), camera, BLACK);
-        }
-        Worlds::Random => {
-            let look_at = Vec3A::new(0.0, 0.0, -1.0);
-            let look_from = Vec3A::new(20.0 * 0.47f32.cos(), 4.0 * 0.47f32.sin(), 3.0);
-
-            let camera = Camera::new(
-                look_from,
-                look_at,
-                camera_fov,
-                (frame_width as f32) / (frame_height as f32),
-                camera_aperture,
-            );
-            return (world_random(), camera, Color::new(0.70, 0.80, 1.00));
-        }
-        Worlds::RandomGlass => {
-            let look_at = Vec3A::new(0.0, 0.0, -1.0);
-            let look_from = Vec3A::new(20.0 * 0.47f32.cos(), 4.0 * 0.47f32.sin(), 3.0);
-
-            let camera = Camera::new(
-                look_from,
-                look_at,
-                camera_fov,
-                (frame_width as f32) / (frame_height as f32),
-                camera_aperture,
-            );
-            return (world_random_glass(), camera, Color::new(0.70, 0.80, 1.00));
-        }
-        Worlds::VerticalWall => {
-            let look_at = Vec3A::new(0.0, 0.0, -1.0);
-            let look_from = Vec3A::new(2.0 * 0.47f32.cos(), 2.0 * 0.47f32.sin(), 3.0);
-
-            let camera = Camera::new(
-                look_from,
-                look_at,
-                camera_fov,
-                (frame_width as f32) / (frame_height as f32),
-                camera_aperture,
-            );
-            return (world_random_vertical_wall(), camera, Color::new(0.70, 0.80, 1.00));
-        }
-        Worlds::ThreeSphere => {
-            let look_at = Vec3A::new(0.0, 0.0, -1.0);
-            let look_from = Vec3A::new(0.0, 0.0, 1.0);
-
-            let camera = Camera::new(
-                look_from,
-                look_at,
-                camera_fov,
-                (frame_width as f32) / (frame_height as f32),
-                camera_aperture,
-            );
-            return (world_default(), camera, Color::new(0.70, 0.80, 1.00));
-        }
+    let camera =
+        example_camera(w, camera_fov, camera_aperture).to_camera(frame_width, frame_height);
+    let (hitables, background) = example_world(w, seed);
+    (hitables, camera, background)
+}
 
-        Worlds::SdfSpheres => {
-            let look_at = Vec3A::new(0.0, 0.0, -1.0);
-            let look_from = Vec3A::new(0.0, 0.0, 1.0);
+// Where the camera of every example world looks from and at
+pub fn example_camera(w: &Worlds, camera_fov: f32, camera_aperture: f32) -> CameraDescription {
+    let (look_from, look_at) = match w {
+        Worlds::CornellBox => ([278.0, 278.0, 800.0], [278.0, 278.0, 0.0]),
+        Worlds::SimpleAreaLight => ([0.0, 0.0, 9.0], [0.0, 0.0, 0.0]),
+        Worlds::Random | Worlds::RandomGlass => (
+            [20.0 * 0.47f32.cos(), 4.0 * 0.47f32.sin(), 3.0],
+            [0.0, 0.0, -1.0],
+        ),
+        Worlds::VerticalWall => (
+            [2.0 * 0.47f32.cos(), 2.0 * 0.47f32.sin(), 3.0],
+            [0.0, 0.0, -1.0],
+        ),
+        Worlds::ThreeSphere | Worlds::SdfSpheres => ([0.0, 0.0, 1.0], [0.0, 0.0, -1.0]),
+        Worlds::SdfWall => ([1.1, 0.9, 1.0], [0.0, 0.0, -1.0]),
+    };
+    CameraDescription {
+        look_from,
+        look_at,
+        fov: camera_fov,
+        aperture: camera_aperture,
+        ..CameraDescription::default()
+    }
+}
 
-            let camera = Camera::new(
-                look_from,
-                look_at,
-                camera_fov,
-                (frame_width as f32) / (frame_height as f32),
-                camera_aperture,
-            );
-            return (world_sdf(), camera, Color::new(0.70, 0.80, 1.00));
-        }
-        Worlds::SdfWall => {
-            let look_at = Vec3A::new(0.0, 0.0, -1.0);
-            let look_from = Vec3A::new(1.1, 0.9, 1.0);
-            //let look_from = Vec3A::new(0.0, 0.0, 1.0);
-
-            let camera = Camera::new(
-                look_from,
-                look_at,
-                camera_fov,
-                (frame_width as f32) / (frame_height as f32),
-                camera_aperture,
-            );
-            return (world_wall_sdf(), camera, Color::new(0.70, 0.80, 1.00));
-        }
+// The hitables and the background of every example world
+pub fn example_world(w: &Worlds, seed: u64) -> (HitableStore, Color) {
+    let sky = Color::new(0.70, 0.80, 1.00);
+    match w {
+        Worlds::CornellBox => (world_cornell_box(), sky),
+        Worlds::SimpleAreaLight => (simple_area_light(seed), BLACK),
+        Worlds::Random => (world_random(seed), sky),
+        Worlds::RandomGlass => (world_random_glass(seed), sky),
+        Worlds::VerticalWall => (world_random_vertical_wall(seed), sky),
+        Worlds::ThreeSphere => (world_default(), sky),
+        Worlds::SdfSpheres => (world_sdf(), sky),
+        Worlds::SdfWall => (world_wall_sdf(seed), sky),
     }
 }
+
+#[test]
+fn test_random_glass_takes_any_seed() {
+    assert!(!world_random_glass(u64::MAX).is_empty());
+}
@@ -0,0 +1,177 @@
+use crate::color::Color;
+use crate::render_error::RenderError;
+use crate::setup::BLACK;
+use crate::tone_mapping::ToneMappingOpt;
+use image::codecs::hdr::HdrEncoder;
+use image::{DynamicImage, ImageBuffer, Rgb, Rgba};
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+
+// Linear, unclamped RGB pixels as they come out of the renderers.
+// Emitters brighter than 1.0 are kept as they are, so the image can be saved in a
+// high dynamic range format or converted to 8 bit afterwards.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Framebuffer {
+    width: u32,
+    height: u32,
+    pixels: Vec<Color>,
+}
+
+impl Framebuffer {
+    pub fn new(width: u32, height: u32) -> Self {
+        Framebuffer {
+            width,
+            height,
+            pixels: vec![BLACK; (width * height) as usize],
+        }
+    }
+
+    pub fn from_pixels(width: u32, height: u32, pixels: Vec<Color>) -> Self {
+        assert_eq!(pixels.len(), (width * height) as usize);
+        Framebuffer {
+            width,
+            height,
+            pixels,
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn pixels(&self) -> &[Color] {
+        &self.pixels
+    }
+
+    pub fn get_pixel(&self, x: u32, y: u32) -> Color {
+        self.pixels[self.index(x, y)]
+    }
+
+    pub fn put_pixel(&mut self, x: u32, y: u32, color: Color) {
+        let index = self.index(x, y);
+        self.pixels[index] = color;
+    }
+
+    // The 8 bit, gamma encoded version of the image, used by the GUI and to save PNGs.
+    pub fn to_rgba(&self) -> ImageBuffer<Rgba<u8>, Vec<u8>> {
+        self.to_rgba_tone_mapped(&ToneMappingOpt::default())
+    }
+
+    pub fn to_rgba_tone_mapped(
+        &self,
+        tone_mapping: &ToneMappingOpt,
+    ) -> ImageBuffer<Rgba<u8>, Vec<u8>> {
+        ImageBuffer::from_fn(self.width, self.height, |x, y| {
+            tone_mapping.to_rgba(self.get_pixel(x, y))
+        })
+    }
+
+    pub fn to_rgb32f(&self) -> ImageBuffer<Rgb<f32>, Vec<f32>> {
+        ImageBuffer::from_fn(self.width, self.height, |x, y| {
+            let col = self.get_pixel(x, y);
+            Rgb([col.red, col.green, col.blue])
+        })
+    }
+
+    pub fn save_exr(&self, path: &Path) -> Result<(), RenderError> {
+        self.to_rgb32f()
+            .save_with_format(path, image::ImageFormat::OpenExr)?;
+        Ok(())
+    }
+
+    // Radiance .hdr file
+    pub fn save_hdr(&self, path: &Path) -> Result<(), RenderError> {
+        let pixels: Vec<Rgb<f32>> = self
+            .pixels
+            .iter()
+            .map(|col| Rgb([col.red, col.green, col.blue]))
+            .collect();
+        let writer = BufWriter::new(File::create(path)?);
+        HdrEncoder::new(writer).encode(&pixels, self.width as usize, self.height as usize)?;
+        Ok(())
+    }
+
+    // Saves the image in the format given by the extension of the path. Exr and hdr files
+    // keep the linear values, every other format gets the 8 bit version.
+    pub fn save(&self, path: &Path) -> Result<(), RenderError> {
+        self.save_tone_mapped(path, &ToneMappingOpt::default())
+    }
+
+    // Like `save`, the tone mapping is used only by the 8 bit formats.
+    pub fn save_tone_mapped(
+        &self,
+        path: &Path,
+        tone_mapping: &ToneMappingOpt,
+    ) -> Result<(), RenderError> {
+        let extension = path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .map(|ext| ext.to_lowercase());
+        match extension.as_deref() {
+            Some("exr") => self.save_exr(path),
+            Some("hdr") => self.save_hdr(path),
+            _ => {
+                // without alpha, so that formats like jpeg can be written too
+                DynamicImage::ImageRgba8(self.to_rgba_tone_mapped(tone_mapping))
+                    .to_rgb8()
+                    .save(path)?;
+                Ok(())
+            }
+        }
+    }
+
+    fn index(&self, x: u32, y: u32) -> usize {
+        (y * self.width + x) as usize
+    }
+}
+
+#[test]
+fn test_exr_and_hdr_keep_linear_values() {
+    // values above 1 and in the shadows, that an 8 bit image would lose
+    let colors = [
+        Color::new(0.0, 0.0, 0.0),
+        Color::new(0.002, 0.25, 0.5),
+        Color::new(1.0, 1.0, 1.0),
+        Color::new(4.0, 16.0, 250.0),
+        Color::new(0.75, 0.1, 3.5),
+        Color::new(0.01, 0.02, 0.03),
+    ];
+    let mut framebuffer = Framebuffer::new(3, 2);
+    for (i, color) in colors.into_iter().enumerate() {
+        framebuffer.put_pixel(i as u32 % 3, i as u32 / 3, color);
+    }
+    let dir = std::env::temp_dir().join("tuot_framebuffer_test");
+    std::fs::create_dir_all(&dir).unwrap();
+    let check = |file: &str, pixels: Vec<Rgb<f32>>, tolerance: f32| {
+        assert_eq!(pixels.len(), colors.len());
+        for (pixel, expected) in pixels.into_iter().zip(colors) {
+            let expected = [expected.red, expected.green, expected.blue];
+            let brightest = expected.iter().copied().fold(0.0, f32::max);
+            for (channel, expected) in pixel.0.into_iter().zip(expected) {
+                assert!(
+                    (channel - expected).abs() <= tolerance * brightest,
+                    "{file}: {pixel:?}"
+                );
+            }
+        }
+    };
+
+    // exr stores the floats
+    let path = dir.join("image.exr");
+    framebuffer.save(&path).unwrap();
+    let image = image::open(&path).unwrap().to_rgb32f();
+    check("exr", image.pixels().copied().collect(), 1e-6);
+
+    // the shared exponent of hdr keeps about 1% of the brightest channel. image::open
+    // turns hdr files into 8 bit ones, the floats come from the decoder.
+    let path = dir.join("image.hdr");
+    framebuffer.save(&path).unwrap();
+    let reader = std::io::BufReader::new(File::open(&path).unwrap());
+    let decoder = image::codecs::hdr::HdrDecoder::new(reader).unwrap();
+    check("hdr", decoder.read_image_hdr().unwrap(), 0.01);
+}
@@ -0,0 +1,115 @@
+use glam::Vec3A;
+
+use crate::aabb::Aabb;
+use crate::camera::Camera;
+use crate::color::Color;
+use crate::hitable::{Hitable, HitableStore};
+use crate::material::Material;
+use crate::scene_description::CameraDescription;
+use crate::sphere::Sphere;
+use crate::texture::Texture;
+
+// The camera and the light of the models opened without a scene file, placed from their
+// bounding box so that they work at any size.
+
+// where the camera looks from, seen from the center of the model: a three-quarter view
+// from above
+const VIEW_DIRECTION: [f32; 3] = [1.0, 0.8, 2.0];
+// where the key light is, above the model and about 45 degrees from the camera
+const LIGHT_DIRECTION: [f32; 3] = [-0.5, 1.5, 1.5];
+// distance and radius of the key light, in radii of the model
+const LIGHT_DISTANCE: f32 = 3.0;
+const LIGHT_RADIUS: f32 = 0.5;
+
+// A camera looking at the center of `aabb`, far enough to see the whole sphere around it
+// with the vertical field of view `fov`, in degrees, on a frame of the `aspect` ratio.
+pub fn frame_camera(aabb: &Aabb, fov: f32, aspect: f32, aperture: f32) -> Camera {
+    let look_from = framing_position(aabb, fov, aspect);
+    Camera::new(look_from, aabb.center(), fov, aspect, aperture)
+}
+
+// The same camera as `frame_camera`, as a description that can be animated
+pub fn frame_camera_description(
+    aabb: &Aabb,
+    fov: f32,
+    aspect: f32,
+    aperture: f32,
+) -> CameraDescription {
+    CameraDescription {
+        look_from: framing_position(aabb, fov, aspect).into(),
+        look_at: aabb.center().into(),
+        fov,
+        aperture,
+        ..CameraDescription::default()
+    }
+}
+
+fn framing_position(aabb: &Aabb, fov: f32, aspect: f32) -> Vec3A {
+    let half_height = (fov.to_radians() * 0.5).tan();
+    // on portrait frames the horizontal field of view is the narrower one
+    let half_fov = (half_height * aspect.min(1.0)).atan();
+    let radius = aabb.radius().max(f32::EPSILON);
+    let distance = radius / half_fov.sin();
+    aabb.center() + Vec3A::from(VIEW_DIRECTION).normalize() * distance
+}
+
+// Adds a spherical light scaled with the model when none of the hitables is emissive.
+// Its irradiance at the center of the model is the same at any scale. Returns whether
+// the light was added.
+pub fn add_key_light(hitables: &mut HitableStore) -> bool {
+    let has_emitters = hitables
+        .iter()
+        .any(|hitable| hitable.material().is_some_and(Material::is_emitter));
+    let aabb = match hitables.bounding_box() {
+        Some(aabb) if !has_emitters => aabb,
+        _ => return false,
+    };
+
+    let radius = aabb.radius().max(f32::EPSILON);
+    // the irradiance of a sphere of radiance L is about L * PI * (r / d)^2, PI like the
+    // one of a white sky
+    let radiance = (LIGHT_DISTANCE / LIGHT_RADIUS).powi(2);
+    hitables.push(Sphere {
+        position: aabb.center()
+            + Vec3A::from(LIGHT_DIRECTION).normalize() * radius * LIGHT_DISTANCE,
+        radius: radius * LIGHT_RADIUS,
+        mat: Material::diffuse_light(Texture::constant_color(
+            Color::new(1.0, 1.0, 1.0) * radiance,
+        )),
+    });
+    true
+}
+
+#[test]
+fn test_frame_camera_and_key_light() {
+    use crate::cuboid::Cuboid;
+
+    let aabb = Aabb {
+        min: Vec3A::new(-1.0, 0.0, -1.0),
+        max: Vec3A::new(3.0, 2.0, 1.0),
+    };
+    // on the portrait frame the sphere touches the sides
+    let camera = frame_camera(&aabb, 60.0, 0.5, 0.0);
+    let half_width = (30f32.to_radians().tan() * 0.5).atan();
+    let distance = camera.origin.distance(aabb.center());
+    assert!((distance * half_width.sin() - aabb.radius()).abs() < 1e-4);
+    // the camera looks at the center
+    let to_center = (aabb.center() - camera.origin).normalize();
+    assert!(to_center.distance(-camera.w) < 1e-4);
+    // on the landscape frame it touches the top and the bottom
+    let camera = frame_camera(&aabb, 60.0, 2.0, 0.0);
+    let distance = camera.origin.distance(aabb.center());
+    assert!((distance * 30f32.to_radians().sin() - aabb.radius()).abs() < 1e-4);
+
+    let description = frame_camera_description(&aabb, 60.0, 2.0, 0.0);
+    assert_eq!(Vec3A::from(description.look_from), camera.origin);
+    assert_eq!(Vec3A::from(description.look_at), aabb.center());
+
+    let grey = Material::lambertian(Texture::constant_color(Color::new(0.5, 0.5, 0.5)));
+    let mut hitables = HitableStore::new();
+    hitables.push(Cuboid::new(aabb.min, aabb.max, grey));
+    assert!(add_key_light(&mut hitables));
+    assert_eq!(hitables.len(), 2);
+    // the light just added is an emitter
+    assert!(!add_key_light(&mut hitables));
+}
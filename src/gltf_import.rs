@@ -0,0 +1,581 @@
+use std::f32::consts::PI;
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+
+use base64::Engine;
+use glam::{Affine3A, Mat4, Vec2, Vec3A};
+use gltf::khr_lights_punctual::Kind;
+use gltf::material::AlphaMode;
+use gltf::mesh::Mode;
+use image::{DynamicImage, GrayImage, Luma};
+
+use crate::bvh::BvhTree;
+use crate::color::Color;
+use crate::hitable::{Hitable, HitableStore};
+use crate::material::{Dielectric, Material};
+use crate::render_error::RenderError;
+use crate::scene_description::CameraDescription;
+use crate::sphere::Sphere;
+use crate::texture::{Bitmap, Texture};
+use crate::transform::Transformed;
+use crate::triangle_mesh::TriangleMesh;
+
+// the sun of a directional light is a sphere this many radii of the scene away from its
+// center, as wide as the real one in the sky
+const SUN_DISTANCE: f32 = 100.0;
+const SUN_ANGULAR_RADIUS: f32 = 0.0047;
+
+// The hitables and the camera of a glTF 2.0 file, .gltf with its buffers and images
+// next to it or a binary .glb.
+pub struct GltfScene {
+    pub hitables: HitableStore,
+    // the first perspective camera of the scene
+    pub camera: Option<CameraDescription>,
+    // what was imported only in part, like the cones of the spot lights
+    pub warnings: Vec<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct GltfOpt {
+    // radius of the spheres that replace the point and the spot lights
+    pub light_radius: f32,
+    // multiplies the intensity of the lights, given in candela for the point and the spot
+    // lights and in lux for the directional ones
+    pub light_scale: f32,
+}
+
+impl Default for GltfOpt {
+    fn default() -> Self {
+        GltfOpt {
+            light_radius: 0.05,
+            light_scale: 1.0,
+        }
+    }
+}
+
+impl From<gltf::Error> for RenderError {
+    fn from(error: gltf::Error) -> Self {
+        RenderError {
+            kind: String::from("gltf"),
+            message: error.to_string(),
+        }
+    }
+}
+
+// Every mesh is built once, in its own BVH when it has more primitives, and placed by
+// the nodes that use it. The metallic-roughness materials become microfacet ones, or
+// tinted dielectrics when they are transmissive, the emissive ones diffuse lights.
+pub fn load_gltf(path: &Path, opt: &GltfOpt) -> Result<GltfScene, RenderError> {
+    let base_dir = path.parent().unwrap_or_else(|| Path::new(""));
+    let gltf::Gltf { document, blob } = gltf::Gltf::open(path)?;
+    let buffers = document
+        .buffers()
+        .map(|buffer| buffer_data(&buffer, blob.as_deref(), base_dir))
+        .collect::<Result<Vec<_>, _>>()?;
+    let images = document
+        .images()
+        .map(|image| image_data(&image, &buffers, base_dir))
+        .collect::<Result<Vec<_>, _>>()?;
+    let materials: Vec<Arc<Material>> = document
+        .materials()
+        .map(|material| Arc::new(to_material(&material, &images)))
+        .collect();
+    let meshes = document
+        .meshes()
+        .map(|mesh| load_mesh(&mesh, &buffers, &images, &materials))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let scene = document
+        .default_scene()
+        .or_else(|| document.scenes().next())
+        .ok_or_else(|| RenderError {
+            kind: String::from("gltf"),
+            message: format!("{} has no scenes", path.display()),
+        })?;
+    let mut gltf_scene = GltfScene {
+        hitables: HitableStore::new(),
+        camera: None,
+        warnings: Vec::new(),
+    };
+    let mut suns = Vec::new();
+    for node in scene.nodes() {
+        add_node(
+            &node,
+            Mat4::IDENTITY,
+            &meshes,
+            opt,
+            &mut gltf_scene,
+            &mut suns,
+        );
+    }
+    add_suns(&suns, &mut gltf_scene.hitables);
+    Ok(gltf_scene)
+}
+
+// .gltf or .glb
+pub fn is_gltf(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("gltf") || ext.eq_ignore_ascii_case("glb"))
+}
+
+fn add_node(
+    node: &gltf::Node,
+    parent: Mat4,
+    meshes: &[Option<Arc<dyn Hitable + Send + Sync>>],
+    opt: &GltfOpt,
+    scene: &mut GltfScene,
+    suns: &mut Vec<(Vec3A, Vec3A)>,
+) {
+    let transform = parent * Mat4::from_cols_array_2d(&node.transform().matrix());
+    let position = Vec3A::from(transform.transform_point3(glam::Vec3::ZERO));
+    // cameras and lights look towards -z
+    let forward = Vec3A::from(transform.transform_vector3(-glam::Vec3::Z)).normalize_or_zero();
+
+    if let Some(mesh) = node.mesh().and_then(|mesh| meshes[mesh.index()].as_ref()) {
+        let instance = Transformed::new(Arc::clone(mesh), Affine3A::from_mat4(transform));
+        scene.hitables.push(instance);
+    }
+
+    if let Some(camera) = node.camera() {
+        if let (None, gltf::camera::Projection::Perspective(perspective)) =
+            (&scene.camera, camera.projection())
+        {
+            scene.camera = Some(CameraDescription {
+                look_from: position.into(),
+                look_at: (position + forward).into(),
+                fov: perspective.yfov().to_degrees(),
+                aperture: 0.0,
+                // the camera can be rolled
+                up: transform.transform_vector3(glam::Vec3::Y).into(),
+                ..CameraDescription::default()
+            });
+        }
+    }
+
+    if let Some(light) = node.light() {
+        let color = Vec3A::from(light.color()) * light.intensity() * opt.light_scale;
+        match light.kind() {
+            // placed once the size of the scene is known
+            Kind::Directional => suns.push((-forward, color)),
+            Kind::Point | Kind::Spot { .. } => {
+                // a sphere shines in every direction
+                if let Kind::Spot { .. } = light.kind() {
+                    scene.warnings.push(format!(
+                        "spot light {} is imported as a point light, its cones are ignored",
+                        light.index()
+                    ));
+                }
+                // the radiance of a sphere with the intensity of the light
+                let radius = opt.light_radius;
+                let radiance = to_color((color / (PI * radius * radius)).into());
+                scene.hitables.push(Sphere {
+                    position,
+                    radius,
+                    mat: Material::diffuse_light(Texture::constant_color(radiance)),
+                });
+            }
+        }
+    }
+
+    for child in node.children() {
+        add_node(&child, transform, meshes, opt, scene, suns);
+    }
+}
+
+// Adds the suns of the directional lights, given by the direction towards them and their
+// illuminance, around the bounding box of `hitables`
+fn add_suns(suns: &[(Vec3A, Vec3A)], hitables: &mut HitableStore) {
+    let (center, radius) = match hitables.bounding_box() {
+        Some(aabb) => (aabb.center(), aabb.radius().max(f32::EPSILON)),
+        None => (Vec3A::ZERO, 1.0),
+    };
+    let distance = radius * SUN_DISTANCE;
+    let solid_angle = PI * SUN_ANGULAR_RADIUS * SUN_ANGULAR_RADIUS;
+    for &(direction, color) in suns {
+        let radiance = to_color((color / solid_angle).into());
+        hitables.push(Sphere {
+            position: center + direction * distance,
+            radius: distance * SUN_ANGULAR_RADIUS,
+            mat: Material::diffuse_light(Texture::constant_color(radiance)),
+        });
+    }
+}
+
+// A triangle mesh for every primitive, None when the mesh has no triangles
+fn load_mesh(
+    mesh: &gltf::Mesh,
+    buffers: &[Vec<u8>],
+    images: &[DynamicImage],
+    materials: &[Arc<Material>],
+) -> Result<Option<Arc<dyn Hitable + Send + Sync>>, RenderError> {
+    let default_mat: Arc<Material> = Arc::new(Material::lambertian(Texture::constant_color(
+        Color::new(0.6, 0.6, 0.6),
+    )));
+    let mut store = HitableStore::new();
+    // points, lines and triangle strips or fans are skipped
+    for primitive in mesh.primitives().filter(|p| p.mode() == Mode::Triangles) {
+        let reader = primitive.reader(|buffer| buffers.get(buffer.index()).map(Vec::as_slice));
+        let positions: Vec<Vec3A> = match reader.read_positions() {
+            Some(positions) => positions.map(Vec3A::from).collect(),
+            None => continue,
+        };
+        let indices: Vec<u32> = match reader.read_indices() {
+            Some(indices) => indices.into_u32().collect(),
+            None => (0..positions.len() as u32).collect(),
+        };
+        let mesh_error = |what: &str| RenderError {
+            kind: String::from("gltf"),
+            message: format!("mesh {} has {}", mesh.index(), what),
+        };
+        if indices.iter().any(|&i| i as usize >= positions.len()) {
+            return Err(mesh_error("indices out of bounds"));
+        }
+        let faces: Vec<[u32; 3]> = indices
+            .chunks_exact(3)
+            .map(|f| [f[0], f[1], f[2]])
+            .collect();
+        if faces.is_empty() {
+            continue;
+        }
+
+        let material = match primitive.material().index() {
+            Some(index) => Arc::clone(&materials[index]),
+            None => Arc::clone(&default_mat),
+        };
+        let mut triangles = TriangleMesh::new(positions, faces, material);
+        if let Some(normals) = reader.read_normals() {
+            triangles = triangles
+                .with_normals(normals.map(Vec3A::from).collect())
+                .map_err(|_| mesh_error("more or fewer normals than positions"))?;
+        }
+        // the v of gltf goes down the image
+        if let Some(uvs) = reader.read_tex_coords(0) {
+            let uvs = uvs.into_f32().map(|[u, v]| Vec2::new(u, 1.0 - v)).collect();
+            triangles = triangles
+                .with_uvs(uvs)
+                .map_err(|_| mesh_error("more or fewer uvs than positions"))?;
+            if let Some(normal_texture) = primitive.material().normal_texture() {
+                let image = &images[normal_texture.texture().source().index()];
+                triangles = triangles.with_normal_map(Bitmap::new(image.clone()));
+            }
+        }
+        store.push(triangles);
+    }
+
+    Ok(match store.len() {
+        0 => None,
+        1 => Some(Arc::from(store.remove(0))),
+        _ => Some(Arc::new(BvhTree::new(store))),
+    })
+}
+
+fn to_material(material: &gltf::Material, images: &[DynamicImage]) -> Material {
+    // the textures are multiplied by the factors of the material
+    let texture = |info: Option<gltf::texture::Info>, factor: Color| match info {
+        Some(info) => Texture::Bitmap(Bitmap {
+            bitmap: images[info.texture().source().index()].clone(),
+            factor,
+        }),
+        None => Texture::constant_color(factor),
+    };
+
+    let emissive =
+        Vec3A::from(material.emissive_factor()) * material.emissive_strength().unwrap_or(1.0);
+    if emissive.max_element() > 0.0 {
+        let emit = texture(material.emissive_texture(), to_color(emissive.into()));
+        return Material::diffuse_light(emit);
+    }
+
+    let pbr = material.pbr_metallic_roughness();
+    let [red, green, blue, alpha] = pbr.base_color_factor();
+    let transmission = material
+        .transmission()
+        .map_or(0.0, |t| t.transmission_factor());
+    let base_color = texture(pbr.base_color_texture(), Color::new(red, green, blue));
+    // the base color tints the transmitted light. The roughness defaults to 1 in glTF, so
+    // it only frosts the glass of the materials that are transmissive, not just blended.
+    if transmission > 0.0 || (material.alpha_mode() == AlphaMode::Blend && alpha < 1.0) {
+        let roughness = if transmission > 0.0 {
+            pbr.roughness_factor()
+        } else {
+            0.0
+        };
+        return Material::Dielectric(
+            Dielectric::new(material.ior().unwrap_or(1.5))
+                .with_tint(base_color)
+                .with_roughness(roughness),
+        );
+    }
+
+    // roughness is in the green channel of the texture and metallic in the blue one
+    let metallic_roughness = pbr
+        .metallic_roughness_texture()
+        .map(|info| &images[info.texture().source().index()]);
+    let scalar = |channel: usize, factor: f32| match metallic_roughness {
+        Some(image) => Texture::Bitmap(Bitmap::new(channel_image(image, channel, factor))),
+        None => Texture::constant_color(Color::new(factor, factor, factor)),
+    };
+    // the reflectance at normal incidence of the ior, 0.5 is the 4% of an ior of 1.5
+    let ior = material.ior().unwrap_or(1.5);
+    let specular = ((ior - 1.0) / (ior + 1.0)).powi(2) / 0.08;
+    Material::microfacet(
+        base_color,
+        scalar(2, pbr.metallic_factor()),
+        scalar(1, pbr.roughness_factor()),
+        Texture::constant_color(Color::new(specular, specular, specular)),
+    )
+}
+
+// A grey image of one channel of `image`, scaled by `factor`
+fn channel_image(image: &DynamicImage, channel: usize, factor: f32) -> DynamicImage {
+    let rgb = image.to_rgb8();
+    DynamicImage::ImageLuma8(GrayImage::from_fn(rgb.width(), rgb.height(), |x, y| {
+        Luma([(rgb.get_pixel(x, y)[channel] as f32 * factor).round() as u8])
+    }))
+}
+
+fn buffer_data(
+    buffer: &gltf::Buffer,
+    blob: Option<&[u8]>,
+    base_dir: &Path,
+) -> Result<Vec<u8>, RenderError> {
+    let data = match buffer.source() {
+        gltf::buffer::Source::Bin => blob.map(<[u8]>::to_vec).ok_or_else(|| RenderError {
+            kind: String::from("gltf"),
+            message: String::from("the binary chunk of the glb is missing"),
+        })?,
+        gltf::buffer::Source::Uri(uri) => read_uri(uri, base_dir)?,
+    };
+    // the accessors and the views are read within the declared length
+    if data.len() < buffer.length() {
+        return Err(RenderError {
+            kind: String::from("gltf"),
+            message: format!(
+                "buffer {} has {} bytes instead of {}",
+                buffer.index(),
+                data.len(),
+                buffer.length()
+            ),
+        });
+    }
+    Ok(data)
+}
+
+fn image_data(
+    image: &gltf::Image,
+    buffers: &[Vec<u8>],
+    base_dir: &Path,
+) -> Result<DynamicImage, RenderError> {
+    let bytes = match image.source() {
+        gltf::image::Source::View { view, .. } => buffers
+            .get(view.buffer().index())
+            .and_then(|buffer| buffer.get(view.offset()..view.offset() + view.length()))
+            .ok_or_else(|| RenderError {
+                kind: String::from("gltf"),
+                message: format!("the view of image {} is out of its buffer", image.index()),
+            })?
+            .to_vec(),
+        gltf::image::Source::Uri { uri, .. } => read_uri(uri, base_dir)?,
+    };
+    Ok(image::load_from_memory(&bytes)?)
+}
+
+// The content of a base64 data uri or of a file relative to the gltf file
+fn read_uri(uri: &str, base_dir: &Path) -> Result<Vec<u8>, RenderError> {
+    if let Some(data) = uri.strip_prefix("data:") {
+        let encoded = data.split_once(";base64,").map(|(_, encoded)| encoded);
+        return encoded
+            .and_then(|encoded| {
+                base64::engine::general_purpose::STANDARD
+                    .decode(encoded)
+                    .ok()
+            })
+            .ok_or_else(|| RenderError {
+                kind: String::from("gltf"),
+                message: String::from("only base64 data uris are supported"),
+            });
+    }
+    let path = base_dir.join(uri.replace("%20", " "));
+    fs::read(&path).map_err(|error| RenderError {
+        kind: String::from("io"),
+        message: format!("{}: {}", path.display(), error),
+    })
+}
+
+fn to_color(c: [f32; 3]) -> Color {
+    Color::new(c[0], c[1], c[2])
+}
+
+#[test]
+fn test_load_gltf_boxes() {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("app/assets/boxes.gltf");
+    let scene = load_gltf(&path, &GltfOpt::default()).unwrap();
+    assert!(scene.camera.is_some());
+    assert!(scene
+        .hitables
+        .iter()
+        .any(|h| h.material().is_some_and(Material::is_emitter)));
+    assert!(load_gltf(Path::new("missing.gltf"), &GltfOpt::default()).is_err());
+}
+
+#[test]
+fn test_load_gltf_rejects_mismatched_normals() {
+    // a triangle with three positions but only two normals
+    let floats: [f32; 15] = [0., 0., 0., 1., 0., 0., 0., 1., 0., 0., 0., 1., 0., 0., 1.];
+    let bytes: Vec<u8> = floats.iter().flat_map(|f| f.to_le_bytes()).collect();
+    let uri = base64::engine::general_purpose::STANDARD.encode(&bytes);
+    let gltf = format!(
+        r#"{{
+            "asset": {{"version": "2.0"}},
+            "scenes": [{{"nodes": [0]}}],
+            "nodes": [{{"mesh": 0}}],
+            "meshes": [{{"primitives": [{{"attributes": {{"POSITION": 0, "NORMAL": 1}}}}]}}],
+            "buffers": [{{"byteLength": 60, "uri": "data:application/octet-stream;base64,{uri}"}}],
+            "bufferViews": [
+                {{"buffer": 0, "byteOffset": 0, "byteLength": 36}},
+                {{"buffer": 0, "byteOffset": 36, "byteLength": 24}}
+            ],
+            "accessors": [
+                {{"bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3",
+                  "min": [0, 0, 0], "max": [1, 1, 0]}},
+                {{"bufferView": 1, "componentType": 5126, "count": 2, "type": "VEC3"}}
+            ]
+        }}"#
+    );
+    let error = load_error("normals.gltf", &gltf);
+    assert!(error.message.contains("normals"), "{}", error.message);
+}
+
+#[test]
+fn test_load_gltf_checks_the_buffer_lengths() {
+    let uri = base64::engine::general_purpose::STANDARD.encode([0u8; 16]);
+    // the buffer declares more bytes than its uri has
+    let short_buffer = format!(
+        r#"{{
+            "asset": {{"version": "2.0"}},
+            "buffers": [{{"byteLength": 32, "uri": "data:application/octet-stream;base64,{uri}"}}]
+        }}"#
+    );
+    let error = load_error("short_buffer.gltf", &short_buffer);
+    assert!(error.message.contains("16 bytes"), "{}", error.message);
+
+    // the view of the image ends after the buffer
+    let image_view = format!(
+        r#"{{
+            "asset": {{"version": "2.0"}},
+            "buffers": [{{"byteLength": 16, "uri": "data:application/octet-stream;base64,{uri}"}}],
+            "bufferViews": [{{"buffer": 0, "byteOffset": 8, "byteLength": 16}}],
+            "images": [{{"bufferView": 0, "mimeType": "image/png"}}]
+        }}"#
+    );
+    let error = load_error("image_view.gltf", &image_view);
+    assert!(error.message.contains("image 0"), "{}", error.message);
+}
+
+#[test]
+fn test_gltf_textures_are_multiplied_by_the_factors() {
+    let gltf = gltf::Gltf::from_slice(
+        br#"{
+            "asset": {"version": "2.0"},
+            "extensionsUsed": ["KHR_materials_emissive_strength"],
+            "images": [{"uri": "white.png"}],
+            "textures": [{"source": 0}],
+            "materials": [
+                {"emissiveFactor": [1, 0.5, 0], "emissiveTexture": {"index": 0},
+                 "extensions": {"KHR_materials_emissive_strength": {"emissiveStrength": 4}}},
+                {"pbrMetallicRoughness":
+                    {"baseColorFactor": [0.5, 0.25, 1, 1], "baseColorTexture": {"index": 0}}}
+            ]
+        }"#,
+    )
+    .unwrap();
+    let white = DynamicImage::ImageRgb8(image::RgbImage::from_pixel(1, 1, image::Rgb([255; 3])));
+    let materials: Vec<Material> = gltf
+        .materials()
+        .map(|material| to_material(&material, &[white.clone()]))
+        .collect();
+
+    let emitted = match &materials[0] {
+        Material::DiffuseLight(light) => light.albedo.value(0.5, 0.5, Vec3A::ZERO),
+        other => panic!("{:?} is not a light", other),
+    };
+    assert_eq!(emitted, Color::new(4.0, 2.0, 0.0));
+    let base_color = match &materials[1] {
+        Material::Microfacet(microfacet) => microfacet.base_color.value(0.5, 0.5, Vec3A::ZERO),
+        other => panic!("{:?} is not a microfacet", other),
+    };
+    assert_eq!(base_color, Color::new(0.5, 0.25, 1.0));
+}
+
+#[test]
+fn test_gltf_spot_lights_are_reported() {
+    let gltf = r#"{
+        "asset": {"version": "2.0"},
+        "extensionsUsed": ["KHR_lights_punctual"],
+        "extensions": {"KHR_lights_punctual": {"lights": [
+            {"type": "point", "intensity": 10},
+            {"type": "spot", "intensity": 10, "spot": {"outerConeAngle": 0.5}}
+        ]}},
+        "scenes": [{"nodes": [0, 1]}],
+        "nodes": [
+            {"extensions": {"KHR_lights_punctual": {"light": 0}}},
+            {"extensions": {"KHR_lights_punctual": {"light": 1}}}
+        ]
+    }"#;
+    let scene = load_gltf(&write_gltf("spot.gltf", gltf), &GltfOpt::default()).unwrap();
+    assert_eq!(scene.hitables.len(), 2);
+    assert_eq!(scene.warnings.len(), 1);
+    let warning = &scene.warnings[0];
+    assert!(warning.contains("spot light 1"), "{}", warning);
+}
+
+#[test]
+fn test_gltf_suns_are_placed_around_the_scene() {
+    // a point light far from the origin and a sun above it, towards +z
+    let gltf = r#"{
+        "asset": {"version": "2.0"},
+        "extensionsUsed": ["KHR_lights_punctual"],
+        "extensions": {"KHR_lights_punctual": {"lights": [
+            {"type": "directional", "intensity": 3},
+            {"type": "point", "intensity": 10}
+        ]}},
+        "scenes": [{"nodes": [0, 1]}],
+        "nodes": [
+            {"extensions": {"KHR_lights_punctual": {"light": 0}}},
+            {"translation": [5000, 0, 0], "extensions": {"KHR_lights_punctual": {"light": 1}}}
+        ]
+    }"#;
+    let scene = load_gltf(&write_gltf("sun.gltf", gltf), &GltfOpt::default()).unwrap();
+    assert_eq!(scene.hitables.len(), 2);
+    let bounds = scene.hitables[0].bounding_box().unwrap();
+    let sun = scene.hitables[1].bounding_box().unwrap();
+    let distance = bounds.radius() * SUN_DISTANCE;
+    let expected = bounds.center() + Vec3A::Z * distance;
+    assert!(sun.center().distance(expected) < 1e-3, "{}", sun.center());
+    // as wide in the sky at any distance, z is the precise axis this far from the origin
+    let angular_radius = (sun.max.z - sun.min.z) * 0.5 / distance;
+    assert!((angular_radius - SUN_ANGULAR_RADIUS).abs() < 1e-6);
+}
+
+// Saves `gltf` as `name` in a temporary directory
+#[cfg(test)]
+fn write_gltf(name: &str, gltf: &str) -> std::path::PathBuf {
+    let dir = std::env::temp_dir().join("tuot_gltf_test");
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join(name), gltf).unwrap();
+    dir.join(name)
+}
+
+// The error of loading `gltf`, which has to be a glTF one
+#[cfg(test)]
+fn load_error(name: &str, gltf: &str) -> RenderError {
+    match load_gltf(&write_gltf(name, gltf), &GltfOpt::default()) {
+        Ok(_) => panic!("{} was loaded", name),
+        Err(error) => {
+            assert_eq!(error.kind, "gltf", "{}", error.message);
+            error
+        }
+    }
+}
@@ -1,8 +1,11 @@
 use std::fmt;
 use crate::aabb::{surrounding_box, Aabb};
+use crate::color::Color;
 use crate::material::Material;
 use crate::ray::Ray;
+use crate::utils::{pdf_towards_sphere, random_towards_sphere, random_unit_vector};
 use glam::Vec3A;
+use rand::rngs::SmallRng;
 // TODO, Material should be an Arc, as when we load the material from
 // an obj, the same material is shared in a safe thread mode between more triangles
 
@@ -10,10 +13,15 @@ use glam::Vec3A;
 pub struct HitRecord<'material> {
     pub t: f32,
     pub pos: Vec3A,
+    // the shading normal, interpolated on smooth triangles
     pub normal: Vec3A,
+    // the normal of the actual surface, it tells on which side of it the ray is
+    pub geometric_normal: Vec3A,
     pub mat: &'material Material,
     pub u: f32,
     pub v: f32,
+    // the interpolated color of the vertices, on meshes that have one
+    pub vertex_color: Option<Color>,
 }
 
 pub struct HitableStore(Vec<Box<dyn Hitable + Send + Sync>>);
@@ -21,6 +29,30 @@ pub struct HitableStore(Vec<Box<dyn Hitable + Send + Sync>>);
 pub trait Hitable: Sync {
     fn hit(&self, r: &Ray, t0: f32, t1: f32) -> Option<HitRecord>;
     fn bounding_box(&self) -> Option<Aabb>;
+
+    // The material of a primitive. Scene uses it to collect the emitters that are sampled
+    // directly, so every primitive carrying a material should return it.
+    fn material(&self) -> Option<&Material> {
+        None
+    }
+
+    // Solid angle pdf of `random` choosing `direction` from `origin`.
+    // By default directions are sampled in the cone subtended by the sphere
+    // enclosing the bounding box, primitives can override both methods with a tighter sampling.
+    fn pdf_value(&self, origin: Vec3A, direction: Vec3A) -> f32 {
+        match self.bounding_box() {
+            Some(aabb) => pdf_towards_sphere(origin, direction, aabb.center(), aabb.radius()),
+            None => 0.0,
+        }
+    }
+
+    // A random direction from `origin` towards the primitive.
+    fn random(&self, origin: Vec3A, rng: &mut SmallRng) -> Vec3A {
+        match self.bounding_box() {
+            Some(aabb) => random_towards_sphere(rng, origin, aabb.center(), aabb.radius()),
+            None => random_unit_vector(rng),
+        }
+    }
 }
 
 impl fmt::Debug for dyn Hitable {
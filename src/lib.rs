@@ -1,24 +1,43 @@
 //Tlön, Uqbar, Orbis Tertius
 
 pub mod aabb;
+pub mod animation;
 pub mod bvh;
 pub mod camera;
 pub mod camera_effects;
 pub mod color;
+pub mod cone;
+pub mod cuboid;
+pub mod cylinder;
 pub mod disc;
 pub mod examples;
+pub mod framebuffer;
+pub mod framing;
+pub mod gltf_import;
 pub mod hitable;
 pub mod material;
+pub mod mesh_import;
 pub mod montecarlo_pimped;
+pub mod progressive;
+pub mod quad;
 pub mod ray;
 pub mod rect;
 pub mod render_error;
+pub mod render_settings;
 pub mod renderer;
 pub mod scene;
+pub mod scene_description;
 pub mod sdf;
 pub mod sdfa;
 pub mod setup;
+pub mod spectrum;
 pub mod sphere;
+#[cfg(test)]
+mod test_utils;
 pub mod triangle;
+pub mod triangle_mesh;
 pub mod texture;
+pub mod tone_mapping;
+pub mod torus;
+pub mod transform;
 pub mod utils;
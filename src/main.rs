@@ -0,0 +1,400 @@
+// Headless renderer, renders an example world or a scene file to disk.
+//
+//   cargo run --release -- --world cornell-box --samples 64 -o cornell.png
+//   cargo run --release -- --scene app/assets/scenes/sdf_spheres.ron --format exr
+//   cargo run --release -- --scene animated.ron --frame 0 --last-frame 24 --output-dir renderings
+
+use clap::{Parser, ValueEnum};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use tuot::camera_effects::CameraEffects;
+use tuot::animation::{render_sequence, Animation, SequenceOpt};
+use tuot::color::Color;
+use tuot::examples::{example_camera, example_world, Worlds};
+use tuot::framing::{add_key_light, frame_camera_description};
+use tuot::gltf_import::{is_gltf, load_gltf, GltfOpt};
+use tuot::hitable::Hitable;
+use tuot::mesh_import::load_mesh_to_hitable;
+use tuot::montecarlo_pimped::deflection_opt::DeflectionForce;
+use tuot::montecarlo_pimped::tint_opt::BandOp;
+use tuot::progressive::ProgressiveRenderer;
+use tuot::render_error::RenderError;
+use tuot::render_settings::{RenderEngine, RenderSettings};
+use tuot::scene::Scene;
+use tuot::setup::BLACK;
+use tuot::scene_description::{CameraDescription, SceneDescription};
+use tuot::tone_mapping::{ToneMapOperator, ToneMappingOpt, TransferCurve};
+
+#[derive(Parser)]
+#[command(name = "tuot", about = "Renders a world of T.U.O.T. to an image file")]
+struct Args {
+    /// Example world to render
+    #[arg(long, value_enum, default_value_t = WorldArg::CornellBox, conflicts_with = "scene")]
+    world: WorldArg,
+    /// RON or JSON scene file, or a glTF, OBJ, PLY or STL model, to render instead of an
+    /// example world. The models without a camera are framed automatically.
+    #[arg(long)]
+    scene: Option<PathBuf>,
+
+    #[arg(long, default_value_t = 640)]
+    width: u32,
+    #[arg(long, default_value_t = 400)]
+    height: u32,
+    /// Samples per pixel
+    #[arg(long, default_value_t = 16)]
+    samples: usize,
+    #[arg(long, default_value_t = 8)]
+    max_depth: usize,
+    #[arg(long, default_value_t = 0)]
+    seed: u64,
+    /// Frame of the animation, every frame has its own noise
+    #[arg(long, default_value_t = 0)]
+    frame: u32,
+    /// Renders the frames from --frame to this one into a numbered image sequence
+    #[arg(long)]
+    last_frame: Option<u32>,
+    /// Folder of the image sequence
+    #[arg(long, default_value = "renderings")]
+    output_dir: PathBuf,
+    #[arg(long, value_enum, default_value_t = EngineArg::Montecarlo)]
+    engine: EngineArg,
+
+    /// Vertical field of view of the example worlds, scene files have their own camera
+    #[arg(long, default_value_t = 40.0)]
+    fov: f32,
+    #[arg(long, default_value_t = 0.1)]
+    aperture: f32,
+
+    /// Camera distorter, used by the pimped engine
+    #[arg(long)]
+    distorter: bool,
+    #[arg(long)]
+    distorter_noise_scale: Option<f32>,
+    #[arg(long)]
+    distorter_amplitude: Option<f32>,
+    #[arg(long)]
+    distorter_easing: Option<f32>,
+
+    #[arg(long)]
+    deflection_amplitude: Option<f32>,
+    #[arg(long)]
+    deflection_randomness: Option<f32>,
+    #[arg(long, value_enum)]
+    deflection_force: Option<ForceArg>,
+
+    /// Tint colors as r,g,b with values between 0 and 1
+    #[arg(long, value_parser = parse_color)]
+    tint_normal_color: Option<Color>,
+    #[arg(long, value_parser = parse_color)]
+    tint_background_color: Option<Color>,
+    #[arg(long)]
+    tint_mix: Option<f32>,
+    #[arg(long)]
+    tint_freq: Option<f32>,
+    #[arg(long)]
+    tint_amplitude: Option<f32>,
+    #[arg(long, value_enum)]
+    tint_band_op: Option<BandOpArg>,
+
+    /// Exposure in stops, used by the 8 bit formats
+    #[arg(long, default_value_t = 0.0)]
+    exposure: f32,
+    #[arg(long, value_enum, default_value_t = ToneMapArg::Clamp)]
+    tone_map: ToneMapArg,
+    #[arg(long, value_enum, default_value_t = TransferArg::Gamma)]
+    transfer: TransferArg,
+
+    /// Output file
+    #[arg(short, long, default_value = "render.png")]
+    output: PathBuf,
+    /// Output format, when missing it is given by the extension of the output file
+    #[arg(long, value_enum)]
+    format: Option<FormatArg>,
+    /// Do not print the progress
+    #[arg(short, long)]
+    quiet: bool,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum WorldArg {
+    CornellBox,
+    Random,
+    RandomGlass,
+    VerticalWall,
+    ThreeSpheres,
+    SdfSpheres,
+    SdfWall,
+    AreaLight,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum EngineArg {
+    Montecarlo,
+    Pimped,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum ForceArg {
+    CameraRay,
+    ObjectToCamera,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum BandOpArg {
+    Sin,
+    Fract,
+    Mod,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum ToneMapArg {
+    Clamp,
+    Reinhard,
+    Aces,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum TransferArg {
+    Gamma,
+    Srgb,
+    Linear,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum FormatArg {
+    Png,
+    Jpg,
+    Exr,
+    Hdr,
+}
+
+fn main() -> ExitCode {
+    let args = Args::parse();
+    match run(&args) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("{}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn is_scene_file(path: &Path) -> bool {
+    matches!(
+        path.extension().and_then(|ext| ext.to_str()),
+        Some("ron") | Some("json")
+    )
+}
+
+fn run(args: &Args) -> Result<(), RenderError> {
+    let settings = render_settings(args);
+    let (world, camera, background, animation) = match &args.scene {
+        Some(path) if !is_scene_file(path) => {
+            let (mut world, camera) = if is_gltf(path) {
+                let gltf = load_gltf(path, &GltfOpt::default())?;
+                for warning in &gltf.warnings {
+                    eprintln!("{}", warning);
+                }
+                (gltf.hitables, gltf.camera)
+            } else {
+                (load_mesh_to_hitable(path)?, None)
+            };
+            let aspect = args.width as f32 / args.height as f32;
+            let camera = match (camera, world.bounding_box()) {
+                (Some(camera), _) => camera,
+                (None, Some(aabb)) => {
+                    frame_camera_description(&aabb, args.fov, aspect, args.aperture)
+                }
+                // nothing to frame, the origin is seen from the front
+                (None, None) => CameraDescription {
+                    fov: args.fov,
+                    aperture: args.aperture,
+                    ..CameraDescription::default()
+                },
+            };
+            add_key_light(&mut world);
+            (world, camera, BLACK, Animation::default())
+        }
+        Some(path) => {
+            let description = SceneDescription::load(path)?;
+            let base_dir = path.parent().unwrap_or_else(|| Path::new(""));
+            let (world, background) = description.build_world(base_dir)?;
+            let animation = description.animation.unwrap_or_default();
+            (world, description.camera, background, animation)
+        }
+        None => {
+            let w = world(args.world);
+            let (world, background) = example_world(&w, args.seed);
+            let camera = example_camera(&w, args.fov, args.aperture);
+            (world, camera, background, Animation::default())
+        }
+    };
+    let tone_mapping = tone_mapping(args);
+    let scene = Scene::new(
+        world,
+        animation
+            .camera_at(args.frame, &camera)
+            .to_camera(args.width, args.height),
+        background,
+    );
+
+    if let Some(last_frame) = args.last_frame {
+        let sequence = SequenceOpt {
+            first_frame: args.frame,
+            last_frame,
+            folder: args.output_dir.clone(),
+            extension: String::from(args.format.map_or("png", extension)),
+            tone_mapping,
+        };
+        return render_sequence(
+            &sequence,
+            &settings,
+            &animation,
+            &scene,
+            &camera,
+            |frame, _| {
+                if !args.quiet {
+                    eprintln!("saved frame {} of {}", frame, last_frame);
+                }
+                true
+            },
+        );
+    }
+
+    let settings = animation.settings_at(args.frame, &settings);
+
+    let mut renderer = ProgressiveRenderer::new(settings);
+    let framebuffer = renderer.render(&scene, |progress, _| {
+        if !args.quiet {
+            eprint!("\rrendering {:3.0}%", progress.fraction() * 100.0);
+            let _ = std::io::stderr().flush();
+        }
+    })?;
+    if !args.quiet {
+        eprintln!();
+    }
+
+    let output = match args.format {
+        Some(format) => args.output.with_extension(extension(format)),
+        None => args.output.clone(),
+    };
+    framebuffer.save_tone_mapped(Path::new(&output), &tone_mapping)?;
+    if !args.quiet {
+        eprintln!("saved {}", output.display());
+    }
+    Ok(())
+}
+
+fn tone_mapping(args: &Args) -> ToneMappingOpt {
+    ToneMappingOpt {
+        exposure: args.exposure,
+        operator: match args.tone_map {
+            ToneMapArg::Clamp => ToneMapOperator::Clamp,
+            ToneMapArg::Reinhard => ToneMapOperator::Reinhard,
+            ToneMapArg::Aces => ToneMapOperator::Aces,
+        },
+        transfer: match args.transfer {
+            TransferArg::Gamma => TransferCurve::Gamma(2.2),
+            TransferArg::Srgb => TransferCurve::Srgb,
+            TransferArg::Linear => TransferCurve::Linear,
+        },
+        ..ToneMappingOpt::default()
+    }
+}
+
+fn render_settings(args: &Args) -> RenderSettings {
+    let mut settings = RenderSettings {
+        width: args.width,
+        height: args.height,
+        max_depth: args.max_depth,
+        samples: args.samples,
+        seed: args.seed,
+        current_frame: args.frame,
+        engine: match args.engine {
+            EngineArg::Montecarlo => RenderEngine::MonteCarlo,
+            EngineArg::Pimped => RenderEngine::MonteCarloPimped,
+        },
+        camera_effects: if args.distorter {
+            CameraEffects::Distorter
+        } else {
+            CameraEffects::NoEffects
+        },
+        ..RenderSettings::default()
+    };
+
+    let distorter = &mut settings.camera_distorter_opt;
+    set(&mut distorter.noise_scale, args.distorter_noise_scale);
+    set(&mut distorter.amplitude, args.distorter_amplitude);
+    set(&mut distorter.easing, args.distorter_easing);
+
+    let deflection = &mut settings.deflection_opt;
+    set(&mut deflection.amplitude, args.deflection_amplitude);
+    set(&mut deflection.randomness, args.deflection_randomness);
+    set(
+        &mut deflection.force,
+        args.deflection_force.map(|force| match force {
+            ForceArg::CameraRay => DeflectionForce::CameraRay,
+            ForceArg::ObjectToCamera => DeflectionForce::ObjectToCamera,
+        }),
+    );
+
+    let tint = &mut settings.tint_opt;
+    set(&mut tint.normal_color, args.tint_normal_color);
+    set(&mut tint.background_color, args.tint_background_color);
+    set(&mut tint.mix, args.tint_mix);
+    set(&mut tint.freq, args.tint_freq);
+    set(&mut tint.amplitude, args.tint_amplitude);
+    set(
+        &mut tint.band_op,
+        args.tint_band_op.map(|op| match op {
+            BandOpArg::Sin => BandOp::Sin,
+            BandOpArg::Fract => BandOp::Fract,
+            BandOpArg::Mod => BandOp::Mod,
+        }),
+    );
+    settings
+}
+
+// keeps the default of the option when the flag is missing
+fn set<T>(option: &mut T, value: Option<T>) {
+    if let Some(value) = value {
+        *option = value;
+    }
+}
+
+fn world(world: WorldArg) -> Worlds {
+    match world {
+        WorldArg::CornellBox => Worlds::CornellBox,
+        WorldArg::Random => Worlds::Random,
+        WorldArg::RandomGlass => Worlds::RandomGlass,
+        WorldArg::VerticalWall => Worlds::VerticalWall,
+        WorldArg::ThreeSpheres => Worlds::ThreeSphere,
+        WorldArg::SdfSpheres => Worlds::SdfSpheres,
+        WorldArg::SdfWall => Worlds::SdfWall,
+        WorldArg::AreaLight => Worlds::SimpleAreaLight,
+    }
+}
+
+fn extension(format: FormatArg) -> &'static str {
+    match format {
+        FormatArg::Png => "png",
+        FormatArg::Jpg => "jpg",
+        FormatArg::Exr => "exr",
+        FormatArg::Hdr => "hdr",
+    }
+}
+
+fn parse_color(value: &str) -> Result<Color, String> {
+    let channels = value
+        .split(',')
+        .map(|channel| channel.trim().parse::<f32>())
+        .collect::<Result<Vec<f32>, _>>()
+        .map_err(|e| e.to_string())?;
+    match channels[..] {
+        [red, green, blue] => Ok(Color::new(red, green, blue)),
+        _ => Err(String::from(
+            "expected three comma separated values, like 1.0,0.5,0.0",
+        )),
+    }
+}
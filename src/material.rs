@@ -3,18 +3,55 @@ use crate::hitable::HitRecord;
 use crate::ray::Ray;
 use crate::setup::{BLACK, WHITE};
 use crate::texture::Texture;
-use crate::utils::{near_zero, random_in_unit_sphere, reflect, refract, schlick};
+use crate::utils::{
+    near_zero, random_in_unit_sphere, random_unit_vector, reflect, refract, schlick,
+};
 use glam::Vec3A;
+use rand::rngs::SmallRng;
 use rand::Rng;
+use std::f32::consts::PI;
 
 pub struct Scatter {
     pub color: Color,
     pub ray: Option<Ray>,
 }
 
+// The direction of the next ray of a path, chosen by a material
+pub struct BsdfSample {
+    // where the light comes from, away from the surface and normalized
+    pub direction: Vec3A,
+    // the BSDF times the cosine divided by the pdf, the attenuation of the path
+    pub weight: Color,
+    // the density of `direction` over the solid angle, 0 for the specular lobes
+    pub pdf: f32,
+    // mirrors and glass reflect towards `direction` only, the lights sampled in any
+    // other direction do not reach the viewer through them
+    pub specular: bool,
+}
+
+// The directions `wi`, towards the light, and `wo`, towards the viewer, point away from
+// the surface and are normalized.
 pub trait Scatterable {
-    fn scatter(&self, ray: &Ray, hit_record: &HitRecord) -> Option<Scatter>;
-    fn emitted(&self, _u: f32, _v: f32, _p: Vec3A) -> Color {
+    fn sample(&self, ray: &Ray, hit_record: &HitRecord, rng: &mut SmallRng) -> Option<BsdfSample>;
+
+    // The BSDF times the cosine between `wi` and the normal, black for the specular lobes
+    fn eval(&self, _wi: Vec3A, _wo: Vec3A, _hit_record: &HitRecord) -> Color {
+        BLACK
+    }
+
+    // The density with which `sample` chooses `wi` when the surface is seen from `wo`
+    fn pdf(&self, _wi: Vec3A, _wo: Vec3A, _hit_record: &HitRecord) -> f32 {
+        0.0
+    }
+
+    fn scatter(&self, ray: &Ray, hit_record: &HitRecord, rng: &mut SmallRng) -> Option<Scatter> {
+        self.sample(ray, hit_record, rng).map(|sample| Scatter {
+            color: sample.weight,
+            ray: Some(Ray::new(hit_record.pos, sample.direction)),
+        })
+    }
+
+    fn emitted(&self, _hit_record: &HitRecord) -> Color {
         BLACK
     }
 }
@@ -27,6 +64,9 @@ pub enum Material {
     Dielectric(Dielectric),
     // Texture(Texture),
     DiffuseLight(DiffuseLight),
+    Microfacet(Microfacet),
+    Mix(Mix),
+    Clearcoat(Clearcoat),
 }
 
 impl Material {
@@ -39,29 +79,110 @@ impl Material {
     }
 
     pub fn dielectric(index_of_refraction: f32) -> Material {
-        Material::Dielectric(Dielectric {
-            index_of_refraction,
-        })
+        Material::Dielectric(Dielectric::new(index_of_refraction))
     }
 
     pub fn diffuse_light(albedo: Texture) -> Material {
         Material::DiffuseLight(DiffuseLight { albedo })
     }
+
+    // Metallic-roughness material, like the ones of glTF. `metallic`, `roughness` and
+    // `specular` are read from the red channel of their textures.
+    pub fn microfacet(
+        base_color: Texture,
+        metallic: Texture,
+        roughness: Texture,
+        specular: Texture,
+    ) -> Material {
+        Material::Microfacet(Microfacet {
+            base_color,
+            metallic,
+            roughness,
+            specular,
+        })
+    }
+
+    // `b` where the red channel of `mask` is 1, `a` where it is 0
+    pub fn mix(a: Material, b: Material, mask: Texture) -> Material {
+        Material::Mix(Mix {
+            a: Box::new(a),
+            b: Box::new(b),
+            mask,
+        })
+    }
+
+    pub fn clearcoat(base: Material, index_of_refraction: f32) -> Material {
+        Material::Clearcoat(Clearcoat {
+            base: Box::new(base),
+            index_of_refraction,
+        })
+    }
+
+    pub fn is_emitter(&self) -> bool {
+        match self {
+            Material::DiffuseLight(_) => true,
+            Material::Mix(mix) => mix.a.is_emitter() || mix.b.is_emitter(),
+            Material::Clearcoat(coat) => coat.base.is_emitter(),
+            _ => false,
+        }
+    }
+
+    pub fn is_dispersive(&self) -> bool {
+        match self {
+            Material::Dielectric(d) => d.dispersion.is_some(),
+            Material::Mix(mix) => mix.a.is_dispersive() || mix.b.is_dispersive(),
+            Material::Clearcoat(coat) => coat.base.is_dispersive(),
+            _ => false,
+        }
+    }
+
+    // All the samples are specular, the lights are not sampled at its hits
+    pub fn is_specular(&self) -> bool {
+        match self {
+            Material::Lambertian(_) | Material::Microfacet(_) => false,
+            Material::Mix(mix) => mix.a.is_specular() && mix.b.is_specular(),
+            Material::Clearcoat(coat) => coat.base.is_specular(),
+            _ => true,
+        }
+    }
 }
 
 impl Scatterable for Material {
-    fn scatter(&self, ray: &Ray, hit_record: &HitRecord) -> Option<Scatter> {
+    fn sample(&self, ray: &Ray, hit_record: &HitRecord, rng: &mut SmallRng) -> Option<BsdfSample> {
         match self {
-            Material::Lambertian(l) => l.scatter(ray, hit_record),
-            Material::Metal(m) => m.scatter(ray, hit_record),
-            Material::Dielectric(d) => d.scatter(ray, hit_record),
-            Material::DiffuseLight(l) => l.scatter(ray, hit_record),
+            Material::Lambertian(l) => l.sample(ray, hit_record, rng),
+            Material::Metal(m) => m.sample(ray, hit_record, rng),
+            Material::Dielectric(d) => d.sample(ray, hit_record, rng),
+            Material::DiffuseLight(l) => l.sample(ray, hit_record, rng),
+            Material::Microfacet(m) => m.sample(ray, hit_record, rng),
+            Material::Mix(m) => m.sample(ray, hit_record, rng),
+            Material::Clearcoat(c) => c.sample(ray, hit_record, rng),
         }
     }
-    fn emitted(&self, u: f32, v: f32, p: Vec3A) -> Color {
+    fn eval(&self, wi: Vec3A, wo: Vec3A, hit_record: &HitRecord) -> Color {
+        match self {
+            Material::Lambertian(l) => l.eval(wi, wo, hit_record),
+            Material::Microfacet(m) => m.eval(wi, wo, hit_record),
+            Material::Mix(m) => m.eval(wi, wo, hit_record),
+            Material::Clearcoat(c) => c.eval(wi, wo, hit_record),
+            _ => BLACK,
+        }
+    }
+    fn pdf(&self, wi: Vec3A, wo: Vec3A, hit_record: &HitRecord) -> f32 {
+        match self {
+            Material::Lambertian(l) => l.pdf(wi, wo, hit_record),
+            Material::Microfacet(m) => m.pdf(wi, wo, hit_record),
+            Material::Mix(m) => m.pdf(wi, wo, hit_record),
+            Material::Clearcoat(c) => c.pdf(wi, wo, hit_record),
+            _ => 0.0,
+        }
+    }
+    fn emitted(&self, hit_record: &HitRecord) -> Color {
         match self {
             // just light has an emission value
-            Material::DiffuseLight(dl) => dl.emitted(u, v, p),
+            Material::DiffuseLight(dl) => dl.emitted(hit_record),
+            Material::Mix(m) => m.emitted(hit_record),
+            Material::Clearcoat(c) => c.emitted(hit_record),
             //_ => Color::new(0.0, 0.0, 0.0),
             _ => BLACK,
         }
@@ -81,23 +202,30 @@ impl Lambertian {
 }
 
 impl Scatterable for Lambertian {
-    fn scatter(&self, _ray: &Ray, hit_record: &HitRecord) -> Option<Scatter> {
-        let mut scatter_direction = hit_record.normal + random_in_unit_sphere();
+    fn sample(&self, _ray: &Ray, hit_record: &HitRecord, rng: &mut SmallRng) -> Option<BsdfSample> {
+        // a point on the unit sphere tangent to the surface gives a cosine distribution
+        let mut scatter_direction = hit_record.normal + random_unit_vector(rng);
         // this is because some of the scattered rays hit the object they are reflecting.
         // This is because they start a bit below the hitten surface. In this case, we coerce
         // their direction to the the surface direction
         if near_zero(&scatter_direction) {
             scatter_direction = hit_record.normal;
         }
-        let target = hit_record.pos + scatter_direction;
-        Some(Scatter {
-            color: self
-                .albedo
-                .value(hit_record.u, hit_record.v, hit_record.pos),
-            ray: Some(Ray::new(hit_record.pos, target - hit_record.pos)),
+        let direction = scatter_direction.normalize();
+        Some(BsdfSample {
+            direction,
+            weight: self.albedo.hit_value(hit_record),
+            pdf: direction.dot(hit_record.normal).max(0.0) / PI,
+            specular: false,
         })
     }
-    fn emitted(&self, _u: f32, _v: f32, _p: Vec3A) -> Color {
+    fn eval(&self, wi: Vec3A, _wo: Vec3A, hit_record: &HitRecord) -> Color {
+        self.albedo.hit_value(hit_record) * (wi.dot(hit_record.normal).max(0.0) / PI)
+    }
+    fn pdf(&self, wi: Vec3A, _wo: Vec3A, hit_record: &HitRecord) -> f32 {
+        wi.dot(hit_record.normal).max(0.0) / PI
+    }
+    fn emitted(&self, _hit_record: &HitRecord) -> Color {
         BLACK
     }
 }
@@ -117,84 +245,188 @@ impl Metal {
     }
 }
 
+// The fuzzy reflections have no density that can be evaluated, the metals are specular
+// for the renderer even when they are rough.
 impl Scatterable for Metal {
-    fn scatter(&self, ray: &Ray, hit_record: &HitRecord) -> Option<Scatter> {
+    fn sample(&self, ray: &Ray, hit_record: &HitRecord, rng: &mut SmallRng) -> Option<BsdfSample> {
         let reflected = reflect(&ray.direction, &hit_record.normal);
-        let scattered = Ray::new(
-            hit_record.pos,
-            reflected + random_in_unit_sphere() * self.fuzz,
-        );
-        let attenuation = self
-            .albedo
-            .value(hit_record.u, hit_record.v, hit_record.pos);
-        if scattered.direction.dot(hit_record.normal) > 0.0 {
-            Some(Scatter {
-                color: attenuation,
-                ray: Some(scattered),
+        let direction = (reflected + random_in_unit_sphere(rng) * self.fuzz).try_normalize()?;
+        if direction.dot(hit_record.normal) > 0.0 {
+            Some(BsdfSample {
+                direction,
+                weight: self.albedo.hit_value(hit_record),
+                pdf: 0.0,
+                specular: true,
             })
         } else {
             None
         }
     }
-    fn emitted(&self, _u: f32, _v: f32, _p: Vec3A) -> Color {
+    fn emitted(&self, _hit_record: &HitRecord) -> Color {
         BLACK
     }
 }
 
-#[derive(Debug, Clone, Copy)]
+// Glass and other clear media. The light is multiplied by `tint` every time it goes
+// through the surface and loses exp(-absorption * distance) on its way inside, so a
+// thick piece of tinted glass is darker than a thin one. A `roughness` above zero frosts
+// the surface with GGX microfacets. With a `dispersion` the index of refraction depends
+// on the wavelength of the rays that carry one, the others use `index_of_refraction`.
+#[derive(Debug, Clone)]
 pub struct Dielectric {
     pub index_of_refraction: f32,
+    pub tint: Texture,
+    pub absorption: Color,
+    pub roughness: f32,
+    pub dispersion: Option<Dispersion>,
 }
 
 impl Dielectric {
     pub fn new(index_of_refraction: f32) -> Dielectric {
         Dielectric {
             index_of_refraction,
+            tint: Texture::constant_color(WHITE),
+            absorption: BLACK,
+            roughness: 0.0,
+            dispersion: None,
+        }
+    }
+
+    pub fn with_tint(mut self, tint: Texture) -> Dielectric {
+        self.tint = tint;
+        self
+    }
+
+    pub fn with_absorption(mut self, absorption: Color) -> Dielectric {
+        self.absorption = absorption;
+        self
+    }
+
+    pub fn with_roughness(mut self, roughness: f32) -> Dielectric {
+        self.roughness = roughness;
+        self
+    }
+
+    pub fn with_dispersion(mut self, dispersion: Dispersion) -> Dielectric {
+        self.dispersion = Some(dispersion);
+        self
+    }
+
+    pub fn index_of_refraction_at(&self, wavelength: Option<f32>) -> f32 {
+        match (self.dispersion, wavelength) {
+            (Some(dispersion), Some(wavelength)) => dispersion.index_of_refraction(wavelength),
+            _ => self.index_of_refraction,
+        }
+    }
+}
+
+// The index of refraction as a function of the wavelength, the coefficients are for
+// wavelengths in micrometers like in the optical glass catalogs.
+#[derive(Debug, Clone, Copy)]
+pub enum Dispersion {
+    // n = a + b / λ²
+    Cauchy { a: f32, b: f32 },
+    // n² = 1 + Σ b λ² / (λ² - c)
+    Sellmeier { b: [f32; 3], c: [f32; 3] },
+}
+
+impl Dispersion {
+    // BK7, the common crown glass of lenses and prisms
+    pub const CROWN_GLASS: Dispersion = Dispersion::Sellmeier {
+        b: [1.039_612, 0.231_792_34, 1.010_469_5],
+        c: [0.006_000_7, 0.020_017_9, 103.560_65],
+    };
+    // SF11, a dense flint glass that spreads the colors almost four times as much
+    pub const FLINT_GLASS: Dispersion = Dispersion::Sellmeier {
+        b: [1.737_597, 0.313_747_35, 1.898_781],
+        c: [0.013_188_7, 0.062_306_8, 155.236_3],
+    };
+
+    // `wavelength` is in nanometers
+    pub fn index_of_refraction(&self, wavelength: f32) -> f32 {
+        let micrometers = wavelength / 1000.0;
+        let l2 = micrometers * micrometers;
+        match self {
+            Dispersion::Cauchy { a, b } => a + b / l2,
+            Dispersion::Sellmeier { b, c } => {
+                (1.0 + b.iter().zip(c).map(|(b, c)| b * l2 / (l2 - c)).sum::<f32>()).sqrt()
+            }
         }
     }
 }
 
 impl Scatterable for Dielectric {
-    fn scatter(&self, r_in: &Ray, hit: &HitRecord) -> Option<Scatter> {
-        let mut rng = rand::thread_rng();
-        let outward_normal: Vec3A;
-        let ni_over_nt: f32;
-        let cosine: f32;
-        let attenuation = Texture::constant_color(WHITE);
-
-        if r_in.direction.dot(hit.normal) > 0.0 {
-            outward_normal = -hit.normal;
-            ni_over_nt = self.index_of_refraction;
-            cosine =
-                self.index_of_refraction * r_in.direction.dot(hit.normal) / r_in.direction.length();
+    fn sample(&self, r_in: &Ray, hit: &HitRecord, rng: &mut SmallRng) -> Option<BsdfSample> {
+        let direction = r_in.direction.normalize();
+        let ior = self.index_of_refraction_at(r_in.wavelength);
+        // the side is given by the geometric normal, an interpolated one can face away
+        // from the ray close to the silhouette of a smooth mesh
+        let inside = direction.dot(hit.geometric_normal) > 0.0;
+        let (normal, ni_over_nt) = if inside {
+            (-hit.normal, ior)
         } else {
-            outward_normal = hit.normal;
-            ni_over_nt = 1.0 / self.index_of_refraction;
-            cosine = -r_in.direction.dot(hit.normal) / r_in.direction.length();
-        }
-
-        match refract(r_in.direction, outward_normal, ni_over_nt) {
-            Some(refracted) => {
-                if rng.gen::<f32>() > schlick(cosine, self.index_of_refraction) {
-                    return Some(Scatter {
-                        ray: Some(Ray::new(hit.pos, refracted.normalize())),
-                        color: attenuation.value(hit.u, hit.v, hit.pos),
-                    });
-                }
-            }
-            None => {}
+            (hit.normal, 1.0 / ior)
+        };
+
+        // the ray travelled inside the medium since the previous hit
+        let transmittance = if inside {
+            let distance = hit.t * r_in.direction.length();
+            Color::new(
+                (-self.absorption.red * distance).exp(),
+                (-self.absorption.green * distance).exp(),
+                (-self.absorption.blue * distance).exp(),
+            )
+        } else {
+            WHITE
+        };
+
+        let rough = self.roughness > 0.0;
+        let alpha = (self.roughness * self.roughness).max(MIN_ALPHA);
+        let facet = if rough {
+            sample_ggx_visible_normal(normal, -direction, alpha, rng)
+        } else {
+            normal
+        };
+        let cosine = if inside {
+            -ior * direction.dot(facet)
+        } else {
+            -direction.dot(facet)
+        };
+
+        let (out, weight) = match refract(direction, facet, ni_over_nt) {
+            Some(refracted) if rng.gen::<f32>() > schlick(cosine, ior) => (
+                refracted.normalize(),
+                self.tint.hit_value(hit) * transmittance,
+            ),
+            _ => (reflect(&direction, &facet), transmittance),
+        };
+
+        if !rough {
+            return Some(BsdfSample {
+                direction: out,
+                weight,
+                pdf: 0.0,
+                specular: true,
+            });
         }
 
-        Some(Scatter {
-            color: attenuation.value(hit.u, hit.v, hit.pos),
-            ray: Some(Ray::new(
-                hit.pos,
-                reflect(&r_in.direction.normalize(), &hit.normal),
-            )),
+        // a facet can send the ray back through the macro surface, and the shadowing
+        // of the outgoing direction is what the visible normals did not account for
+        let transmitted = out.dot(normal) < 0.0;
+        let facet_side = out.dot(facet) < 0.0;
+        if transmitted != facet_side {
+            return None;
+        }
+        let shadowing = 1.0 / (1.0 + smith_lambda(out.dot(normal).abs(), alpha * alpha));
+        Some(BsdfSample {
+            direction: out,
+            weight: weight * shadowing,
+            pdf: 0.0,
+            specular: true,
         })
     }
 
-    fn emitted(&self, _u: f32, _v: f32, _p: Vec3A) -> Color {
+    fn emitted(&self, _hit_record: &HitRecord) -> Color {
         BLACK
     }
 }
@@ -211,11 +443,478 @@ impl DiffuseLight {
 }
 
 impl Scatterable for DiffuseLight {
-    fn scatter(&self, _r_in: &Ray, _hit: &HitRecord) -> Option<Scatter> {
+    fn sample(&self, _r_in: &Ray, _hit: &HitRecord, _rng: &mut SmallRng) -> Option<BsdfSample> {
         None
     }
-    fn emitted(&self, u: f32, v: f32, p: Vec3A) -> Color {
+    fn emitted(&self, hit_record: &HitRecord) -> Color {
         //println!("{}",p.x);
-        self.albedo.value(u, v, p)
+        self.albedo.hit_value(hit_record)
+    }
+}
+
+// A GGX microfacet specular lobe over a lambertian one, in the metallic-roughness model.
+// Metals have no diffuse lobe and reflect with the base color. The other materials
+// reflect `0.08 * specular` at normal incidence, 4% with the default 0.5, and the
+// diffuse lobe gets the energy not reflected by the Fresnel term.
+#[derive(Debug, Clone)]
+pub struct Microfacet {
+    pub base_color: Texture,
+    pub metallic: Texture,
+    pub roughness: Texture,
+    pub specular: Texture,
+}
+
+// the smallest alpha, mirrors would have an infinite distribution of the normals
+const MIN_ALPHA: f32 = 1e-3;
+
+// The parameters of a microfacet material at a hit point, the directions are in the
+// frame of the shading normal, facing the side of the incoming ray
+struct MicrofacetHit {
+    base_color: Color,
+    metallic: f32,
+    alpha: f32,
+    f0: Color,
+    normal: Vec3A,
+    view: Vec3A,
+}
+
+impl MicrofacetHit {
+    // Value of the BSDF times the cosine, and the pdf of `sample` choosing `light`
+    fn eval(&self, light: Vec3A, specular_chance: f32) -> Option<(Color, f32)> {
+        let (n_dot_v, n_dot_l) = (self.normal.dot(self.view), self.normal.dot(light));
+        if n_dot_l <= 0.0 || n_dot_v <= 0.0 {
+            return None;
+        }
+        let half = (self.view + light).normalize();
+        let (n_dot_h, v_dot_h) = (self.normal.dot(half), self.view.dot(half).max(0.0));
+        let a2 = self.alpha * self.alpha;
+        let fresnel = fresnel_schlick(self.f0, v_dot_h);
+        let d = ggx_distribution(n_dot_h, a2);
+        let lambda_v = smith_lambda(n_dot_v, a2);
+        let g2 = 1.0 / (1.0 + lambda_v + smith_lambda(n_dot_l, a2));
+
+        let specular = fresnel * (d * g2 / (4.0 * n_dot_v));
+        let diffuse = Color::new(1.0 - fresnel.red, 1.0 - fresnel.green, 1.0 - fresnel.blue)
+            * self.base_color
+            * ((1.0 - self.metallic) * n_dot_l / PI);
+        let specular_pdf = d / (4.0 * n_dot_v * (1.0 + lambda_v));
+        let diffuse_pdf = n_dot_l / PI;
+        let pdf = specular_chance * specular_pdf + (1.0 - specular_chance) * diffuse_pdf;
+        Some((specular + diffuse, pdf))
+    }
+
+    // The probability of sampling the specular lobe instead of the diffuse one, from
+    // their reflectance towards the viewer
+    fn specular_chance(&self) -> f32 {
+        let specular = luminance(fresnel_schlick(self.f0, self.normal.dot(self.view)));
+        let diffuse = (1.0 - self.metallic) * luminance(self.base_color) * (1.0 - specular);
+        if diffuse <= 0.0 {
+            1.0
+        } else {
+            (specular / (specular + diffuse)).clamp(0.1, 0.9)
+        }
+    }
+}
+
+impl Microfacet {
+    pub fn new(
+        base_color: Texture,
+        metallic: Texture,
+        roughness: Texture,
+        specular: Texture,
+    ) -> Microfacet {
+        Microfacet {
+            base_color,
+            metallic,
+            roughness,
+            specular,
+        }
+    }
+
+    fn at(&self, view: Vec3A, hit: &HitRecord) -> MicrofacetHit {
+        let base_color = self.base_color.hit_value(hit);
+        let metallic = self.metallic.scalar_value(hit).clamp(0.0, 1.0);
+        let roughness = self.roughness.scalar_value(hit).clamp(0.0, 1.0);
+        let specular = self.specular.scalar_value(hit).max(0.0) * 0.08;
+        // both sides of the surface reflect
+        let normal = if view.dot(hit.geometric_normal) < 0.0 {
+            -hit.normal
+        } else {
+            hit.normal
+        };
+        MicrofacetHit {
+            base_color,
+            metallic,
+            alpha: (roughness * roughness).max(MIN_ALPHA),
+            f0: Color::new(specular, specular, specular).lerp(base_color, metallic),
+            normal,
+            view,
+        }
+    }
+
+    // The lobes are evaluated when both directions are on the same side of the actual
+    // surface, the interpolated normals could send the light through it
+    fn lobes(&self, wi: Vec3A, wo: Vec3A, hit: &HitRecord) -> Option<(Color, f32)> {
+        if wi.dot(hit.geometric_normal) * wo.dot(hit.geometric_normal) <= 0.0 {
+            return None;
+        }
+        let m = self.at(wo, hit);
+        m.eval(wi, m.specular_chance())
+    }
+}
+
+impl Scatterable for Microfacet {
+    fn sample(&self, ray: &Ray, hit_record: &HitRecord, rng: &mut SmallRng) -> Option<BsdfSample> {
+        let m = self.at(-ray.direction.normalize(), hit_record);
+        if m.normal.dot(m.view) <= 0.0 {
+            return None;
+        }
+        let specular_chance = m.specular_chance();
+        let light = if rng.gen::<f32>() < specular_chance {
+            let half = sample_ggx_visible_normal(m.normal, m.view, m.alpha, rng);
+            reflect(&-m.view, &half)
+        } else {
+            let direction = m.normal + random_unit_vector(rng);
+            if near_zero(&direction) {
+                m.normal
+            } else {
+                direction.normalize()
+            }
+        };
+        // the interpolated normals can send the rays below the surface
+        let below =
+            light.dot(hit_record.geometric_normal) * m.view.dot(hit_record.geometric_normal);
+        if below <= 0.0 {
+            return None;
+        }
+        let (value, pdf) = m.eval(light, specular_chance)?;
+        if pdf <= 0.0 {
+            return None;
+        }
+        Some(BsdfSample {
+            direction: light,
+            weight: value / pdf,
+            pdf,
+            specular: false,
+        })
+    }
+    fn eval(&self, wi: Vec3A, wo: Vec3A, hit_record: &HitRecord) -> Color {
+        self.lobes(wi, wo, hit_record)
+            .map_or(BLACK, |(value, _)| value)
+    }
+    fn pdf(&self, wi: Vec3A, wo: Vec3A, hit_record: &HitRecord) -> f32 {
+        self.lobes(wi, wo, hit_record).map_or(0.0, |(_, pdf)| pdf)
+    }
+}
+
+// Two materials blended by a mask. One of them is sampled, with the chance of its share,
+// and the non specular samples are weighted with the BSDF and the density of the blend,
+// so that the lights sampled at the hit agree with them.
+#[derive(Debug, Clone)]
+pub struct Mix {
+    pub a: Box<Material>,
+    pub b: Box<Material>,
+    pub mask: Texture,
+}
+
+impl Mix {
+    // the share of `b` at the hit
+    fn share(&self, hit_record: &HitRecord) -> f32 {
+        self.mask.hit_value(hit_record).red.clamp(0.0, 1.0)
+    }
+}
+
+impl Scatterable for Mix {
+    fn sample(&self, ray: &Ray, hit_record: &HitRecord, rng: &mut SmallRng) -> Option<BsdfSample> {
+        let layer = if rng.gen::<f32>() < self.share(hit_record) {
+            &self.b
+        } else {
+            &self.a
+        };
+        let sample = layer.sample(ray, hit_record, rng)?;
+        if sample.specular {
+            return Some(sample);
+        }
+        let view = -ray.direction.normalize();
+        let pdf = self.pdf(sample.direction, view, hit_record);
+        if pdf <= 0.0 {
+            return None;
+        }
+        Some(BsdfSample {
+            weight: self.eval(sample.direction, view, hit_record) / pdf,
+            pdf,
+            ..sample
+        })
+    }
+    fn eval(&self, wi: Vec3A, wo: Vec3A, hit_record: &HitRecord) -> Color {
+        let share = self.share(hit_record);
+        self.a.eval(wi, wo, hit_record) * (1.0 - share) + self.b.eval(wi, wo, hit_record) * share
+    }
+    fn pdf(&self, wi: Vec3A, wo: Vec3A, hit_record: &HitRecord) -> f32 {
+        let share = self.share(hit_record);
+        self.a.pdf(wi, wo, hit_record) * (1.0 - share) + self.b.pdf(wi, wo, hit_record) * share
+    }
+    fn emitted(&self, hit_record: &HitRecord) -> Color {
+        let share = self.share(hit_record);
+        self.a.emitted(hit_record) * (1.0 - share) + self.b.emitted(hit_record) * share
+    }
+}
+
+// A smooth dielectric coat, like the varnish of a car paint. The coat reflects with the
+// Fresnel term of its index of refraction, the base gets the light that goes through it
+// on the way in and on the way out. The bounces between the coat and the base are left out.
+#[derive(Debug, Clone)]
+pub struct Clearcoat {
+    pub base: Box<Material>,
+    pub index_of_refraction: f32,
+}
+
+impl Clearcoat {
+    // the reflectance of the coat for a direction leaving the surface
+    pub fn reflectance(&self, direction: Vec3A, hit_record: &HitRecord) -> f32 {
+        let cosine = direction.dot(hit_record.normal).abs().min(1.0);
+        schlick(cosine, self.index_of_refraction)
+    }
+
+    // the coat is on the outer side, the rays inside a transmissive base do not see it
+    fn is_outside(&self, view: Vec3A, hit_record: &HitRecord) -> bool {
+        view.dot(hit_record.geometric_normal) > 0.0
+    }
+}
+
+impl Scatterable for Clearcoat {
+    fn sample(&self, ray: &Ray, hit_record: &HitRecord, rng: &mut SmallRng) -> Option<BsdfSample> {
+        let view = -ray.direction.normalize();
+        if !self.is_outside(view, hit_record) {
+            return self.base.sample(ray, hit_record, rng);
+        }
+        let reflectance = self.reflectance(view, hit_record);
+        if rng.gen::<f32>() < reflectance {
+            return Some(BsdfSample {
+                direction: reflect(&-view, &hit_record.normal),
+                weight: WHITE,
+                pdf: 0.0,
+                specular: true,
+            });
+        }
+        let sample = self.base.sample(ray, hit_record, rng)?;
+        Some(BsdfSample {
+            weight: sample.weight * (1.0 - self.reflectance(sample.direction, hit_record)),
+            pdf: sample.pdf * (1.0 - reflectance),
+            ..sample
+        })
+    }
+    fn eval(&self, wi: Vec3A, wo: Vec3A, hit_record: &HitRecord) -> Color {
+        let base = self.base.eval(wi, wo, hit_record);
+        if !self.is_outside(wo, hit_record) {
+            return base;
+        }
+        let transmitted =
+            (1.0 - self.reflectance(wi, hit_record)) * (1.0 - self.reflectance(wo, hit_record));
+        base * transmitted
+    }
+    fn pdf(&self, wi: Vec3A, wo: Vec3A, hit_record: &HitRecord) -> f32 {
+        let base = self.base.pdf(wi, wo, hit_record);
+        if !self.is_outside(wo, hit_record) {
+            return base;
+        }
+        base * (1.0 - self.reflectance(wo, hit_record))
+    }
+    fn emitted(&self, hit_record: &HitRecord) -> Color {
+        self.base.emitted(hit_record)
+    }
+}
+
+fn fresnel_schlick(f0: Color, cosine: f32) -> Color {
+    let weight = (1.0 - cosine.clamp(0.0, 1.0)).powi(5);
+    f0.lerp(WHITE, weight)
+}
+
+// Trowbridge-Reitz distribution of the normals, `a2` is the squared alpha
+fn ggx_distribution(n_dot_h: f32, a2: f32) -> f32 {
+    let d = n_dot_h * n_dot_h * (a2 - 1.0) + 1.0;
+    a2 / (PI * d * d)
+}
+
+// The Smith shadowing of the GGX distribution is 1 / (1 + lambda)
+fn smith_lambda(cosine: f32, a2: f32) -> f32 {
+    let cos2 = cosine * cosine;
+    ((a2 + (1.0 - a2) * cos2).sqrt() / cosine - 1.0) * 0.5
+}
+
+// A normal of the microfacets visible from `view`, "Sampling the GGX Distribution of
+// Visible Normals", Heitz 2018
+fn sample_ggx_visible_normal(normal: Vec3A, view: Vec3A, alpha: f32, rng: &mut SmallRng) -> Vec3A {
+    let (tangent, bitangent) = normal.any_orthonormal_pair();
+    let local = Vec3A::new(view.dot(tangent), view.dot(bitangent), view.dot(normal));
+    // the view in the hemisphere configuration, where the distribution is uniform
+    let stretched = Vec3A::new(alpha * local.x, alpha * local.y, local.z).normalize();
+    let len2 = stretched.x * stretched.x + stretched.y * stretched.y;
+    let t1 = if len2 > 0.0 {
+        Vec3A::new(-stretched.y, stretched.x, 0.0) / len2.sqrt()
+    } else {
+        Vec3A::X
+    };
+    let t2 = stretched.cross(t1);
+
+    let r = rng.gen::<f32>().sqrt();
+    let phi = 2.0 * PI * rng.gen::<f32>();
+    let p1 = r * phi.cos();
+    let s = 0.5 * (1.0 + stretched.z);
+    let p2 = (1.0 - s) * (1.0 - p1 * p1).sqrt() + s * r * phi.sin();
+    let p3 = (1.0 - p1 * p1 - p2 * p2).max(0.0).sqrt();
+    let hemisphere = p1 * t1 + p2 * t2 + p3 * stretched;
+
+    let half = Vec3A::new(
+        alpha * hemisphere.x,
+        alpha * hemisphere.y,
+        hemisphere.z.max(0.0),
+    )
+    .normalize();
+    tangent * half.x + bitangent * half.y + normal * half.z
+}
+
+fn luminance(color: Color) -> f32 {
+    0.2126 * color.red + 0.7152 * color.green + 0.0722 * color.blue
+}
+
+#[test]
+fn test_microfacet_conserves_energy() {
+    use crate::test_utils::{grey, hit_record};
+    use rand::SeedableRng;
+
+    let mut rng = SmallRng::seed_from_u64(0);
+    let normal = Vec3A::Y;
+    let view = Vec3A::new(0.6, 0.8, 0.0);
+    let ray = Ray::new(view, -view);
+    // a white furnace: the rough metal loses only the light of multiple bounces, the
+    // plastic can not reflect more than what arrives
+    for (metallic, roughness) in [(1.0, 0.5), (0.0, 0.3), (0.0, 1.0)] {
+        let mat = Material::microfacet(grey(1.0), grey(metallic), grey(roughness), grey(0.5));
+        let hit = hit_record(&mat, 1.0, normal);
+        let n = 20000;
+        let albedo: f32 = (0..n)
+            .filter_map(|_| mat.scatter(&ray, &hit, &mut rng))
+            .map(|scatter| scatter.color.green)
+            .sum::<f32>()
+            / n as f32;
+        assert!(
+            albedo > 0.85 && albedo < 1.02,
+            "{metallic} {roughness}: {albedo}"
+        );
+
+        // the renderer weights the lights with eval and pdf, they agree with the samples
+        let sample = mat.sample(&ray, &hit, &mut rng).unwrap();
+        let pdf = mat.pdf(sample.direction, view, &hit);
+        let weight = mat.eval(sample.direction, view, &hit) / pdf;
+        assert!((pdf - sample.pdf).abs() <= 1e-3 * pdf && !sample.specular);
+        assert!((weight.green - sample.weight.green).abs() <= 1e-3 * weight.green);
+    }
+}
+
+#[test]
+fn test_dielectric_absorbs_along_the_path() {
+    use crate::test_utils::hit_record;
+    use rand::SeedableRng;
+
+    let mut rng = SmallRng::seed_from_u64(0);
+    let (ior, thickness) = (1.5f32, 2.0);
+    let absorption = Color::new(0.1, 0.5, 1.0);
+    // a slab between y = 0 and y = thickness, entered from the top at 60 degrees
+    let incoming = Vec3A::new(3f32.sqrt(), -1.0, 0.0).normalize();
+    let cos_refracted = (1.0 - 0.75 / (ior * ior)).sqrt();
+    let path = thickness / cos_refracted;
+    let beer_lambert = [
+        (-absorption.red * path).exp(),
+        (-absorption.green * path).exp(),
+        (-absorption.blue * path).exp(),
+    ];
+    for (absorption, expected) in [(absorption, beer_lambert), (BLACK, [1.0; 3])] {
+        let mat = Material::Dielectric(Dielectric::new(ior).with_absorption(absorption));
+        let ray = Ray::new(Vec3A::new(0.0, 1.0, 0.0), incoming);
+        let entry = hit_record(&mat, 1.0, Vec3A::Y);
+        // the reflections off the top never go through
+        let inside = std::iter::repeat_with(|| mat.sample(&ray, &entry, &mut rng).unwrap())
+            .find(|sample| sample.direction.y < 0.0)
+            .unwrap();
+        assert_eq!(inside.weight, WHITE);
+        assert!((inside.direction.y + cos_refracted).abs() < 1e-5);
+
+        // whether it leaves through the bottom or is reflected back, the light crossed the slab
+        let ray = Ray::new(Vec3A::ZERO, inside.direction);
+        let exit = hit_record(&mat, thickness / -inside.direction.y, -Vec3A::Y);
+        for _ in 0..8 {
+            let weight = mat.sample(&ray, &exit, &mut rng).unwrap().weight;
+            let channels = [weight.red, weight.green, weight.blue];
+            for (channel, expected) in channels.into_iter().zip(expected) {
+                assert!((channel - expected).abs() < 1e-5, "{weight:?}");
+            }
+        }
+    }
+
+    // the glass is tinted by the colors of the vertices, like the ones of a ply mesh
+    let orange = Color::new(1.0, 0.5, 0.0);
+    let mat = Material::Dielectric(Dielectric::new(ior).with_tint(Texture::vertex_color(WHITE)));
+    let ray = Ray::new(Vec3A::Y, -Vec3A::Y);
+    let entry = HitRecord {
+        vertex_color: Some(orange),
+        ..hit_record(&mat, 1.0, Vec3A::Y)
+    };
+    let inside = std::iter::repeat_with(|| mat.sample(&ray, &entry, &mut rng).unwrap())
+        .find(|sample| sample.direction.y < 0.0)
+        .unwrap();
+    assert_eq!(inside.weight, orange);
+}
+
+#[test]
+fn test_mix_and_clearcoat() {
+    use crate::test_utils::{grey, hit_record};
+    use rand::SeedableRng;
+
+    let mut rng = SmallRng::seed_from_u64(0);
+    let view = Vec3A::new(0.6, 0.8, 0.0);
+    let ray = Ray::new(view, -view);
+    let light = Vec3A::new(-0.3, 0.9, 0.3).normalize();
+
+    // a mask of 0 is only `a`, a mask of 1 only `b`
+    let a = Material::lambertian(grey(0.8));
+    let b = Material::microfacet(grey(1.0), grey(1.0), grey(0.4), grey(0.5));
+    for (share, base) in [(0.0, &a), (1.0, &b)] {
+        let mix = Material::mix(a.clone(), b.clone(), grey(share));
+        let mix_hit = hit_record(&mix, 1.0, Vec3A::Y);
+        let base_hit = hit_record(base, 1.0, Vec3A::Y);
+        let (eval, expected) = (
+            mix.eval(light, view, &mix_hit),
+            base.eval(light, view, &base_hit),
+        );
+        assert!(
+            (eval.green - expected.green).abs() < 1e-6,
+            "{share}: {eval:?}"
+        );
+        let pdf = mix.pdf(light, view, &mix_hit);
+        let expected = base.pdf(light, view, &base_hit);
+        assert!((pdf - expected).abs() < 1e-6, "{share}: {pdf}");
+    }
+
+    // the emission is mixed with the same mask as the reflection, here the vertex colors
+    let lights = Material::mix(
+        Material::diffuse_light(grey(2.0)),
+        Material::diffuse_light(grey(4.0)),
+        Texture::vertex_color(Color::new(0.0, 0.0, 0.0)),
+    );
+    let light_hit = HitRecord {
+        vertex_color: Some(Color::new(0.25, 0.25, 0.25)),
+        ..hit_record(&lights, 1.0, Vec3A::Y)
+    };
+    assert_eq!(lights.emitted(&light_hit), Color::new(2.5, 2.5, 2.5));
+
+    // the coat takes its share of the light from the white base, it never adds any
+    let coat = Material::clearcoat(Material::lambertian(grey(1.0)), 1.5);
+    let coat_hit = hit_record(&coat, 1.0, Vec3A::Y);
+    for _ in 0..1000 {
+        let weight = coat.sample(&ray, &coat_hit, &mut rng).unwrap().weight;
+        for channel in [weight.red, weight.green, weight.blue] {
+            assert!((0.0..=1.0).contains(&channel), "{weight:?}");
+        }
     }
 }
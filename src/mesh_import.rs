@@ -0,0 +1,576 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+
+use glam::{Vec2, Vec3A};
+use image::Rgba;
+
+use crate::color::Color;
+use crate::hitable::HitableStore;
+use crate::material::Material;
+use crate::render_error::RenderError;
+use crate::texture::Texture;
+use crate::triangle_mesh::TriangleMesh;
+use crate::utils::load_obj_to_hitable;
+
+// The vertices and the triangles read from a ply or an stl file, the attributes other than
+// the positions are empty when the file does not have them
+#[derive(Default)]
+struct MeshData {
+    positions: Vec<Vec3A>,
+    normals: Vec<Vec3A>,
+    uvs: Vec<Vec2>,
+    colors: Vec<Color>,
+    faces: Vec<[u32; 3]>,
+}
+
+// An obj, ply or stl file, chosen by the extension. Files with other extensions are read
+// as obj.
+pub fn load_mesh_to_hitable(path: &Path) -> Result<HitableStore, RenderError> {
+    let extension = path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(str::to_ascii_lowercase);
+    match extension.as_deref() {
+        Some("ply") => load_ply_to_hitable(path),
+        Some("stl") => load_stl_to_hitable(path),
+        _ => load_obj_to_hitable(path),
+    }
+}
+
+// A triangle mesh from an ascii or binary ply file. Polygons are triangulated as fans.
+// When the vertices have colors the mesh is textured by them, otherwise it is grey.
+pub fn load_ply_to_hitable(path: &Path) -> Result<HitableStore, RenderError> {
+    let bytes = fs::read(path).map_err(|error| mesh_error("ply", path, error))?;
+    let mesh = parse_ply(&bytes).map_err(|message| mesh_error("ply", path, message))?;
+    mesh.into_hitables()
+}
+
+// A triangle mesh from an ascii or binary stl file, with flat shading. Stl files repeat
+// the vertices in every triangle, the ones with the same position are merged.
+pub fn load_stl_to_hitable(path: &Path) -> Result<HitableStore, RenderError> {
+    let bytes = fs::read(path).map_err(|error| mesh_error("stl", path, error))?;
+    let mesh = parse_stl(&bytes).map_err(|message| mesh_error("stl", path, message))?;
+    mesh.into_hitables()
+}
+
+impl MeshData {
+    fn into_hitables(self) -> Result<HitableStore, RenderError> {
+        let mut hitables = HitableStore::new();
+        if self.faces.is_empty() {
+            return Ok(hitables);
+        }
+        let grey = Color::new(0.6, 0.6, 0.6);
+        let albedo = if self.colors.is_empty() {
+            Texture::constant_color(grey)
+        } else {
+            Texture::vertex_color(grey)
+        };
+        let material = Arc::new(Material::lambertian(albedo));
+
+        let mut triangles = TriangleMesh::new(self.positions, self.faces, material);
+        if !self.normals.is_empty() {
+            triangles = triangles.with_normals(self.normals)?;
+        }
+        if !self.uvs.is_empty() {
+            triangles = triangles.with_uvs(self.uvs)?;
+        }
+        if !self.colors.is_empty() {
+            triangles = triangles.with_colors(self.colors)?;
+        }
+        hitables.push(triangles);
+        Ok(hitables)
+    }
+
+    // Splits a polygon in a fan of triangles around its first vertex
+    fn push_polygon(&mut self, polygon: &[u32]) {
+        for i in 2..polygon.len() {
+            self.faces.push([polygon[0], polygon[i - 1], polygon[i]]);
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum PlyFormat {
+    Ascii,
+    BinaryLittleEndian,
+    BinaryBigEndian,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum PlyScalar {
+    I8,
+    U8,
+    I16,
+    U16,
+    I32,
+    U32,
+    F32,
+    F64,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum PlyType {
+    Scalar(PlyScalar),
+    // the type of the length, then the one of the items
+    List(PlyScalar, PlyScalar),
+}
+
+struct PlyElement {
+    name: String,
+    count: usize,
+    properties: Vec<(String, PlyType)>,
+}
+
+impl PlyScalar {
+    fn parse(name: &str) -> Result<PlyScalar, String> {
+        Ok(match name {
+            "char" | "int8" => PlyScalar::I8,
+            "uchar" | "uint8" => PlyScalar::U8,
+            "short" | "int16" => PlyScalar::I16,
+            "ushort" | "uint16" => PlyScalar::U16,
+            "int" | "int32" => PlyScalar::I32,
+            "uint" | "uint32" => PlyScalar::U32,
+            "float" | "float32" => PlyScalar::F32,
+            "double" | "float64" => PlyScalar::F64,
+            _ => return Err(format!("unknown property type {}", name)),
+        })
+    }
+
+    fn size(self) -> usize {
+        match self {
+            PlyScalar::I8 | PlyScalar::U8 => 1,
+            PlyScalar::I16 | PlyScalar::U16 => 2,
+            PlyScalar::I32 | PlyScalar::U32 | PlyScalar::F32 => 4,
+            PlyScalar::F64 => 8,
+        }
+    }
+
+    // the value of a full channel, integer colors go up to the largest integer
+    fn full_color(self) -> f64 {
+        match self {
+            PlyScalar::I8 => i8::MAX as f64,
+            PlyScalar::U8 => u8::MAX as f64,
+            PlyScalar::I16 => i16::MAX as f64,
+            PlyScalar::U16 => u16::MAX as f64,
+            PlyScalar::I32 => i32::MAX as f64,
+            PlyScalar::U32 => u32::MAX as f64,
+            PlyScalar::F32 | PlyScalar::F64 => 1.0,
+        }
+    }
+}
+
+// The values of the body of a ply file, in its format
+struct PlyReader<'a> {
+    format: PlyFormat,
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl PlyReader<'_> {
+    fn read(&mut self, scalar: PlyScalar) -> Result<f64, String> {
+        if self.format == PlyFormat::Ascii {
+            return self.read_word(scalar);
+        }
+        let size = scalar.size();
+        let bytes = self
+            .data
+            .get(self.pos..self.pos + size)
+            .ok_or("unexpected end of file")?;
+        self.pos += size;
+        let mut buf = [0u8; 8];
+        buf[..size].copy_from_slice(bytes);
+        if self.format == PlyFormat::BinaryBigEndian {
+            buf[..size].reverse();
+        }
+        let [b0, b1, b2, b3, ..] = buf;
+        Ok(match scalar {
+            PlyScalar::I8 => b0 as i8 as f64,
+            PlyScalar::U8 => b0 as f64,
+            PlyScalar::I16 => i16::from_le_bytes([b0, b1]) as f64,
+            PlyScalar::U16 => u16::from_le_bytes([b0, b1]) as f64,
+            PlyScalar::I32 => i32::from_le_bytes([b0, b1, b2, b3]) as f64,
+            PlyScalar::U32 => u32::from_le_bytes([b0, b1, b2, b3]) as f64,
+            PlyScalar::F32 => f32::from_le_bytes([b0, b1, b2, b3]) as f64,
+            PlyScalar::F64 => f64::from_le_bytes(buf),
+        })
+    }
+
+    fn read_word(&mut self, scalar: PlyScalar) -> Result<f64, String> {
+        let is_space = |b: &u8| b.is_ascii_whitespace();
+        let rest = &self.data[self.pos..];
+        let start = rest.iter().position(|b| !is_space(b)).unwrap_or(rest.len());
+        let len = rest[start..]
+            .iter()
+            .position(is_space)
+            .unwrap_or(rest.len() - start);
+        if len == 0 {
+            return Err(String::from("unexpected end of file"));
+        }
+        self.pos += start + len;
+        let word = String::from_utf8_lossy(&rest[start..start + len]);
+        let value = word
+            .parse::<f64>()
+            .map_err(|_| format!("{} is not a number", word))?;
+        match scalar {
+            PlyScalar::F32 | PlyScalar::F64 => Ok(value),
+            _ if value.fract() == 0.0 => Ok(value),
+            _ => Err(format!("{} is not an integer", word)),
+        }
+    }
+
+    // The scalars of an element in `values`, the lists leave a NaN in their place. The
+    // items of the list in the column `list_column` are read into `list`, the other lists
+    // are skipped, like the texcoords that some exporters add to the faces.
+    fn read_element(
+        &mut self,
+        element: &PlyElement,
+        list_column: Option<usize>,
+        values: &mut Vec<f64>,
+        list: &mut Vec<f64>,
+    ) -> Result<(), String> {
+        values.clear();
+        list.clear();
+        for (column, (_, property)) in element.properties.iter().enumerate() {
+            match *property {
+                PlyType::Scalar(scalar) => values.push(self.read(scalar)?),
+                PlyType::List(len, item) => {
+                    let len = self.read(len)?;
+                    if len < 0.0 {
+                        return Err(format!("list with {} items", len));
+                    }
+                    for _ in 0..len as usize {
+                        let value = self.read(item)?;
+                        if list_column == Some(column) {
+                            list.push(value);
+                        }
+                    }
+                    values.push(f64::NAN);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn parse_ply(bytes: &[u8]) -> Result<MeshData, String> {
+    if !bytes.starts_with(b"ply") {
+        return Err(String::from("not a ply file"));
+    }
+    let end_header = bytes
+        .windows(10)
+        .position(|window| window == b"end_header")
+        .ok_or("the header has no end_header")?;
+    // the body starts on the line after end_header
+    let body = bytes[end_header..]
+        .iter()
+        .position(|&b| b == b'\n')
+        .map_or(bytes.len(), |newline| end_header + newline + 1);
+
+    let mut format = None;
+    let mut elements: Vec<PlyElement> = Vec::new();
+    for line in String::from_utf8_lossy(&bytes[..end_header])
+        .lines()
+        .skip(1)
+    {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        match words.as_slice() {
+            [] | ["comment", ..] | ["obj_info", ..] => {}
+            ["format", name, _] => {
+                format = Some(match *name {
+                    "ascii" => PlyFormat::Ascii,
+                    "binary_little_endian" => PlyFormat::BinaryLittleEndian,
+                    "binary_big_endian" => PlyFormat::BinaryBigEndian,
+                    _ => return Err(format!("unknown format {}", name)),
+                })
+            }
+            ["element", name, count] => elements.push(PlyElement {
+                name: name.to_string(),
+                count: count
+                    .parse()
+                    .map_err(|_| format!("element {} has {} items", name, count))?,
+                properties: Vec::new(),
+            }),
+            ["property", kind @ .., name] => {
+                let kind = match kind {
+                    ["list", len, item] => {
+                        PlyType::List(PlyScalar::parse(len)?, PlyScalar::parse(item)?)
+                    }
+                    [scalar] => PlyType::Scalar(PlyScalar::parse(scalar)?),
+                    _ => return Err(format!("invalid property: {}", line)),
+                };
+                let element = elements
+                    .last_mut()
+                    .ok_or_else(|| format!("property {} outside of an element", name))?;
+                element.properties.push((name.to_string(), kind));
+            }
+            _ => return Err(format!("invalid header line: {}", line)),
+        }
+    }
+
+    let mut reader = PlyReader {
+        format: format.ok_or("the header has no format")?,
+        data: bytes,
+        pos: body,
+    };
+    let mut mesh = MeshData::default();
+    let (mut values, mut list) = (Vec::new(), Vec::new());
+    for element in &elements {
+        let column = |names: &[&str]| {
+            element
+                .properties
+                .iter()
+                .position(|(name, _)| names.contains(&name.as_str()))
+        };
+        match element.name.as_str() {
+            "vertex" => {
+                let columns = |names: [&[&str]; 3]| {
+                    Some([column(names[0])?, column(names[1])?, column(names[2])?])
+                };
+                let position =
+                    columns([&["x"], &["y"], &["z"]]).ok_or("the vertices have no x, y and z")?;
+                let normal = columns([&["nx"], &["ny"], &["nz"]]);
+                let uv = [
+                    ["u", "v"],
+                    ["s", "t"],
+                    ["texture_u", "texture_v"],
+                    ["texture_s", "texture_t"],
+                ]
+                .iter()
+                .find_map(|[u, v]| Some([column(&[u])?, column(&[v])?]));
+                let color = columns([
+                    &["red", "diffuse_red"],
+                    &["green", "diffuse_green"],
+                    &["blue", "diffuse_blue"],
+                ]);
+                // the channels are encoded like the ones of an image
+                let full_color = color.map(|[red, ..]| match element.properties[red].1 {
+                    PlyType::Scalar(scalar) => scalar.full_color(),
+                    PlyType::List(..) => 1.0,
+                });
+                for _ in 0..element.count {
+                    reader.read_element(element, None, &mut values, &mut list)?;
+                    let vec3 = |[x, y, z]: [usize; 3]| {
+                        Vec3A::new(values[x] as f32, values[y] as f32, values[z] as f32)
+                    };
+                    mesh.positions.push(vec3(position));
+                    if let Some(normal) = normal {
+                        mesh.normals.push(vec3(normal));
+                    }
+                    if let Some([u, v]) = uv {
+                        mesh.uvs.push(Vec2::new(values[u] as f32, values[v] as f32));
+                    }
+                    if let (Some(channels), Some(full)) = (color, full_color) {
+                        let channel =
+                            |i: usize| (values[i] / full * 255.0).round().clamp(0.0, 255.0) as u8;
+                        let [red, green, blue] = channels.map(channel);
+                        mesh.colors
+                            .push(Color::from_rgba(Rgba([red, green, blue, 255])));
+                    }
+                }
+            }
+            "face" => {
+                let indices = column(&["vertex_indices", "vertex_index"])
+                    .ok_or("the faces have no vertex_indices")?;
+                if !matches!(element.properties[indices].1, PlyType::List(..)) {
+                    return Err(String::from("vertex_indices is not a list"));
+                }
+                let mut polygon = Vec::new();
+                for _ in 0..element.count {
+                    reader.read_element(element, Some(indices), &mut values, &mut list)?;
+                    polygon.clear();
+                    for &index in list.iter() {
+                        if index.fract() != 0.0 {
+                            return Err(format!("vertex index {} is not an integer", index));
+                        }
+                        if index < 0.0 || index >= mesh.positions.len() as f64 {
+                            return Err(format!("vertex index {} out of bounds", index));
+                        }
+                        polygon.push(index as u32);
+                    }
+                    mesh.push_polygon(&polygon);
+                }
+            }
+            // like the edges, or the materials of some exporters
+            _ => {
+                for _ in 0..element.count {
+                    reader.read_element(element, None, &mut values, &mut list)?;
+                }
+            }
+        }
+    }
+    Ok(mesh)
+}
+
+fn parse_stl(bytes: &[u8]) -> Result<MeshData, String> {
+    // the ascii files start with "solid", but some binary ones do too. These have the
+    // size given by their number of triangles, or are not text when they are truncated.
+    let triangles = bytes
+        .get(80..84)
+        .map(|count| u32::from_le_bytes([count[0], count[1], count[2], count[3]]) as usize);
+    let binary_size = triangles.map(|triangles| 84 + 50 * triangles);
+    let is_text = bytes
+        .iter()
+        .all(|b| !b.is_ascii_control() || b.is_ascii_whitespace());
+    if bytes.starts_with(b"solid") && binary_size != Some(bytes.len()) && is_text {
+        return parse_ascii_stl(bytes);
+    }
+
+    let (triangles, binary_size) = triangles
+        .zip(binary_size)
+        .ok_or("the header is truncated")?;
+    if bytes.len() < binary_size {
+        return Err(format!(
+            "{} triangles need {} bytes, the file has {}",
+            triangles,
+            binary_size,
+            bytes.len()
+        ));
+    }
+    let mut welder = Welder::default();
+    for triangle in bytes[84..binary_size].chunks_exact(50) {
+        // the normal comes first, then the vertices and two bytes of attributes
+        let float = |i: usize| {
+            let b = &triangle[12 + 4 * i..16 + 4 * i];
+            f32::from_le_bytes([b[0], b[1], b[2], b[3]])
+        };
+        let face = [0, 1, 2]
+            .map(|v| welder.vertex(Vec3A::new(float(3 * v), float(3 * v + 1), float(3 * v + 2))));
+        welder.mesh.faces.push(face);
+    }
+    Ok(welder.mesh)
+}
+
+fn parse_ascii_stl(bytes: &[u8]) -> Result<MeshData, String> {
+    let text = String::from_utf8_lossy(bytes);
+    let mut words = text.split_whitespace();
+    let mut welder = Welder::default();
+    let mut polygon = Vec::new();
+    while let Some(word) = words.next() {
+        match word {
+            "loop" => polygon.clear(),
+            "vertex" => {
+                let mut coordinate = || {
+                    let word = words.next().ok_or("unexpected end of file")?;
+                    word.parse::<f32>()
+                        .map_err(|_| format!("{} is not a number", word))
+                };
+                let position = Vec3A::new(coordinate()?, coordinate()?, coordinate()?);
+                polygon.push(welder.vertex(position));
+            }
+            "endloop" => welder.mesh.push_polygon(&polygon),
+            _ => {}
+        }
+    }
+    Ok(welder.mesh)
+}
+
+// Merges the vertices with the same position
+#[derive(Default)]
+struct Welder {
+    mesh: MeshData,
+    indices: HashMap<[u32; 3], u32>,
+}
+
+impl Welder {
+    fn vertex(&mut self, position: Vec3A) -> u32 {
+        let positions = &mut self.mesh.positions;
+        *self
+            .indices
+            .entry(position.to_array().map(f32::to_bits))
+            .or_insert_with(|| {
+                positions.push(position);
+                positions.len() as u32 - 1
+            })
+    }
+}
+
+// An error of the mesh file at `path`, with its format as the kind, like "ply" or "obj"
+pub(crate) fn mesh_error(kind: &str, path: &Path, error: impl std::fmt::Display) -> RenderError {
+    RenderError {
+        kind: String::from(kind),
+        message: format!("{}: {}", path.display(), error),
+    }
+}
+
+#[test]
+fn test_load_ply_and_stl() {
+    use crate::hitable::Hitable;
+    use crate::ray::Ray;
+
+    let dir = std::env::temp_dir().join("tuot_mesh_test");
+    fs::create_dir_all(&dir).unwrap();
+    let down = Ray::new(Vec3A::new(0.25, 1.0, 0.25), Vec3A::new(0.0, -1.0, 0.0));
+
+    // a quad on the xz plane, red in the corner at the origin
+    let ply = "ply\nformat ascii 1.0\ncomment quad\nelement vertex 4\n\
+               property float x\nproperty float y\nproperty float z\n\
+               property uchar red\nproperty uchar green\nproperty uchar blue\n\
+               element face 1\nproperty list uchar int vertex_indices\nend_header\n\
+               0 0 0 255 0 0\n1 0 0 255 0 0\n1 0 1 255 0 0\n0 0 1 255 0 0\n4 0 1 2 3\n";
+    fs::write(dir.join("quad.ply"), ply).unwrap();
+    let quad = load_mesh_to_hitable(&dir.join("quad.ply")).unwrap();
+    let hit = quad.hit(&down, 0.001, f32::MAX).unwrap();
+    assert!((hit.t - 1.0).abs() < 1e-5);
+    assert_eq!(hit.vertex_color, Some(Color::new(1.0, 0.0, 0.0)));
+
+    // the same quad as two binary triangles sharing two vertices, the header starts
+    // with solid like the ones of some exporters
+    let mut stl = b"solid binary".to_vec();
+    stl.resize(80, 0);
+    stl.extend(2u32.to_le_bytes());
+    for triangle in [
+        [0., 0., 0., 1., 0., 0., 1., 0., 1.],
+        [0., 0., 0., 1., 0., 1., 0., 0., 1.],
+    ] {
+        stl.extend([0u8; 12]);
+        stl.extend(triangle.iter().flat_map(|c: &f32| c.to_le_bytes()));
+        stl.extend([0u8; 2]);
+    }
+    fs::write(dir.join("quad.stl"), &stl).unwrap();
+    let mesh = parse_stl(&stl).unwrap();
+    assert_eq!((mesh.positions.len(), mesh.faces.len()), (4, 2));
+    let quad = load_mesh_to_hitable(&dir.join("quad.stl")).unwrap();
+    assert!(quad.hit(&down, 0.001, f32::MAX).is_some());
+
+    let ascii = "solid quad\nfacet normal 0 1 0\nouter loop\nvertex 0 0 0\nvertex 1 0 0\n\
+                 vertex 1 0 1\nendloop\nendfacet\nendsolid quad\n";
+    assert_eq!(parse_stl(ascii.as_bytes()).unwrap().faces, vec![[0, 1, 2]]);
+
+    fs::write(dir.join("truncated.stl"), &stl[..100]).unwrap();
+    assert_eq!(
+        load_stl_to_hitable(&dir.join("truncated.stl"))
+            .err()
+            .unwrap()
+            .kind,
+        "stl"
+    );
+    fs::write(dir.join("bad.ply"), ply.replace("4 0 1 2 3", "3 0 1 7")).unwrap();
+    assert_eq!(
+        load_ply_to_hitable(&dir.join("bad.ply"))
+            .err()
+            .unwrap()
+            .kind,
+        "ply"
+    );
+}
+
+#[test]
+fn test_ply_faces_with_texcoords() {
+    // the faces of MeshLab carry their uvs in a second list, after the indices
+    let ply = "ply\nformat ascii 1.0\nelement vertex 4\n\
+               property float x\nproperty float y\nproperty float z\n\
+               element face 1\nproperty list uchar int vertex_indices\n\
+               property list uchar float texcoord\nend_header\n\
+               0 0 0\n1 0 0\n1 0 1\n0 0 1\n4 0 1 2 3 8 0 0 1 0 1 1 0 1\n";
+    let mesh = parse_ply(ply.as_bytes()).unwrap();
+    assert_eq!(mesh.faces, vec![[0, 1, 2], [0, 2, 3]]);
+
+    // the indices are integers even in a list of floats
+    let floats = ply
+        .replace("uchar int vertex_indices", "uchar float vertex_indices")
+        .replace("4 0 1 2 3", "4 0 1 2.5 3");
+    let error = parse_ply(floats.as_bytes()).err().unwrap();
+    assert!(error.contains("not an integer"), "{}", error);
+}
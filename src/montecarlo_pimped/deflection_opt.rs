@@ -1,9 +1,10 @@
-#[derive(PartialEq)]
+#[derive(PartialEq, Clone, Copy)]
 pub enum DeflectionForce {
     CameraRay,
     ObjectToCamera,
 }
 
+#[derive(Clone, Copy)]
 pub struct DeflectionOpt {
     pub amplitude: f32,
     pub randomness: f32,
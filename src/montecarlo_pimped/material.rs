@@ -3,6 +3,7 @@ use std::ops::{Add, Neg, Sub};
 use super::deflection_opt::{DeflectionForce, DeflectionOpt};
 use super::tint_opt::{ BandOp, TintOpt};
 use glam::Vec3A;
+use rand::rngs::SmallRng;
 use rand::Rng;
 
 use crate::{
@@ -10,13 +11,18 @@ use crate::{
     color::Color,
     //color::Color,
     hitable::HitRecord,
-    material::{Dielectric, DiffuseLight, Lambertian, Material, Metal, Scatter},
+    material::{
+        BsdfSample, Clearcoat, Dielectric, DiffuseLight, Lambertian, Material, Metal, Microfacet,
+        Mix, Scatterable,
+    },
     ray::Ray,
     setup::WHITE,
-    texture::Texture,
-    utils::{near_zero, random_in_unit_sphere, reflect, refract, schlick},
+    utils::{near_zero, random_in_unit_sphere, reflect},
 };
 
+// The sampling of the materials with the deflection and the tint of the T.U.O.T. renderer.
+// The deflected directions have no density, the samples are specular unless the material
+// is not deflected.
 pub trait DeflectableNormal {
     fn scattero(
         &self,
@@ -25,7 +31,8 @@ pub trait DeflectableNormal {
         camera: &Camera,
         deflection_opt: &DeflectionOpt,
         tint_opt: &TintOpt,
-    ) -> Option<Scatter>;
+        rng: &mut SmallRng,
+    ) -> Option<BsdfSample>;
     //fn default_color(&self) -> Texture;
 }
 
@@ -37,17 +44,29 @@ impl DeflectableNormal for Material {
         camera: &Camera,
         deflection_opt: &DeflectionOpt,
         tint_opt: &TintOpt,
-    ) -> Option<Scatter> {
+        rng: &mut SmallRng,
+    ) -> Option<BsdfSample> {
         match self {
             Material::Lambertian(l) => {
-                l.scattero(ray, hit_record, camera, deflection_opt, tint_opt)
+                l.scattero(ray, hit_record, camera, deflection_opt, tint_opt, rng)
+            }
+            Material::Metal(m) => {
+                m.scattero(ray, hit_record, camera, deflection_opt, tint_opt, rng)
             }
-            Material::Metal(m) => m.scattero(ray, hit_record, camera, deflection_opt, tint_opt),
             Material::Dielectric(_d) => {
-                _d.scattero(ray, hit_record, camera, deflection_opt, tint_opt)
+                _d.scattero(ray, hit_record, camera, deflection_opt, tint_opt, rng)
             }
             Material::DiffuseLight(li) => {
-                li.scattero(ray, hit_record, camera, deflection_opt, tint_opt)
+                li.scattero(ray, hit_record, camera, deflection_opt, tint_opt, rng)
+            }
+            Material::Microfacet(m) => {
+                m.scattero(ray, hit_record, camera, deflection_opt, tint_opt, rng)
+            }
+            Material::Mix(m) => {
+                m.scattero(ray, hit_record, camera, deflection_opt, tint_opt, rng)
+            }
+            Material::Clearcoat(c) => {
+                c.scattero(ray, hit_record, camera, deflection_opt, tint_opt, rng)
             }
         }
     }
@@ -98,13 +117,15 @@ impl DeflectableNormal for Lambertian {
         camera: &Camera,
         deflection_opt: &DeflectionOpt,
         tint_opt: &TintOpt,
-    ) -> Option<Scatter> {
+        rng: &mut SmallRng,
+    ) -> Option<BsdfSample> {
         //let mut scatter_direction = hit_record.normal + random_in_unit_sphere();
         let mut scatter_direction = deflect_normal(
             &hit_record.pos,
             &hit_record.normal,
             &camera.origin,
             deflection_opt,
+            rng,
         );
         // this is because some of the scattered rays hit the object they are reflecting.
         // This is because they start a bit below the hitten surface. In this case, we coerce
@@ -113,15 +134,14 @@ impl DeflectableNormal for Lambertian {
             scatter_direction = hit_record.normal;
         }
 
-        let target = hit_record.pos + scatter_direction;
-        let original_color = self
-            .albedo
-            .value(hit_record.u, hit_record.v, hit_record.pos);
+        let original_color = self.albedo.hit_value(hit_record);
         let new_color = get_deflected_color(&scatter_direction, &original_color, &tint_opt);
 
-        Some(Scatter {
-            color: new_color,
-            ray: Some(Ray::new(hit_record.pos, target - hit_record.pos)),
+        Some(BsdfSample {
+            direction: scatter_direction.normalize(),
+            weight: new_color,
+            pdf: 0.0,
+            specular: true,
         })
     }
 }
@@ -134,22 +154,23 @@ impl DeflectableNormal for Metal {
         _camera: &Camera,
         _deflection_opt: &DeflectionOpt,
         tint_opt: &TintOpt,
-    ) -> Option<Scatter> {
+        rng: &mut SmallRng,
+    ) -> Option<BsdfSample> {
         let reflected = reflect(&ray.direction, &hit_record.normal);
         let scattered = Ray::new(
             hit_record.pos,
-            reflected + random_in_unit_sphere() * self.fuzz,
+            reflected + random_in_unit_sphere(rng) * self.fuzz,
         );
-        let attenuation = self
-            .albedo
-            .value(hit_record.u, hit_record.v, hit_record.pos);
+        let attenuation = self.albedo.hit_value(hit_record);
 
         let new_color = get_deflected_color(&scattered.direction(), &attenuation, tint_opt);
 
         if scattered.direction.dot(hit_record.normal) > 0.0 {
-            Some(Scatter {
-                color: new_color,
-                ray: Some(scattered),
+            Some(BsdfSample {
+                direction: scattered.direction.try_normalize()?,
+                weight: new_color,
+                pdf: 0.0,
+                specular: true,
             })
         } else {
             None
@@ -157,6 +178,7 @@ impl DeflectableNormal for Metal {
     }
 }
 
+// only the refracted rays are tinted
 impl DeflectableNormal for Dielectric {
     fn scattero(
         &self,
@@ -165,48 +187,93 @@ impl DeflectableNormal for Dielectric {
         _camera: &Camera,
         _deflection_opt: &DeflectionOpt,
         tint_opt: &TintOpt,
-    ) -> Option<Scatter> {
-        let mut rng = rand::thread_rng();
-        let outward_normal: Vec3A;
-        let ni_over_nt: f32;
-        let cosine: f32;
-        let attenuation = Texture::constant_color(WHITE);
-
-        if r_in.direction.dot(hit.normal) > 0.0 {
-            outward_normal = -hit.normal;
-            ni_over_nt = self.index_of_refraction;
-            cosine =
-                self.index_of_refraction * r_in.direction.dot(hit.normal) / r_in.direction.length();
-        } else {
-            outward_normal = hit.normal;
-            ni_over_nt = 1.0 / self.index_of_refraction;
-            cosine = -r_in.direction.dot(hit.normal) / r_in.direction.length();
+        rng: &mut SmallRng,
+    ) -> Option<BsdfSample> {
+        let sample = self.sample(r_in, hit, rng)?;
+        let incoming_side = r_in.direction.dot(hit.geometric_normal) > 0.0;
+        let outgoing_side = sample.direction.dot(hit.geometric_normal) > 0.0;
+        if incoming_side != outgoing_side {
+            return Some(sample);
         }
+        Some(BsdfSample {
+            weight: get_deflected_color(&sample.direction, &sample.weight, tint_opt),
+            ..sample
+        })
+    }
+}
 
-        match refract(r_in.direction, outward_normal, ni_over_nt) {
-            Some(refracted) => {
-                if rng.gen::<f32>() > schlick(cosine, self.index_of_refraction) {
-                    let scattered = Ray::new(hit.pos, refracted.normalize());
-                    let old_color = attenuation.value(hit.u, hit.v, hit.pos);
-                    let new_color =
-                        get_deflected_color(&scattered.direction(), &old_color, tint_opt);
+// like the metals, the microfacets are tinted but not deflected
+impl DeflectableNormal for Microfacet {
+    fn scattero(
+        &self,
+        ray: &Ray,
+        hit_record: &HitRecord,
+        _camera: &Camera,
+        _deflection_opt: &DeflectionOpt,
+        tint_opt: &TintOpt,
+        rng: &mut SmallRng,
+    ) -> Option<BsdfSample> {
+        let sample = self.sample(ray, hit_record, rng)?;
+        Some(BsdfSample {
+            weight: get_deflected_color(&sample.direction, &sample.weight, tint_opt),
+            ..sample
+        })
+    }
+}
 
-                    return Some(Scatter {
-                        ray: Some(scattered),
-                        //color: attenuation.value(hit.u, hit.v, hit.pos),
-                        color: new_color,
-                    });
-                }
-            }
-            None => {}
-        }
+// one of the two materials, deflected like it would be on its own
+impl DeflectableNormal for Mix {
+    fn scattero(
+        &self,
+        ray: &Ray,
+        hit_record: &HitRecord,
+        camera: &Camera,
+        deflection_opt: &DeflectionOpt,
+        tint_opt: &TintOpt,
+        rng: &mut SmallRng,
+    ) -> Option<BsdfSample> {
+        let share = self.mask.hit_value(hit_record).red;
+        let layer = if rng.gen::<f32>() < share {
+            &self.b
+        } else {
+            &self.a
+        };
+        layer.scattero(ray, hit_record, camera, deflection_opt, tint_opt, rng)
+    }
+}
 
-        Some(Scatter {
-            color: attenuation.value(hit.u, hit.v, hit.pos),
-            ray: Some(Ray::new(
-                hit.pos,
-                reflect(&r_in.direction.normalize(), &hit.normal),
-            )),
+// the coat reflects without deflection, the base below it is deflected
+impl DeflectableNormal for Clearcoat {
+    fn scattero(
+        &self,
+        ray: &Ray,
+        hit_record: &HitRecord,
+        camera: &Camera,
+        deflection_opt: &DeflectionOpt,
+        tint_opt: &TintOpt,
+        rng: &mut SmallRng,
+    ) -> Option<BsdfSample> {
+        let view = -ray.direction.normalize();
+        let outside = view.dot(hit_record.geometric_normal) > 0.0;
+        if outside && rng.gen::<f32>() < self.reflectance(view, hit_record) {
+            return Some(BsdfSample {
+                direction: reflect(&-view, &hit_record.normal),
+                weight: WHITE,
+                pdf: 0.0,
+                specular: true,
+            });
+        }
+        let sample = self
+            .base
+            .scattero(ray, hit_record, camera, deflection_opt, tint_opt, rng)?;
+        let transmitted = if outside {
+            1.0 - self.reflectance(sample.direction, hit_record)
+        } else {
+            1.0
+        };
+        Some(BsdfSample {
+            weight: sample.weight * transmitted,
+            ..sample
         })
     }
 }
@@ -219,7 +286,8 @@ impl DeflectableNormal for DiffuseLight {
         _camera: &Camera,
         _deflection_opt: &DeflectionOpt,
         _tint_opt: &TintOpt,
-    ) -> Option<Scatter> {
+        _rng: &mut SmallRng,
+    ) -> Option<BsdfSample> {
         None
     }
 }
@@ -236,10 +304,11 @@ pub fn deflect_normal(
     surface_normal: &Vec3A,
     camera_pos: &Vec3A,
     deflection_opt: &DeflectionOpt,
+    rng: &mut SmallRng,
 ) -> Vec3A {
     let surface_to_eye = camera_pos.sub(*surface_position);
     let camera_ray = surface_to_eye.neg();
-    let random_ray = random_in_unit_sphere();
+    let random_ray = random_in_unit_sphere(rng);
 
     let pushed_away;
     match deflection_opt.force {
@@ -3,18 +3,22 @@ use crate::camera_effects::camera_distorter::get_distorted_ray;
 use crate::camera_effects::camera_distorter_opt::CameraDistorterOpt;
 use crate::camera_effects::CameraEffects;
 use crate::color::Color;
+use crate::framebuffer::Framebuffer;
 use crate::material::Scatterable;
+use crate::progressive::ProgressiveRenderer;
 use crate::ray::Ray;
 use crate::render_error::RenderError;
+use crate::render_settings::{RenderEngine, RenderSettings};
 use crate::scene::Scene;
-use crate::setup::{BLACK, WHITE};
+use crate::renderer::survives_russian_roulette;
+use crate::setup::{BLACK, RUSSIAN_ROULETTE_DEPTH, WHITE};
+use crate::spectrum::{sample_wavelength, wavelength_to_rgb};
 use crate::texture::Texture;
+use crate::utils::sample_rng;
 use glam::Vec3;
-use image::ImageBuffer;
-use image::Rgba;
-use rayon::prelude::*;
 
-use rand::prelude::*;
+use rand::rngs::SmallRng;
+use rand::Rng;
 //use std::path::Path;
 use std::result::Result;
 
@@ -24,72 +28,60 @@ use super::deflection_opt::DeflectionOpt;
 use super::material::DeflectableNormal;
 use super::tint_opt::TintOpt;
 
+// Renders all the samples of the frame in a single pass, with the pimped engine and the
+// camera effects, deflection and tint of `settings`
 pub fn render_montecarlo_pimped(
-    frame_width: u32,
-    frame_height: u32,
-    max_depth: usize,
-    samples: usize,
-    _current_frame: u32,
-    _tot_frames: u32,
-    camera_effects: &CameraEffects,
-    camera_distorter_opt: &CameraDistorterOpt,
-    deflection_opt: &DeflectionOpt,
-    tint_opt: &TintOpt,
+    settings: &RenderSettings,
     scene: &Scene,
-) -> Result<ImageBuffer<Rgba<u8>, Vec<u8>>, RenderError> {
-    let mut imgbuf = image::ImageBuffer::new(frame_width, frame_height);
-    let mut coords = Vec::with_capacity((frame_width * frame_height) as usize);
-    for y in 0..frame_height {
-        for x in 0..frame_width {
-            coords.push((x, y));
-        }
-    }
-
-    let noise = Texture::noise(camera_distorter_opt.noise_scale);
-    let pixels: Vec<(u32, u32, Color)> = coords
-        .par_iter()
-        .map(|&(x, y)| {
-            let flipped_y = frame_height - y; // render from bottom up to avoid image needing to be flipped
-
-            let mut col = BLACK;
-
-            for _s in 0..samples {
-                let mut rng = rand::thread_rng();
-                let u = ((x as f32) + rng.gen::<f32>()) / (frame_width as f32);
-                let v = ((flipped_y as f32) + rng.gen::<f32>()) / (frame_height as f32);
-
-                let ray = get_camera_ray(
-                    &scene.camera,
-                    u,
-                    v,
-                    &camera_effects,
-                    camera_distorter_opt,
-                    &noise,
-                );
-                // col += Color {
-                //     red: (ray.direction().x.powf(3.0) * 40.).sin(),
-                //     blue: (ray.direction().y.powf(3.0 * 40.)).cos(),
-                //     green: 0.0,
-                // };
-                col += colora(
-                    &ray,
-                    &scene,
-                    &deflection_opt,
-                    &tint_opt,
-                    max_depth,
-                    max_depth,
-                );
-            }
-            col /= samples as f32;
-            (x, y, col)
-        })
-        .collect();
+) -> Result<Framebuffer, RenderError> {
+    let settings = RenderSettings {
+        engine: RenderEngine::MonteCarloPimped,
+        samples_per_pass: settings.samples,
+        ..*settings
+    };
+    ProgressiveRenderer::new(settings).render(scene, |_, _| {})
+}
 
-    for (x, y, col) in pixels {
-        imgbuf.put_pixel(x, y, col.to_rgba());
-    }
-    println!("{}", imgbuf.width());
-    Ok(imgbuf)
+// The color of one camera sample of the pixel (x, y). `seed` is the seed of the frame,
+// `noise` the texture used by the camera distorter.
+pub fn render_sample_pimped(
+    x: u32,
+    y: u32,
+    sample: usize,
+    seed: u64,
+    settings: &RenderSettings,
+    noise: &Texture,
+    scene: &Scene,
+) -> Color {
+    let flipped_y = settings.height - y; // render from bottom up to avoid image needing to be flipped
+
+    let mut rng = sample_rng(seed, x, y, sample);
+    let u = ((x as f32) + rng.gen::<f32>()) / (settings.width as f32);
+    let v = ((flipped_y as f32) + rng.gen::<f32>()) / (settings.height as f32);
+
+    let ray = get_camera_ray(
+        &scene.camera,
+        u,
+        v,
+        &settings.camera_effects,
+        &settings.camera_distorter_opt,
+        noise,
+        &mut rng,
+    )
+    .with_wavelength(sample_wavelength(&mut rng));
+    // col += Color {
+    //     red: (ray.direction().x.powf(3.0) * 40.).sin(),
+    //     blue: (ray.direction().y.powf(3.0 * 40.)).cos(),
+    //     green: 0.0,
+    // };
+    colora(
+        &ray,
+        scene,
+        &settings.deflection_opt,
+        &settings.tint_opt,
+        settings.max_depth,
+        &mut rng,
+    )
 }
 
 // fn produce_error() -> Result<(), RenderError> {
@@ -106,9 +98,10 @@ fn get_camera_ray(
     camera_effects: &CameraEffects,
     camera_distorter_opt: &CameraDistorterOpt,
     noise: &Texture,
+    rng: &mut SmallRng,
 ) -> Ray {
     match camera_effects {
-        CameraEffects::NoEffects => camera.get_ray(u, v),
+        CameraEffects::NoEffects => camera.get_ray(u, v, rng),
         CameraEffects::Distorter => get_distorted_ray(
             camera,
             u,
@@ -116,6 +109,7 @@ fn get_camera_ray(
             noise,
             camera_distorter_opt.amplitude,
             camera_distorter_opt.easing,
+            rng,
         ),
     }
 }
@@ -136,49 +130,67 @@ fn colora(
     deflection_opt: &DeflectionOpt,
     tint_opt: &TintOpt,
     max_depth: usize,
-    depth: usize,
+    rng: &mut SmallRng,
 ) -> Color {
-    let mut col = WHITE;
-    // exit if the max depth has been reached
-    if depth <= 0 {
-        return col;
-    }
-    let hit = scene.bvh.hit(ray, 0.001, f32::MAX);
-    match hit {
-        Some(hit_record) => {
-            let scattered =
-                hit_record
-                    .mat
-                    .scattero(ray, &hit_record, &scene.camera, deflection_opt, tint_opt);
-
-            let emitted = hit_record.mat.emitted(0.0, 0.0, hit_record.pos);
-            match scattered {
-                Some(scatter) => {
-                    if let Some(bounce) = scatter.ray {
-                        col = emitted
-                            + scatter.color
-                                * colora(
-                                    &bounce,
-                                    scene,
-                                    deflection_opt,
-                                    tint_opt,
-                                    max_depth,
-                                    depth - 1,
-                                );
-                    } else {
-                        col = emitted;
-                    }
-                }
-                None => col = emitted,
+    let mut col = BLACK;
+    let mut throughput = WHITE;
+    let mut ray = Ray {
+        wavelength: ray.wavelength,
+        ..Ray::new(ray.origin, ray.direction)
+    };
+    // like in the montecarlo renderer, the paths become monochromatic at the first
+    // dispersive surface
+    let mut dispersed = false;
+
+    for depth in 0..max_depth {
+        let hit_record = match scene.bvh.hit(&ray, 0.001, f32::MAX) {
+            Some(hit_record) => hit_record,
+            None => {
+                // background color, sky in this case.
+                let mut background = BLACK;
+                background_color(&ray, &mut background, &tint_opt.background_color);
+                return col + throughput * background;
+            }
+        };
+
+        col += throughput * hit_record.mat.emitted(&hit_record);
+
+        let scattered = hit_record.mat.scattero(
+            &ray,
+            &hit_record,
+            &scene.camera,
+            deflection_opt,
+            tint_opt,
+            rng,
+        );
+        let sample = match scattered {
+            Some(sample) => sample,
+            None => return col,
+        };
+
+        throughput = throughput * sample.weight;
+        if hit_record.mat.is_dispersive() && !dispersed {
+            if let Some(wavelength) = ray.wavelength {
+                throughput = throughput * wavelength_to_rgb(wavelength);
+                dispersed = true;
             }
         }
-        None => {
-            // background color, sky in this case.
-            background_color(ray, &mut col, &tint_opt.background_color);
-            //col = BLACK;
+        if depth >= RUSSIAN_ROULETTE_DEPTH && !survives_russian_roulette(&mut throughput, rng) {
+            return col;
         }
+        ray = Ray {
+            wavelength: ray.wavelength,
+            ..Ray::new(hit_record.pos, sample.direction)
+        };
+    }
+
+    if scene.background_at_depth_limit {
+        let mut background = BLACK;
+        background_color(&ray, &mut background, &tint_opt.background_color);
+        col + throughput * background
+    } else {
+        col
     }
-    col.clamp()
 }
 
 // #[test]
@@ -1,10 +1,11 @@
 use crate::color::Color;
-#[derive(PartialEq)]
+#[derive(PartialEq, Clone, Copy)]
 pub enum BandOp {
     Sin,
     Fract,
     Mod,
 }
+#[derive(Clone, Copy)]
 pub struct TintOpt {
     pub normal_color: Color,
     pub background_color: Color,
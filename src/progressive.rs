@@ -0,0 +1,314 @@
+use crate::color::Color;
+use crate::framebuffer::Framebuffer;
+use crate::montecarlo_pimped::renderer::render_sample_pimped;
+use crate::render_error::RenderError;
+use crate::render_settings::{ProgressiveMode, RenderEngine, RenderSettings};
+use crate::renderer::render_sample;
+use crate::scene::Scene;
+use crate::setup::BLACK;
+use crate::texture::Texture;
+use crate::utils::frame_seed;
+use rayon::prelude::*;
+use std::ops::Range;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RenderProgress {
+    // number of samples added to the image so far, counted over all the pixels
+    pub samples_done: u64,
+    pub samples_total: u64,
+}
+
+impl RenderProgress {
+    pub fn fraction(&self) -> f32 {
+        if self.samples_total == 0 {
+            return 1.0;
+        }
+        self.samples_done as f32 / self.samples_total as f32
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Tile {
+    x0: u32,
+    y0: u32,
+    x1: u32,
+    y1: u32,
+}
+
+impl Tile {
+    fn area(&self) -> u64 {
+        ((self.x1 - self.x0) * (self.y1 - self.y0)) as u64
+    }
+}
+
+// Renders a frame a piece at a time into an accumulation buffer, calling back after
+// every piece with the image as it is so far. The render can be stopped from another
+// thread with the flag returned by `cancel_flag`.
+pub struct ProgressiveRenderer {
+    settings: RenderSettings,
+    accumulation: Vec<Color>,
+    sample_counts: Vec<usize>,
+    cancel: Arc<AtomicBool>,
+}
+
+impl ProgressiveRenderer {
+    pub fn new(settings: RenderSettings) -> Self {
+        let n_pixels = (settings.width * settings.height) as usize;
+        ProgressiveRenderer {
+            settings,
+            accumulation: vec![BLACK; n_pixels],
+            sample_counts: vec![0; n_pixels],
+            cancel: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    pub fn settings(&self) -> &RenderSettings {
+        &self.settings
+    }
+
+    // Set it to true to stop the render, the pieces already done are kept.
+    pub fn cancel_flag(&self) -> Arc<AtomicBool> {
+        Arc::clone(&self.cancel)
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancel.load(Ordering::Relaxed)
+    }
+
+    // The average of the samples accumulated so far. Pixels without samples are black.
+    pub fn framebuffer(&self) -> Framebuffer {
+        let pixels = self
+            .accumulation
+            .iter()
+            .zip(self.sample_counts.iter())
+            .map(|(&sum, &count)| {
+                let mut col = sum;
+                if count > 0 {
+                    col /= count as f32;
+                }
+                col
+            })
+            .collect();
+        Framebuffer::from_pixels(self.settings.width, self.settings.height, pixels)
+    }
+
+    // Renders the frame from scratch. `on_progress` is called after every pass, or after
+    // every group of tiles, with the image rendered so far.
+    pub fn render<F>(
+        &mut self,
+        scene: &Scene,
+        mut on_progress: F,
+    ) -> Result<Framebuffer, RenderError>
+    where
+        F: FnMut(&RenderProgress, &Framebuffer),
+    {
+        if self.settings.width == 0 || self.settings.height == 0 {
+            return Err(RenderError {
+                kind: String::from("settings"),
+                message: String::from("the frame must be at least one pixel wide and high"),
+            });
+        }
+        self.accumulation.fill(BLACK);
+        self.sample_counts.fill(0);
+
+        let settings = self.settings;
+        let seed = frame_seed(settings.seed, settings.current_frame);
+        match settings.engine {
+            RenderEngine::MonteCarlo => self.accumulate(
+                |x, y, s| render_sample(x, y, s, seed, &settings, scene),
+                &mut on_progress,
+            ),
+            RenderEngine::MonteCarloPimped => {
+                let noise = Texture::noise(settings.camera_distorter_opt.noise_scale, seed);
+                self.accumulate(
+                    |x, y, s| render_sample_pimped(x, y, s, seed, &settings, &noise, scene),
+                    &mut on_progress,
+                )
+            }
+        }
+        Ok(self.framebuffer())
+    }
+
+    fn accumulate<S, F>(&mut self, sample: S, on_progress: &mut F)
+    where
+        S: Fn(u32, u32, usize) -> Color + Sync,
+        F: FnMut(&RenderProgress, &Framebuffer),
+    {
+        let tiles = self.tiles();
+        let samples = self.settings.samples;
+        let samples_per_pass = self.settings.samples_per_pass.max(1);
+
+        // every piece of work is a tile and the samples to add to it
+        let (work, batch_size): (Vec<(Tile, Range<usize>)>, usize) = match self.settings.mode {
+            ProgressiveMode::Passes => (
+                (0..samples)
+                    .step_by(samples_per_pass)
+                    .flat_map(|start| {
+                        let end = (start + samples_per_pass).min(samples);
+                        tiles.iter().map(move |&tile| (tile, start..end))
+                    })
+                    .collect(),
+                tiles.len(),
+            ),
+            ProgressiveMode::Tiles => (
+                tiles.iter().map(|&tile| (tile, 0..samples)).collect(),
+                rayon::current_num_threads(),
+            ),
+        };
+
+        let mut progress = RenderProgress {
+            samples_done: 0,
+            samples_total: (self.settings.width * self.settings.height) as u64 * samples as u64,
+        };
+        for batch in work.chunks(batch_size.max(1)) {
+            let cancel = &self.cancel;
+            let rendered: Vec<(Tile, usize, Vec<Color>)> = batch
+                .par_iter()
+                .filter_map(|(tile, range)| {
+                    if cancel.load(Ordering::Relaxed) {
+                        return None;
+                    }
+                    Some((*tile, range.len(), render_tile(tile, range, &sample)))
+                })
+                .collect();
+
+            for (tile, n_samples, colors) in rendered {
+                let mut colors = colors.into_iter();
+                for y in tile.y0..tile.y1 {
+                    for x in tile.x0..tile.x1 {
+                        let index = (y * self.settings.width + x) as usize;
+                        self.accumulation[index] += colors.next().unwrap_or(BLACK);
+                        self.sample_counts[index] += n_samples;
+                    }
+                }
+                progress.samples_done += tile.area() * n_samples as u64;
+            }
+            on_progress(&progress, &self.framebuffer());
+            if self.is_cancelled() {
+                return;
+            }
+        }
+    }
+
+    fn tiles(&self) -> Vec<Tile> {
+        let size = self.settings.tile_size.max(1);
+        let mut tiles = Vec::new();
+        for y0 in (0..self.settings.height).step_by(size as usize) {
+            for x0 in (0..self.settings.width).step_by(size as usize) {
+                tiles.push(Tile {
+                    x0,
+                    y0,
+                    x1: (x0 + size).min(self.settings.width),
+                    y1: (y0 + size).min(self.settings.height),
+                });
+            }
+        }
+        tiles
+    }
+}
+
+// The sum of the samples in `range` for every pixel of the tile, row by row.
+fn render_tile<S>(tile: &Tile, range: &Range<usize>, sample: &S) -> Vec<Color>
+where
+    S: Fn(u32, u32, usize) -> Color,
+{
+    let mut colors = Vec::with_capacity(tile.area() as usize);
+    for y in tile.y0..tile.y1 {
+        for x in tile.x0..tile.x1 {
+            let mut col = BLACK;
+            for s in range.clone() {
+                col += sample(x, y, s);
+            }
+            colors.push(col);
+        }
+    }
+    colors
+}
+
+#[cfg(test)]
+fn test_scene() -> Scene {
+    use crate::camera::Camera;
+    use crate::hitable::HitableStore;
+    use crate::setup::WHITE;
+    use crate::sphere::Sphere;
+    use crate::test_utils::white_material;
+    use glam::Vec3A;
+
+    let mut world = HitableStore::new();
+    world.push(Sphere {
+        position: Vec3A::ZERO,
+        radius: 1.0,
+        mat: white_material(),
+    });
+    let camera = Camera::new(Vec3A::new(0.0, 0.0, 4.0), Vec3A::ZERO, 40.0, 1.5, 0.0);
+    Scene::new(world, camera, WHITE)
+}
+
+#[test]
+fn test_progress_is_monotonic() {
+    let scene = test_scene();
+    for mode in [ProgressiveMode::Passes, ProgressiveMode::Tiles] {
+        let settings = RenderSettings {
+            width: 12,
+            height: 8,
+            samples: 6,
+            samples_per_pass: 2,
+            tile_size: 4,
+            mode,
+            ..RenderSettings::default()
+        };
+        let mut reports = Vec::new();
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(2)
+            .build()
+            .unwrap()
+            .install(|| {
+                ProgressiveRenderer::new(settings)
+                    .render(&scene, |progress, _| reports.push(*progress))
+                    .unwrap()
+            });
+        // 3 passes over the 6 tiles, or the 6 tiles 2 at a time
+        assert_eq!(reports.len(), 3, "{mode:?}");
+        for pair in reports.windows(2) {
+            assert!(pair[1].samples_done > pair[0].samples_done, "{mode:?}");
+            assert_eq!(pair[1].samples_total, pair[0].samples_total);
+        }
+        let last = reports.last().unwrap();
+        assert_eq!(last.samples_done, 12 * 8 * 6);
+        assert_eq!(last.samples_done, last.samples_total);
+    }
+}
+
+#[test]
+fn test_cancel_stops_between_batches() {
+    let scene = test_scene();
+    let settings = RenderSettings {
+        width: 12,
+        height: 8,
+        samples: 6,
+        samples_per_pass: 1,
+        tile_size: 4,
+        ..RenderSettings::default()
+    };
+    let mut renderer = ProgressiveRenderer::new(settings);
+    let cancel = renderer.cancel_flag();
+    let mut reports = Vec::new();
+    let framebuffer = renderer
+        .render(&scene, |progress, _| {
+            reports.push(*progress);
+            if reports.len() == 2 {
+                cancel.store(true, Ordering::Relaxed);
+            }
+        })
+        .unwrap();
+
+    // the render stops after the pass during which it was cancelled, with the samples of
+    // the two passes done
+    assert!(renderer.is_cancelled());
+    assert_eq!(reports.len(), 2);
+    assert_eq!(reports[1].samples_done, 12 * 8 * 2);
+    assert!(renderer.sample_counts.iter().all(|count| *count == 2));
+    assert!(framebuffer.pixels().iter().all(|pixel| *pixel != BLACK));
+}
@@ -0,0 +1,149 @@
+use crate::aabb::Aabb;
+use crate::hitable::{HitRecord, Hitable};
+use crate::material::Material;
+use crate::ray::Ray;
+use glam::Vec3A;
+use rand::rngs::SmallRng;
+use rand::Rng;
+
+// Parallelogram with a corner in `corner` and the sides `u` and `v`. The normal is
+// u × v, the uvs go from 0 to 1 along the two sides.
+#[derive(Debug)]
+pub struct Quad {
+    corner: Vec3A,
+    u: Vec3A,
+    v: Vec3A,
+    normal: Vec3A,
+    // the plane of the quad is normal · p = d
+    d: f32,
+    // used to find the coordinates of a point of the plane along u and v
+    w: Vec3A,
+    area: f32,
+    mat: Material,
+}
+
+impl Quad {
+    pub fn new(corner: Vec3A, u: Vec3A, v: Vec3A, mat: Material) -> Self {
+        let n = u.cross(v);
+        let normal = n.normalize();
+        Quad {
+            corner,
+            u,
+            v,
+            normal,
+            d: normal.dot(corner),
+            w: n / n.dot(n),
+            area: n.length(),
+            mat,
+        }
+    }
+}
+
+impl Hitable for Quad {
+    fn hit(&self, r: &Ray, tmin: f32, tmax: f32) -> Option<HitRecord<'_>> {
+        let denom = self.normal.dot(r.direction);
+        if denom.abs() < 1e-8 {
+            return None;
+        }
+        let t = (self.d - self.normal.dot(r.origin)) / denom;
+        if t <= tmin || t >= tmax {
+            return None;
+        }
+        let pos = r.point_at_parameter(t);
+        let planar = pos - self.corner;
+        let alpha = self.w.dot(planar.cross(self.v));
+        let beta = self.w.dot(self.u.cross(planar));
+        if !(0.0..=1.0).contains(&alpha) || !(0.0..=1.0).contains(&beta) {
+            return None;
+        }
+        Some(HitRecord {
+            t,
+            pos,
+            normal: self.normal,
+            geometric_normal: self.normal,
+            mat: &self.mat,
+            u: alpha,
+            v: beta,
+            vertex_color: None,
+        })
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let corners = [
+            self.corner,
+            self.corner + self.u,
+            self.corner + self.v,
+            self.corner + self.u + self.v,
+        ];
+        let min = corners
+            .iter()
+            .fold(Vec3A::splat(f32::MAX), |min, c| min.min(*c));
+        let max = corners
+            .iter()
+            .fold(Vec3A::splat(f32::MIN), |max, c| max.max(*c));
+        // a quad in an axis aligned plane would have a flat box
+        let padding = Vec3A::splat(0.0001);
+        Some(Aabb {
+            min: min - padding,
+            max: max + padding,
+        })
+    }
+
+    fn material(&self) -> Option<&Material> {
+        Some(&self.mat)
+    }
+
+    fn pdf_value(&self, origin: Vec3A, direction: Vec3A) -> f32 {
+        match self.hit(&Ray::new(origin, direction), 0.001, f32::MAX) {
+            Some(hit) => {
+                let cosine = self.normal.dot(direction.normalize()).abs();
+                hit.t * hit.t / (cosine * self.area)
+            }
+            None => 0.0,
+        }
+    }
+
+    fn random(&self, origin: Vec3A, rng: &mut SmallRng) -> Vec3A {
+        let point = self.corner + rng.gen::<f32>() * self.u + rng.gen::<f32>() * self.v;
+        point - origin
+    }
+}
+
+#[test]
+fn test_quad_hit_and_pdf() {
+    use crate::test_utils::white_material;
+
+    let quad = Quad::new(
+        Vec3A::new(-1.0, -1.0, 0.0),
+        Vec3A::new(2.0, 0.0, 0.0),
+        Vec3A::new(0.0, 2.0, 0.0),
+        white_material(),
+    );
+    let origin = Vec3A::new(0.0, 0.0, 5.0);
+    let hit = quad
+        .hit(&Ray::new(origin, -Vec3A::Z), 0.001, f32::MAX)
+        .unwrap();
+    assert!((hit.t - 5.0).abs() < 1e-5 && hit.normal == Vec3A::Z);
+    assert!((hit.u - 0.5).abs() < 1e-5 && (hit.v - 0.5).abs() < 1e-5);
+    assert!(quad
+        .hit(
+            &Ray::new(Vec3A::new(1.5, 0.0, 5.0), -Vec3A::Z),
+            0.001,
+            f32::MAX
+        )
+        .is_none());
+
+    // straight on, the solid angle density is distance² / area
+    assert!((quad.pdf_value(origin, -Vec3A::Z) - 25.0 / 4.0).abs() < 1e-3);
+    // towards a corner the distance grows and the cosine shrinks
+    let corner = Vec3A::new(1.0, 1.0, 0.0) - origin;
+    let cosine = corner.normalize().z.abs();
+    let expected = corner.length_squared() / (cosine * 4.0);
+    let pdf = quad.pdf_value(origin, corner * 0.999 + Vec3A::new(-0.001, -0.001, 0.0));
+    assert!((pdf - expected).abs() < 0.01 * expected, "{pdf} {expected}");
+    assert_eq!(quad.pdf_value(origin, Vec3A::Z), 0.0);
+
+    let aabb = quad.bounding_box().unwrap();
+    assert!(aabb.min.z < 0.0 && aabb.max.z > 0.0);
+    assert!((aabb.min.x + 1.0).abs() < 1e-3 && (aabb.max.y - 1.0).abs() < 1e-3);
+}
@@ -3,6 +3,9 @@ use glam::Vec3A;
 pub struct Ray {
     pub origin: Vec3A,
     pub direction: Vec3A,
+    // the single wavelength, in nanometers, carried by the paths through dispersive
+    // materials. None is the whole spectrum.
+    pub wavelength: Option<f32>,
 }
 
 impl Ray {
@@ -10,9 +13,15 @@ impl Ray {
         Ray {
             origin: origin,
             direction: direction.normalize(),
+            wavelength: None,
         }
     }
 
+    pub fn with_wavelength(mut self, wavelength: f32) -> Self {
+        self.wavelength = Some(wavelength);
+        self
+    }
+
     pub fn origin(&self) -> Vec3A {
         self.origin
     }
@@ -3,6 +3,8 @@ use crate::hitable::{HitRecord, Hitable};
 use crate::material::Material;
 use crate::ray::Ray;
 use glam::Vec3A;
+use rand::rngs::SmallRng;
+use rand::Rng;
 
 #[derive(Clone, Debug)]
 enum Plane {
@@ -59,31 +61,18 @@ impl Rect {
 
 impl Hitable for Rect {
     fn hit(&self, r: &Ray, t0: f32, t1: f32) -> Option<HitRecord> {
-        let origin = r.origin();
-        let direction = r.direction();
-
-        let t = (self.k - origin[self.k_idx]) / direction[self.k_idx];
-        if t < t0 || t > t1 {
-            return None;
-        }
-
-        let a = origin[self.a_idx] + t * direction[self.a_idx];
-        if a < self.a_bound.0 || a > self.a_bound.1 {
-            return None;
-        }
-
-        let b = origin[self.b_idx] + t * direction[self.b_idx];
-        if b < self.b_bound.0 || b > self.b_bound.1 {
-            return None;
-        }
+        let axes = [self.a_idx, self.b_idx, self.k_idx];
+        let (t, u, v) = intersect(self.a_bound, self.b_bound, axes, self.k, r, t0, t1)?;
 
         let hit_rec = HitRecord{
             t,
             pos: r.point_at_parameter(t),
             normal: self.plane_normal,
+            geometric_normal: self.plane_normal,
             mat: &self.mat,
-            u:(a - self.a_bound.0) / (self.a_bound.1 - self.a_bound.0),
-            v:(b - self.b_bound.0) / (self.b_bound.1 - self.b_bound.0),
+            u,
+            v,
+            vertex_color: None,
         };
         Some(hit_rec)
     }
@@ -102,4 +91,61 @@ impl Hitable for Rect {
         };
         Some(Aabb{min, max})
     }
-}
\ No newline at end of file
+
+    fn material(&self) -> Option<&Material> {
+        Some(&self.mat)
+    }
+
+    fn pdf_value(&self, origin: Vec3A, direction: Vec3A) -> f32 {
+        match self.hit(&Ray::new(origin, direction), 0.001, f32::MAX) {
+            Some(hit) => {
+                let area = (self.a_bound.1 - self.a_bound.0) * (self.b_bound.1 - self.b_bound.0);
+                let cosine = self.plane_normal.dot(direction.normalize()).abs();
+                hit.t * hit.t / (cosine * area)
+            }
+            None => 0.0,
+        }
+    }
+
+    fn random(&self, origin: Vec3A, rng: &mut SmallRng) -> Vec3A {
+        let mut point = Vec3A::ZERO;
+        point[self.a_idx] = rng.gen_range(self.a_bound.0..self.a_bound.1);
+        point[self.b_idx] = rng.gen_range(self.b_bound.0..self.b_bound.1);
+        point[self.k_idx] = self.k;
+        point - origin
+    }
+}
+
+// The distance and the uv of the hit with the rectangle between `a_bound` and `b_bound`
+// on the axes `[a, b, k]`, at `k` on the third one.
+pub(crate) fn intersect(
+    a_bound: (f32, f32),
+    b_bound: (f32, f32),
+    [a_idx, b_idx, k_idx]: [usize; 3],
+    k: f32,
+    r: &Ray,
+    t0: f32,
+    t1: f32,
+) -> Option<(f32, f32, f32)> {
+    let origin = r.origin();
+    let direction = r.direction();
+
+    let t = (k - origin[k_idx]) / direction[k_idx];
+    if t < t0 || t > t1 {
+        return None;
+    }
+
+    let a = origin[a_idx] + t * direction[a_idx];
+    if a < a_bound.0 || a > a_bound.1 {
+        return None;
+    }
+
+    let b = origin[b_idx] + t * direction[b_idx];
+    if b < b_bound.0 || b > b_bound.1 {
+        return None;
+    }
+
+    let u = (a - a_bound.0) / (a_bound.1 - a_bound.0);
+    let v = (b - b_bound.0) / (b_bound.1 - b_bound.0);
+    Some((t, u, v))
+}
@@ -47,3 +47,30 @@ impl From<ImageError> for RenderError {
         }
     }
 }
+
+impl From<ron::error::SpannedError> for RenderError {
+    fn from(error: ron::error::SpannedError) -> Self {
+        RenderError {
+            kind: String::from("ron"),
+            message: error.to_string(),
+        }
+    }
+}
+
+impl From<ron::Error> for RenderError {
+    fn from(error: ron::Error) -> Self {
+        RenderError {
+            kind: String::from("ron"),
+            message: error.to_string(),
+        }
+    }
+}
+
+impl From<serde_json::Error> for RenderError {
+    fn from(error: serde_json::Error) -> Self {
+        RenderError {
+            kind: String::from("json"),
+            message: error.to_string(),
+        }
+    }
+}
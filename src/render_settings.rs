@@ -0,0 +1,61 @@
+use crate::camera_effects::camera_distorter_opt::CameraDistorterOpt;
+use crate::camera_effects::CameraEffects;
+use crate::montecarlo_pimped::deflection_opt::DeflectionOpt;
+use crate::montecarlo_pimped::tint_opt::TintOpt;
+
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum RenderEngine {
+    MonteCarlo,
+    MonteCarloPimped,
+}
+
+// How the progressive renderer splits the work.
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum ProgressiveMode {
+    // every pass adds `samples_per_pass` samples to the whole image
+    Passes,
+    // every tile gets all its samples before moving to the next ones
+    Tiles,
+}
+
+// Everything the renderers need to know about a frame, except the scene.
+#[derive(Clone, Copy)]
+pub struct RenderSettings {
+    pub width: u32,
+    pub height: u32,
+    pub max_depth: usize,
+    pub samples: usize,
+    pub seed: u64,
+    pub current_frame: u32,
+    pub engine: RenderEngine,
+    // used only by RenderEngine::MonteCarloPimped
+    pub camera_effects: CameraEffects,
+    pub camera_distorter_opt: CameraDistorterOpt,
+    pub deflection_opt: DeflectionOpt,
+    pub tint_opt: TintOpt,
+    // used only by the ProgressiveRenderer
+    pub mode: ProgressiveMode,
+    pub samples_per_pass: usize,
+    pub tile_size: u32,
+}
+
+impl Default for RenderSettings {
+    fn default() -> RenderSettings {
+        RenderSettings {
+            width: 640,
+            height: 400,
+            max_depth: 4,
+            samples: 4,
+            seed: 0,
+            current_frame: 0,
+            engine: RenderEngine::MonteCarlo,
+            camera_effects: CameraEffects::NoEffects,
+            camera_distorter_opt: CameraDistorterOpt::default(),
+            deflection_opt: DeflectionOpt::default(),
+            tint_opt: TintOpt::default(),
+            mode: ProgressiveMode::Passes,
+            samples_per_pass: 1,
+            tile_size: 32,
+        }
+    }
+}
@@ -1,60 +1,55 @@
 use crate::color::Color;
+use crate::framebuffer::Framebuffer;
+use crate::hitable::HitRecord;
 use crate::material::Scatterable;
+use crate::progressive::ProgressiveRenderer;
 use crate::ray::Ray;
 use crate::render_error::RenderError;
+use crate::render_settings::{RenderEngine, RenderSettings};
 use crate::scene::Scene;
-use crate::setup::{BLACK, WHITE};
-use image::ImageBuffer;
-use image::Rgba;
-use rayon::prelude::*;
+use crate::setup::{BLACK, RUSSIAN_ROULETTE_DEPTH, WHITE};
+use crate::spectrum::{sample_wavelength, wavelength_to_rgb};
+use crate::utils::sample_rng;
 
-use rand::prelude::*;
+use rand::rngs::SmallRng;
+use rand::Rng;
 //use std::path::Path;
 use std::result::Result;
 
 use crate::hitable::Hitable;
 
+// Renders all the samples of the frame in a single pass, with the montecarlo engine
 pub fn render_montecarlo(
-    frame_width: u32,
-    frame_height: u32,
-    max_depth: usize,
-    n_msaa: usize,
-    _current_frame: u32,
-    _tot_frames: u32,
+    settings: &RenderSettings,
     scene: &Scene,
-) -> Result<ImageBuffer<Rgba<u8>, Vec<u8>>, RenderError> {
-    let mut imgbuf = image::ImageBuffer::new(frame_width, frame_height);
-    let mut coords = Vec::with_capacity((frame_width * frame_height) as usize);
-    for y in 0..frame_height {
-        for x in 0..frame_width {
-            coords.push((x, y));
-        }
-    }
-    let pixels: Vec<(u32, u32, Color)> = coords
-        .par_iter()
-        .map(|&(x, y)| {
-            let flipped_y = frame_height - y; // render from bottom up to avoid image needing to be flipped
-
-            let mut col = BLACK;
+) -> Result<Framebuffer, RenderError> {
+    let settings = RenderSettings {
+        engine: RenderEngine::MonteCarlo,
+        samples_per_pass: settings.samples,
+        ..*settings
+    };
+    ProgressiveRenderer::new(settings).render(scene, |_, _| {})
+}
 
-            for _s in 0..n_msaa {
-                let mut rng = rand::thread_rng();
-                let u = ((x as f32) + rng.gen::<f32>()) / (frame_width as f32);
-                let v = ((flipped_y as f32) + rng.gen::<f32>()) / (frame_height as f32);
+// The color of one camera sample of the pixel (x, y). `seed` is the seed of the frame.
+// Every sample carries a wavelength, for the paths that go through dispersive materials.
+pub fn render_sample(
+    x: u32,
+    y: u32,
+    sample: usize,
+    seed: u64,
+    settings: &RenderSettings,
+    scene: &Scene,
+) -> Color {
+    let flipped_y = settings.height - y; // render from bottom up to avoid image needing to be flipped
 
-                let ray = scene.camera.get_ray(u, v);
-                col += color(&ray, &scene, max_depth, max_depth);
-            }
-            col /= n_msaa as f32;
-            (x, y, col)
-        })
-        .collect();
+    let mut rng = sample_rng(seed, x, y, sample);
+    let u = ((x as f32) + rng.gen::<f32>()) / (settings.width as f32);
+    let v = ((flipped_y as f32) + rng.gen::<f32>()) / (settings.height as f32);
 
-    for (x, y, col) in pixels {
-        //let rgb = to_colour(&col);
-        imgbuf.put_pixel(x, y, col.to_rgba());
-    }
-    Ok(imgbuf)
+    let ray = scene.camera.get_ray(u, v, &mut rng);
+    let ray = ray.with_wavelength(sample_wavelength(&mut rng));
+    color(&ray, scene, settings.max_depth, &mut rng)
 }
 
 // fn produce_error() -> Result<(), RenderError> {
@@ -73,37 +68,144 @@ pub fn render_montecarlo(
 //     col.blue = c.z;
 // }
 
-fn color(ray: &Ray, scene: &Scene, max_depth: usize, depth: usize) -> Color {
-    let mut col = WHITE;
-    // exit if the max depth has been reached
-    if depth <= 0 {
-        return col;
-    }
-    let hit = scene.bvh.hit(ray, 0.001, f32::MAX);
-    match hit {
-        Some(hit_record) => {
-            let scattered = hit_record.mat.scatter(ray, &hit_record);
-
-            let emitted = hit_record
-                .mat
-                .emitted(hit_record.u, hit_record.v, hit_record.pos);
-            //println!("{}", emitted);
-            match scattered {
-                Some(scatter) => {
-                    if let Some(bounce) = scatter.ray {
-                        col = emitted + scatter.color * color(&bounce, scene, max_depth, depth - 1);
-                    } else {
-                        col = emitted;
-                    }
-                }
-                None => col = emitted,
+// Iterative path tracer. `throughput` is the attenuation accumulated along the path,
+// every emission found is weighted by it. Long paths are ended with russian roulette.
+// At the non specular hits a light is sampled directly too, the two ways of reaching it
+// are weighted with multiple importance sampling. From the first dispersive surface on
+// the path carries only the wavelength of its ray, converted to RGB in the throughput.
+fn color(ray: &Ray, scene: &Scene, max_depth: usize, rng: &mut SmallRng) -> Color {
+    let mut col = BLACK;
+    let mut throughput = WHITE;
+    let mut ray = Ray {
+        wavelength: ray.wavelength,
+        ..Ray::new(ray.origin, ray.direction)
+    };
+    let mut dispersed = false;
+    // The pdf of the direction of `ray` when it left a surface where the lights were
+    // sampled too, None for the camera rays and after the specular bounces
+    let mut bsdf_pdf: Option<f32> = None;
+
+    for depth in 0..max_depth {
+        let (hit_index, hit_record) = match scene.bvh.hit_with_index(&ray, 0.001, f32::MAX) {
+            Some(hit) => hit,
+            None => return col + throughput * scene.background,
+        };
+
+        if hit_record.mat.is_emitter() {
+            let weight = match bsdf_pdf {
+                Some(pdf) => power_heuristic(pdf, light_pdf(scene, &ray, hit_index)),
+                None => 1.0,
+            };
+            col += throughput * hit_record.mat.emitted(&hit_record) * weight;
+        }
+
+        let sample = match hit_record.mat.sample(&ray, &hit_record, rng) {
+            Some(sample) => sample,
+            None => return col,
+        };
+
+        // a layered material samples its specular coat some of the times, the lights
+        // are sampled for the rest of it anyway
+        if !hit_record.mat.is_specular() && !scene.lights.is_empty() {
+            col += throughput * sample_lights(scene, &ray, &hit_record, rng);
+            bsdf_pdf = (!sample.specular).then_some(sample.pdf);
+        } else {
+            bsdf_pdf = None;
+        }
+
+        throughput = throughput * sample.weight;
+        if hit_record.mat.is_dispersive() && !dispersed {
+            if let Some(wavelength) = ray.wavelength {
+                throughput = throughput * wavelength_to_rgb(wavelength);
+                dispersed = true;
             }
         }
-        None => {
-            col  = scene.background;
+        if depth >= RUSSIAN_ROULETTE_DEPTH && !survives_russian_roulette(&mut throughput, rng) {
+            return col;
         }
+        ray = Ray {
+            wavelength: ray.wavelength,
+            ..Ray::new(hit_record.pos, sample.direction)
+        };
+    }
+
+    // the path ran out of bounces
+    if scene.background_at_depth_limit {
+        col + throughput * scene.background
+    } else {
+        col
+    }
+}
+
+// Randomly terminates paths that carry little energy. The surviving ones are scaled up
+// so that the estimate stays unbiased.
+pub fn survives_russian_roulette(throughput: &mut Color, rng: &mut SmallRng) -> bool {
+    let survival = throughput
+        .red
+        .max(throughput.green)
+        .max(throughput.blue)
+        .clamp(0.05, 1.0);
+    if rng.gen::<f32>() > survival {
+        return false;
+    }
+    *throughput /= survival;
+    true
+}
+
+// Next event estimation: picks one of the lights, and if it is visible from the hit point
+// returns the light it reflects towards the origin of `ray`.
+fn sample_lights(scene: &Scene, ray: &Ray, hit_record: &HitRecord, rng: &mut SmallRng) -> Color {
+    let light = scene
+        .bvh
+        .hitable(scene.lights[rng.gen_range(0..scene.lights.len())]);
+
+    let direction = match light.random(hit_record.pos, rng).try_normalize() {
+        Some(direction) => direction,
+        None => return BLACK,
+    };
+    let view = -ray.direction.normalize();
+    let reflected = hit_record.mat.eval(direction, view, hit_record);
+    if reflected == BLACK {
+        return BLACK;
+    }
+    let shadow_ray = Ray::new(hit_record.pos, direction);
+    let light_hit = match light.hit(&shadow_ray, 0.001, f32::MAX) {
+        Some(light_hit) => light_hit,
+        None => return BLACK,
+    };
+    if scene.bvh.hit(&shadow_ray, 0.001, light_hit.t - 0.001).is_some() {
+        return BLACK;
+    }
+
+    let pdf = light.pdf_value(hit_record.pos, direction) / scene.lights.len() as f32;
+    if pdf <= 0.0 {
+        return BLACK;
+    }
+    let emitted = light_hit.mat.emitted(&light_hit);
+    let weight = power_heuristic(pdf, hit_record.mat.pdf(direction, view, hit_record));
+    reflected * emitted * (weight / pdf)
+}
+
+// The pdf with which `sample_lights` would have chosen `ray`, that reached the hitable
+// with the index `hit_index` from a surface. Emitters inside other hitables, like the
+// parts of a mesh, are not in the lights and are never sampled.
+fn light_pdf(scene: &Scene, ray: &Ray, hit_index: usize) -> f32 {
+    if scene.lights.binary_search(&hit_index).is_err() {
+        return 0.0;
+    }
+    let light = scene.bvh.hitable(hit_index);
+    light.pdf_value(ray.origin, ray.direction) / scene.lights.len() as f32
+}
+
+// The weight of a sample taken with the pdf `a` when the same path could be taken with
+// the pdf `b`, "Optimally Combining Sampling Techniques", Veach 1995
+fn power_heuristic(a: f32, b: f32) -> f32 {
+    let (a2, b2) = (a * a, b * b);
+    if a2 + b2 > 0.0 {
+        a2 / (a2 + b2)
+    } else {
+        0.0
     }
-    col.clamp()
 }
 
 // #[test]
@@ -127,3 +229,126 @@ fn color(ray: &Ray, scene: &Scene, max_depth: usize, depth: usize) -> Color {
 //     assert_eq!(scene.width, img.width());
 //     assert_eq!(scene.height, img.height());
 // }
+
+#[test]
+fn test_render_is_deterministic() {
+    use crate::examples::{get_world_and_camera, Worlds};
+
+    let render = |threads: usize| {
+        let (world, camera, background) =
+            get_world_and_camera(&Worlds::Random, 40.0, 24, 16, 0.1, 7);
+        let scene = Scene::new(world, camera, background);
+        let settings = RenderSettings {
+            width: 24,
+            height: 16,
+            max_depth: 8,
+            samples: 2,
+            seed: 7,
+            ..RenderSettings::default()
+        };
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build()
+            .unwrap()
+            .install(|| render_montecarlo(&settings, &scene).unwrap())
+    };
+    assert!(render(1) == render(4));
+}
+
+#[test]
+fn test_light_sampling_converges_to_the_bsdf_sampling() {
+    use crate::camera::Camera;
+    use crate::hitable::HitableStore;
+    use crate::material::Material;
+    use crate::sphere::Sphere;
+    use crate::test_utils::grey;
+    use glam::Vec3A;
+    use rand::SeedableRng;
+
+    // a white light of radius 1 two units above a grey floor, on a black background. The
+    // floor right below it reflects albedo * emission * radius² / distance²
+    let mut world = HitableStore::new();
+    world.push(Sphere {
+        position: Vec3A::new(0.0, -1000.0, 0.0),
+        radius: 1000.0,
+        mat: Material::lambertian(grey(0.5)),
+    });
+    world.push(Sphere {
+        position: Vec3A::new(0.0, 2.0, 0.0),
+        radius: 1.0,
+        mat: Material::diffuse_light(grey(4.0)),
+    });
+    let camera = Camera::new(Vec3A::new(0.0, 1.0, 3.0), Vec3A::ZERO, 40.0, 1.0, 0.0);
+    let with_lights = Scene::new(world, camera, BLACK);
+    // without lights to sample every emission is found by the BSDF samples
+    let without_lights = Scene {
+        lights: vec![],
+        ..with_lights.clone()
+    };
+
+    let ray = Ray::new(Vec3A::new(0.0, 1.0, 3.0), Vec3A::new(0.0, -1.0, -3.0));
+    let n = 40000;
+    for scene in [&with_lights, &without_lights] {
+        let mut rng = SmallRng::seed_from_u64(3);
+        let radiance = (0..n)
+            .map(|_| color(&ray, scene, 8, &mut rng).green)
+            .sum::<f32>()
+            / n as f32;
+        assert!(
+            (radiance - 0.5).abs() < 0.02,
+            "{} lights: {radiance}",
+            scene.lights.len()
+        );
+    }
+}
+
+#[test]
+fn test_russian_roulette_and_depth_limit_are_unbiased() {
+    use crate::camera::Camera;
+    use crate::hitable::HitableStore;
+    use crate::material::Material;
+    use crate::sphere::Sphere;
+    use crate::test_utils::grey;
+    use glam::Vec3A;
+    use rand::SeedableRng;
+
+    let mut rng = SmallRng::seed_from_u64(5);
+    let n = 100000;
+    let survivors: f32 = (0..n)
+        .map(|_| {
+            let mut throughput = Color::new(0.3, 0.1, 0.01);
+            if survives_russian_roulette(&mut throughput, &mut rng) {
+                throughput.green
+            } else {
+                0.0
+            }
+        })
+        .sum();
+    assert!((survivors / n as f32 - 0.1).abs() < 0.003);
+
+    // inside a closed sphere the paths never escape, they all stop at the depth limit or
+    // at the roulette. At the limit they see the background, after `max_depth` bounces
+    let mut world = HitableStore::new();
+    world.push(Sphere {
+        position: Vec3A::ZERO,
+        // the negative radius turns the normals inwards
+        radius: -5.0,
+        mat: Material::lambertian(grey(0.7)),
+    });
+    let camera = Camera::new(Vec3A::ZERO, Vec3A::Z, 40.0, 1.0, 0.0);
+    let scene = Scene {
+        background_at_depth_limit: true,
+        ..Scene::new(world, camera, WHITE)
+    };
+    let max_depth = 8;
+    let ray = Ray::new(Vec3A::ZERO, Vec3A::Z);
+    let radiance = (0..n)
+        .map(|_| color(&ray, &scene, max_depth, &mut rng).green)
+        .sum::<f32>()
+        / n as f32;
+    let expected = 0.7f32.powi(max_depth as i32);
+    assert!(
+        (radiance - expected).abs() < 0.05 * expected,
+        "{radiance} {expected}"
+    );
+}
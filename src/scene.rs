@@ -2,6 +2,7 @@ use crate::bvh::BvhTree;
 use crate::camera::Camera;
 use crate::color::Color;
 use crate::hitable::HitableStore;
+use std::sync::Arc;
 
 // use crate::color::Color;
 // use crate::disc::Disc;
@@ -10,20 +11,39 @@ use crate::hitable::HitableStore;
 // use crate::texture::Texture;
 // use glam::Vec3A;
 
-pub struct Scene<'a> {
+// The BVH is shared, cloning a scene is cheap. To render another frame of the same
+// world, clone the scene and change its camera.
+#[derive(Clone)]
+pub struct Scene {
     pub camera: Camera,
-    pub bvh: BvhTree<'a>,
+    pub bvh: Arc<BvhTree>,
     pub background: Color,
-
+    // indices in the bvh of the emissive hitables, in increasing order, sampled directly
+    // by the renderer at every hit that is not specular
+    pub lights: Vec<usize>,
+    // when true, the paths that reach the max depth see the background instead of black
+    pub background_at_depth_limit: bool,
 }
 
-impl<'a> Scene<'a> {
-    pub fn new(models: &'a mut HitableStore, camera: Camera, background:Color) -> Scene<'a> {
+impl Scene {
+    pub fn new(models: HitableStore, camera: Camera, background: Color) -> Scene {
+        Scene::from_bvh(Arc::new(BvhTree::new(models)), camera, background)
+    }
+
+    // A scene reusing a BVH already built
+    pub fn from_bvh(bvh: Arc<BvhTree>, camera: Camera, background: Color) -> Scene {
+        let lights = bvh
+            .hitables()
+            .enumerate()
+            .filter(|(_, hitable)| hitable.material().is_some_and(|mat| mat.is_emitter()))
+            .map(|(index, _)| index)
+            .collect();
         Scene {
             camera,
-            bvh: BvhTree::new(models),
+            bvh,
             background,
-            //lights: vec![],
+            lights,
+            background_at_depth_limit: false,
         }
     }
 
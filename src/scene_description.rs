@@ -0,0 +1,897 @@
+use crate::animation::Animation;
+use crate::camera::{Camera, FULL_FRAME_SENSOR};
+use crate::color::Color;
+use crate::cone::Cone;
+use crate::cuboid::Cuboid;
+use crate::cylinder::Cylinder;
+use crate::disc::Disc;
+use crate::gltf_import::{load_gltf, GltfOpt};
+use crate::bvh::BvhTree;
+use crate::hitable::{Hitable, HitableStore};
+use crate::material::{Dielectric, Dispersion, Material};
+use crate::mesh_import::{load_ply_to_hitable, load_stl_to_hitable};
+use crate::quad::Quad;
+use crate::rect::Rect;
+use crate::render_error::RenderError;
+use crate::sdf::{SdfTree, TracedSDF};
+use crate::sphere::Sphere;
+use crate::texture::Texture;
+use crate::torus::Torus;
+use crate::transform::{affine, Transformed};
+use crate::triangle::Triangle;
+use crate::utils::load_obj_to_hitable;
+use glam::{Quat, Vec3A};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+// A world that can be written by hand and rendered without recompiling.
+// Scene files are RON or JSON, the format is given by the extension of the file.
+// Objects refer to the materials by name, relative paths are resolved from the
+// folder of the scene file.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SceneDescription {
+    pub camera: CameraDescription,
+    #[serde(default)]
+    pub background: [f32; 3],
+    #[serde(default)]
+    pub materials: BTreeMap<String, MaterialDescription>,
+    #[serde(default)]
+    pub objects: Vec<ObjectDescription>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub animation: Option<Animation>,
+}
+
+// `focal_length`, in millimeters, replaces `fov` and frames the `sensor`. The focus is
+// on the look at point, unless there is a `focus_distance` or a `focus_point`. `shift`
+// moves the lens parallel to the sensor, in millimeters, `tilt` tilts the focus plane
+// in degrees around the horizontal and the vertical axes of the frame.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CameraDescription {
+    pub look_from: [f32; 3],
+    pub look_at: [f32; 3],
+    pub fov: f32,
+    #[serde(default)]
+    pub aperture: f32,
+    #[serde(default = "default_up")]
+    pub up: [f32; 3],
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub focal_length: Option<f32>,
+    #[serde(default = "default_sensor")]
+    pub sensor: [f32; 2],
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub focus_distance: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub focus_point: Option<[f32; 3]>,
+    #[serde(default)]
+    pub shift: [f32; 2],
+    #[serde(default)]
+    pub tilt: [f32; 2],
+}
+
+impl Default for CameraDescription {
+    fn default() -> Self {
+        CameraDescription {
+            look_from: [0.0, 0.0, 5.0],
+            look_at: [0.0, 0.0, 0.0],
+            fov: 40.0,
+            aperture: 0.0,
+            up: default_up(),
+            focal_length: None,
+            sensor: default_sensor(),
+            focus_distance: None,
+            focus_point: None,
+            shift: [0.0, 0.0],
+            tilt: [0.0, 0.0],
+        }
+    }
+}
+
+fn default_up() -> [f32; 3] {
+    [0.0, 1.0, 0.0]
+}
+
+fn default_sensor() -> [f32; 2] {
+    FULL_FRAME_SENSOR.into()
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum TextureDescription {
+    Constant([f32; 3]),
+    Checker {
+        squares: usize,
+        odd: [f32; 3],
+        even: [f32; 3],
+    },
+    Noise {
+        scale: f32,
+        seed: u64,
+    },
+    Bitmap {
+        path: PathBuf,
+    },
+    // the colors of the vertices of a ply mesh, the color is used elsewhere
+    VertexColor([f32; 3]),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum MaterialDescription {
+    Lambertian {
+        albedo: TextureDescription,
+    },
+    Metal {
+        albedo: TextureDescription,
+        fuzz: f32,
+    },
+    // `absorption` is per unit of distance inside the medium, `roughness` frosts the glass
+    // and `dispersion` splits the light in its colors
+    Dielectric {
+        index_of_refraction: f32,
+        #[serde(default = "default_tint")]
+        tint: TextureDescription,
+        #[serde(default)]
+        absorption: [f32; 3],
+        #[serde(default)]
+        roughness: f32,
+        #[serde(default)]
+        dispersion: Option<DispersionDescription>,
+    },
+    DiffuseLight {
+        emit: TextureDescription,
+    },
+    // GGX metallic-roughness, like the materials of glTF and Blender. `specular` scales
+    // the reflectance of the dielectric part, 0.5 is 4%.
+    Microfacet {
+        base_color: TextureDescription,
+        #[serde(default = "default_metallic")]
+        metallic: TextureDescription,
+        #[serde(default = "default_roughness")]
+        roughness: TextureDescription,
+        #[serde(default = "default_specular")]
+        specular: TextureDescription,
+    },
+    // `b` where the red channel of `mask` is 1, `a` where it is 0
+    Mix {
+        a: Box<MaterialDescription>,
+        b: Box<MaterialDescription>,
+        mask: TextureDescription,
+    },
+    // a smooth dielectric coat over the base
+    Clearcoat {
+        base: Box<MaterialDescription>,
+        #[serde(default = "default_coat_index_of_refraction")]
+        index_of_refraction: f32,
+    },
+}
+
+// The index of refraction per wavelength, the coefficients are for wavelengths in
+// micrometers. CrownGlass and FlintGlass are BK7 and SF11.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum DispersionDescription {
+    Cauchy { a: f32, b: f32 },
+    Sellmeier { b: [f32; 3], c: [f32; 3] },
+    CrownGlass,
+    FlintGlass,
+}
+
+fn default_tint() -> TextureDescription {
+    TextureDescription::Constant([1.0; 3])
+}
+
+fn default_metallic() -> TextureDescription {
+    TextureDescription::Constant([0.0; 3])
+}
+
+fn default_roughness() -> TextureDescription {
+    TextureDescription::Constant([0.5; 3])
+}
+
+fn default_specular() -> TextureDescription {
+    TextureDescription::Constant([0.5; 3])
+}
+
+fn default_coat_index_of_refraction() -> f32 {
+    1.5
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub enum RectPlane {
+    XY,
+    YZ,
+    XZ,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum ObjectDescription {
+    Sphere {
+        center: [f32; 3],
+        radius: f32,
+        material: String,
+    },
+    Disc {
+        center: [f32; 3],
+        normal: [f32; 3],
+        radius: f32,
+        material: String,
+    },
+    // axis aligned rectangle, `a` and `b` are the bounds on the two axes of the plane,
+    // `k` the position on the third one
+    Rect {
+        plane: RectPlane,
+        a: (f32, f32),
+        b: (f32, f32),
+        k: f32,
+        #[serde(default)]
+        flip_normals: bool,
+        material: String,
+    },
+    Triangle {
+        vertices: [[f32; 3]; 3],
+        material: String,
+    },
+    // axis aligned box, wrap it in Instances to rotate it
+    Cuboid {
+        min: [f32; 3],
+        max: [f32; 3],
+        material: String,
+    },
+    // `center` is the center of the base, the axis goes up along y
+    Cylinder {
+        center: [f32; 3],
+        radius: f32,
+        height: f32,
+        #[serde(default = "default_capped")]
+        capped: bool,
+        material: String,
+    },
+    // `center` is the center of the base, the apex is `height` above it
+    Cone {
+        center: [f32; 3],
+        radius: f32,
+        height: f32,
+        #[serde(default = "default_capped")]
+        capped: bool,
+        material: String,
+    },
+    // lying on the xz plane, `thickness` is the radius of the tube
+    Torus {
+        center: [f32; 3],
+        radius: f32,
+        thickness: f32,
+        material: String,
+    },
+    // parallelogram with the sides `u` and `v` starting from `corner`
+    Quad {
+        corner: [f32; 3],
+        u: [f32; 3],
+        v: [f32; 3],
+        material: String,
+    },
+    // an obj file, with the materials of its mtl file
+    Obj {
+        path: PathBuf,
+    },
+    // a ply file, colored by its vertices when they have colors and grey otherwise
+    Ply {
+        path: PathBuf,
+    },
+    // an stl file, grey. Wrap it in Instances to give it a material.
+    Stl {
+        path: PathBuf,
+    },
+    // the meshes and the lights of a gltf or glb file, its cameras are ignored
+    Gltf {
+        path: PathBuf,
+    },
+    // the aabb has to contain the whole distance field
+    Sdf {
+        sdf: SdfDescription,
+        material: String,
+        aabb_center: [f32; 3],
+        aabb_radius: f32,
+    },
+    // copies of an object, each one moved, rotated and scaled. The object is built once,
+    // in its own BVH when it has more than one hitable, and shared by the copies.
+    Instances {
+        object: Box<ObjectDescription>,
+        instances: Vec<InstanceDescription>,
+    },
+}
+
+// Scale, then rotation around `axis`, then translation. `material` replaces the
+// materials of the object.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InstanceDescription {
+    #[serde(default)]
+    pub translate: [f32; 3],
+    #[serde(default = "default_axis")]
+    pub axis: [f32; 3],
+    #[serde(default)]
+    pub degrees: f32,
+    #[serde(default = "default_scale")]
+    pub scale: [f32; 3],
+    #[serde(default)]
+    pub material: Option<String>,
+}
+
+fn default_capped() -> bool {
+    true
+}
+
+fn default_axis() -> [f32; 3] {
+    [0.0, 1.0, 0.0]
+}
+
+fn default_scale() -> [f32; 3] {
+    [1.0, 1.0, 1.0]
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum SdfDescription {
+    Sphere {
+        radius: f32,
+    },
+    Cuboid {
+        half_extents: [f32; 3],
+    },
+    Torus {
+        radius: f32,
+        thickness: f32,
+    },
+    Union {
+        a: Box<SdfDescription>,
+        b: Box<SdfDescription>,
+    },
+    UnionSmooth {
+        a: Box<SdfDescription>,
+        b: Box<SdfDescription>,
+        softness: f32,
+    },
+    // `a` minus `b`
+    Subtract {
+        a: Box<SdfDescription>,
+        b: Box<SdfDescription>,
+    },
+    Intersection {
+        a: Box<SdfDescription>,
+        b: Box<SdfDescription>,
+    },
+    Translate {
+        sdf: Box<SdfDescription>,
+        offset: [f32; 3],
+    },
+    Rotate {
+        sdf: Box<SdfDescription>,
+        axis: [f32; 3],
+        degrees: f32,
+    },
+    Scale {
+        sdf: Box<SdfDescription>,
+        factor: f32,
+    },
+    Round {
+        sdf: Box<SdfDescription>,
+        radius: f32,
+    },
+}
+
+impl SceneDescription {
+    pub fn load(path: &Path) -> Result<SceneDescription, RenderError> {
+        let text = fs::read_to_string(path)?;
+        if is_json(path) {
+            Ok(serde_json::from_str(&text)?)
+        } else {
+            Ok(ron::from_str(&text)?)
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), RenderError> {
+        let text = if is_json(path) {
+            serde_json::to_string_pretty(self)?
+        } else {
+            ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())?
+        };
+        fs::write(path, text)?;
+        Ok(())
+    }
+
+    // The hitables, the camera and the background, like examples::get_world_and_camera.
+    // Relative paths are resolved from `base_dir`.
+    pub fn build(
+        &self,
+        frame_width: u32,
+        frame_height: u32,
+        base_dir: &Path,
+    ) -> Result<(HitableStore, Camera, Color), RenderError> {
+        let (hitables, background) = self.build_world(base_dir)?;
+        let camera = self.camera_at(0).to_camera(frame_width, frame_height);
+        Ok((hitables, camera, background))
+    }
+
+    // The camera at `frame` of the animation of the scene
+    pub fn camera_at(&self, frame: u32) -> CameraDescription {
+        match &self.animation {
+            Some(animation) => animation.camera_at(frame, &self.camera),
+            None => self.camera.clone(),
+        }
+    }
+
+    // The hitables and the background
+    pub fn build_world(&self, base_dir: &Path) -> Result<(HitableStore, Color), RenderError> {
+        let mut materials = BTreeMap::new();
+        for (name, description) in &self.materials {
+            materials.insert(name.as_str(), description.to_material(base_dir)?);
+        }
+        let material = |name: &str| -> Result<Material, RenderError> {
+            materials.get(name).cloned().ok_or_else(|| RenderError {
+                kind: String::from("scene"),
+                message: format!("unknown material {}", name),
+            })
+        };
+
+        let mut hitables = HitableStore::new();
+        for object in &self.objects {
+            object.build(&material, base_dir, &mut hitables)?;
+        }
+
+        Ok((hitables, color(self.background)))
+    }
+}
+
+impl ObjectDescription {
+    // Adds the hitables of the object to `hitables`, `material` finds the materials by name
+    fn build<M>(
+        &self,
+        material: &M,
+        base_dir: &Path,
+        hitables: &mut HitableStore,
+    ) -> Result<(), RenderError>
+    where
+        M: Fn(&str) -> Result<Material, RenderError>,
+    {
+        match self {
+            ObjectDescription::Sphere {
+                center,
+                radius,
+                material: name,
+            } => hitables.push(Sphere {
+                position: vec3(*center),
+                radius: *radius,
+                mat: material(name)?,
+            }),
+            ObjectDescription::Disc {
+                center,
+                normal,
+                radius,
+                material: name,
+            } => hitables.push(Disc {
+                position: vec3(*center),
+                normal: vec3(*normal).normalize(),
+                radius: *radius,
+                mat: material(name)?,
+            }),
+            ObjectDescription::Rect {
+                plane,
+                a,
+                b,
+                k,
+                flip_normals,
+                material: name,
+            } => {
+                let mat = material(name)?;
+                let mut rect = match plane {
+                    RectPlane::XY => Rect::new_xy(*a, *b, *k, mat),
+                    RectPlane::YZ => Rect::new_yz(*a, *b, *k, mat),
+                    RectPlane::XZ => Rect::new_xz(*a, *b, *k, mat),
+                };
+                if *flip_normals {
+                    rect.flip_normals();
+                }
+                hitables.push(rect);
+            }
+            ObjectDescription::Triangle {
+                vertices,
+                material: name,
+            } => hitables.push(Triangle::new(
+                vec3(vertices[0]),
+                vec3(vertices[1]),
+                vec3(vertices[2]),
+                Arc::new(material(name)?),
+            )),
+            ObjectDescription::Cuboid {
+                min,
+                max,
+                material: name,
+            } => hitables.push(Cuboid::new(vec3(*min), vec3(*max), material(name)?)),
+            ObjectDescription::Cylinder {
+                center,
+                radius,
+                height,
+                capped,
+                material: name,
+            } => hitables.push(Cylinder {
+                position: vec3(*center),
+                radius: *radius,
+                height: *height,
+                capped: *capped,
+                mat: material(name)?,
+            }),
+            ObjectDescription::Cone {
+                center,
+                radius,
+                height,
+                capped,
+                material: name,
+            } => hitables.push(Cone {
+                position: vec3(*center),
+                radius: *radius,
+                height: *height,
+                capped: *capped,
+                mat: material(name)?,
+            }),
+            ObjectDescription::Torus {
+                center,
+                radius,
+                thickness,
+                material: name,
+            } => hitables.push(Torus {
+                position: vec3(*center),
+                radius: *radius,
+                thickness: *thickness,
+                mat: material(name)?,
+            }),
+            ObjectDescription::Quad {
+                corner,
+                u,
+                v,
+                material: name,
+            } => hitables.push(Quad::new(
+                vec3(*corner),
+                vec3(*u),
+                vec3(*v),
+                material(name)?,
+            )),
+            ObjectDescription::Obj { path } => {
+                let mut obj = load_obj_to_hitable(&base_dir.join(path))?;
+                hitables.append(&mut obj);
+            }
+            ObjectDescription::Ply { path } => {
+                let mut ply = load_ply_to_hitable(&base_dir.join(path))?;
+                hitables.append(&mut ply);
+            }
+            ObjectDescription::Stl { path } => {
+                let mut stl = load_stl_to_hitable(&base_dir.join(path))?;
+                hitables.append(&mut stl);
+            }
+            ObjectDescription::Gltf { path } => {
+                // the warnings are reported only for the glTF files opened on their own
+                let mut gltf = load_gltf(&base_dir.join(path), &GltfOpt::default())?;
+                hitables.append(&mut gltf.hitables);
+            }
+            ObjectDescription::Sdf {
+                sdf,
+                material: name,
+                aabb_center,
+                aabb_radius,
+            } => hitables.push(TracedSDF::new(
+                sdf.to_sdf_tree(),
+                material(name)?,
+                *aabb_radius,
+                vec3(*aabb_center),
+            )),
+            ObjectDescription::Instances { object, instances } => {
+                let mut store = HitableStore::new();
+                object.build(material, base_dir, &mut store)?;
+                let shared: Arc<dyn Hitable + Send + Sync> = match store.len() {
+                    1 => Arc::from(store.remove(0)),
+                    _ => Arc::new(BvhTree::new(store)),
+                };
+                for instance in instances {
+                    let transform = affine(
+                        vec3(instance.translate),
+                        vec3(instance.axis),
+                        instance.degrees,
+                        vec3(instance.scale),
+                    );
+                    let mut copy = Transformed::new(Arc::clone(&shared), transform);
+                    if let Some(name) = &instance.material {
+                        copy = copy.with_material(material(name)?);
+                    }
+                    hitables.push(copy);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl CameraDescription {
+    pub fn to_camera(&self, frame_width: u32, frame_height: u32) -> Camera {
+        let mut camera = Camera::builder(vec3(self.look_from), vec3(self.look_at))
+            .with_up(vec3(self.up))
+            .with_fov(self.fov)
+            .with_sensor(self.sensor[0], self.sensor[1])
+            .with_aspect((frame_width as f32) / (frame_height as f32))
+            .with_aperture(self.aperture)
+            .with_shift(self.shift[0], self.shift[1])
+            .with_tilt(self.tilt[0], self.tilt[1]);
+        if let Some(focal_length) = self.focal_length {
+            camera = camera.with_focal_length(focal_length);
+        }
+        if let Some(distance) = self.focus_distance {
+            camera = camera.with_focus_distance(distance);
+        }
+        if let Some(point) = self.focus_point {
+            camera = camera.with_focus_point(vec3(point));
+        }
+        camera.build()
+    }
+}
+
+// Loads a scene file and builds its world, with the paths relative to the scene file.
+pub fn load_scene(
+    path: &Path,
+    frame_width: u32,
+    frame_height: u32,
+) -> Result<(HitableStore, Camera, Color), RenderError> {
+    let base_dir = path.parent().unwrap_or_else(|| Path::new(""));
+    SceneDescription::load(path)?.build(frame_width, frame_height, base_dir)
+}
+
+impl TextureDescription {
+    pub fn to_texture(&self, base_dir: &Path) -> Result<Texture, RenderError> {
+        Ok(match self {
+            TextureDescription::Constant(col) => Texture::constant_color(color(*col)),
+            TextureDescription::Checker { squares, odd, even } => {
+                Texture::checker(*squares, color(*odd), color(*even))
+            }
+            TextureDescription::Noise { scale, seed } => Texture::noise(*scale, *seed),
+            TextureDescription::Bitmap { path } => Texture::bitmap(&base_dir.join(path))?,
+            TextureDescription::VertexColor(col) => Texture::vertex_color(color(*col)),
+        })
+    }
+}
+
+impl MaterialDescription {
+    pub fn to_material(&self, base_dir: &Path) -> Result<Material, RenderError> {
+        Ok(match self {
+            MaterialDescription::Lambertian { albedo } => {
+                Material::lambertian(albedo.to_texture(base_dir)?)
+            }
+            MaterialDescription::Metal { albedo, fuzz } => {
+                Material::metal(albedo.to_texture(base_dir)?, *fuzz)
+            }
+            MaterialDescription::Dielectric {
+                index_of_refraction,
+                tint,
+                absorption,
+                roughness,
+                dispersion,
+            } => {
+                let dielectric = Dielectric::new(*index_of_refraction)
+                    .with_tint(tint.to_texture(base_dir)?)
+                    .with_absorption(color(*absorption))
+                    .with_roughness(*roughness);
+                Material::Dielectric(match dispersion {
+                    Some(dispersion) => dielectric.with_dispersion(dispersion.to_dispersion()),
+                    None => dielectric,
+                })
+            }
+            MaterialDescription::DiffuseLight { emit } => {
+                Material::diffuse_light(emit.to_texture(base_dir)?)
+            }
+            MaterialDescription::Microfacet {
+                base_color,
+                metallic,
+                roughness,
+                specular,
+            } => Material::microfacet(
+                base_color.to_texture(base_dir)?,
+                metallic.to_texture(base_dir)?,
+                roughness.to_texture(base_dir)?,
+                specular.to_texture(base_dir)?,
+            ),
+            MaterialDescription::Mix { a, b, mask } => Material::mix(
+                a.to_material(base_dir)?,
+                b.to_material(base_dir)?,
+                mask.to_texture(base_dir)?,
+            ),
+            MaterialDescription::Clearcoat {
+                base,
+                index_of_refraction,
+            } => Material::clearcoat(base.to_material(base_dir)?, *index_of_refraction),
+        })
+    }
+}
+
+impl DispersionDescription {
+    pub fn to_dispersion(&self) -> Dispersion {
+        match self {
+            DispersionDescription::Cauchy { a, b } => Dispersion::Cauchy { a: *a, b: *b },
+            DispersionDescription::Sellmeier { b, c } => Dispersion::Sellmeier { b: *b, c: *c },
+            DispersionDescription::CrownGlass => Dispersion::CROWN_GLASS,
+            DispersionDescription::FlintGlass => Dispersion::FLINT_GLASS,
+        }
+    }
+}
+
+impl SdfDescription {
+    pub fn to_sdf_tree(&self) -> SdfTree {
+        let tree = |sdf: &SdfDescription| Box::new(sdf.to_sdf_tree());
+        match self {
+            SdfDescription::Sphere { radius } => SdfTree::Sphere { radius: *radius },
+            SdfDescription::Cuboid { half_extents } => SdfTree::Cuboid {
+                half_extents: vec3(*half_extents),
+            },
+            SdfDescription::Torus { radius, thickness } => SdfTree::Torus {
+                radius: *radius,
+                thickness: *thickness,
+            },
+            SdfDescription::Union { a, b } => SdfTree::Union(tree(a), tree(b)),
+            SdfDescription::UnionSmooth { a, b, softness } => {
+                SdfTree::UnionSmooth(tree(a), tree(b), *softness)
+            }
+            SdfDescription::Subtract { a, b } => SdfTree::Subtract(tree(a), tree(b)),
+            SdfDescription::Intersection { a, b } => SdfTree::Intersection(tree(a), tree(b)),
+            SdfDescription::Translate { sdf, offset } => {
+                SdfTree::Translate(tree(sdf), vec3(*offset))
+            }
+            SdfDescription::Rotate { sdf, axis, degrees } => SdfTree::Rotate(
+                tree(sdf),
+                Quat::from_axis_angle(vec3(*axis).normalize().into(), degrees.to_radians()),
+            ),
+            SdfDescription::Scale { sdf, factor } => SdfTree::Scale(tree(sdf), *factor),
+            SdfDescription::Round { sdf, radius } => SdfTree::Round(tree(sdf), *radius),
+        }
+    }
+}
+
+fn is_json(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("json"))
+}
+
+fn vec3(v: [f32; 3]) -> Vec3A {
+    Vec3A::from(v)
+}
+
+fn color(c: [f32; 3]) -> Color {
+    Color::new(c[0], c[1], c[2])
+}
+
+#[test]
+fn test_example_scenes_load() {
+    let scenes = Path::new(env!("CARGO_MANIFEST_DIR")).join("app/assets/scenes");
+    for file in [
+        "cornell_box.ron",
+        "sdf_spheres.ron",
+        "instances.ron",
+        "microfacet_spheres.ron",
+        "dispersion.ron",
+        "layered.ron",
+    ] {
+        let (world, _, _) = load_scene(&scenes.join(file), 32, 32).unwrap();
+        assert!(!world.is_empty());
+    }
+}
+
+#[test]
+fn test_scene_save_load_round_trip() {
+    use crate::animation::{Interpolation, Track};
+
+    // every optional field away from its default, so a field dropped by save or by load
+    // shows up in the comparison
+    let constant = TextureDescription::Constant;
+    let mut fov = Track::new();
+    fov.add_key(0, 40.0, Interpolation::Smooth);
+    fov.add_key(24, 25.0, Interpolation::Linear);
+    let mut look_from = Track::new();
+    look_from.add_key(12, [1.0, 2.0, 3.0], Interpolation::CatmullRom);
+    let scene = SceneDescription {
+        camera: CameraDescription {
+            look_from: [1.0, 2.0, 3.0],
+            look_at: [0.0, 0.5, 0.0],
+            fov: 30.0,
+            aperture: 0.25,
+            up: [0.0, 0.0, 1.0],
+            focal_length: Some(50.0),
+            sensor: [24.0, 16.0],
+            focus_distance: Some(4.5),
+            focus_point: Some([0.0, 1.0, 0.0]),
+            shift: [1.0, -2.0],
+            tilt: [3.0, 0.5],
+        },
+        background: [0.1, 0.2, 0.3],
+        materials: BTreeMap::from([
+            (
+                String::from("glass"),
+                MaterialDescription::Dielectric {
+                    index_of_refraction: 1.45,
+                    tint: constant([0.9, 1.0, 0.8]),
+                    absorption: [0.5, 0.25, 0.0],
+                    roughness: 0.2,
+                    dispersion: Some(DispersionDescription::Cauchy { a: 1.5, b: 0.004 }),
+                },
+            ),
+            (
+                String::from("metal"),
+                MaterialDescription::Metal {
+                    albedo: TextureDescription::Checker {
+                        squares: 4,
+                        odd: [1.0; 3],
+                        even: [0.0; 3],
+                    },
+                    fuzz: 0.3,
+                },
+            ),
+            (
+                String::from("paint"),
+                MaterialDescription::Clearcoat {
+                    base: Box::new(MaterialDescription::Mix {
+                        a: Box::new(MaterialDescription::Microfacet {
+                            base_color: TextureDescription::Checker {
+                                squares: 4,
+                                odd: [1.0; 3],
+                                even: [0.0; 3],
+                            },
+                            metallic: constant([1.0; 3]),
+                            roughness: constant([0.1; 3]),
+                            specular: constant([0.75; 3]),
+                        }),
+                        b: Box::new(MaterialDescription::Lambertian {
+                            albedo: TextureDescription::Noise {
+                                scale: 2.0,
+                                seed: 7,
+                            },
+                        }),
+                        mask: constant([0.25; 3]),
+                    }),
+                    index_of_refraction: 1.3,
+                },
+            ),
+        ]),
+        objects: vec![
+            ObjectDescription::Rect {
+                plane: RectPlane::XZ,
+                a: (-1.0, 1.0),
+                b: (-2.0, 2.0),
+                k: 0.5,
+                flip_normals: true,
+                material: String::from("glass"),
+            },
+            ObjectDescription::Instances {
+                object: Box::new(ObjectDescription::Cone {
+                    center: [0.0; 3],
+                    radius: 1.0,
+                    height: 2.0,
+                    capped: false,
+                    material: String::from("paint"),
+                }),
+                instances: vec![InstanceDescription {
+                    translate: [1.0, 0.0, -1.0],
+                    axis: [1.0, 0.0, 0.0],
+                    degrees: 45.0,
+                    scale: [2.0, 1.0, 0.5],
+                    material: Some(String::from("glass")),
+                }],
+            },
+        ],
+        animation: Some(Animation {
+            fov,
+            look_from,
+            ..Animation::default()
+        }),
+    };
+
+    let dir = std::env::temp_dir().join("tuot_scene_test");
+    fs::create_dir_all(&dir).unwrap();
+    for file in ["scene.ron", "scene.json"] {
+        let path = dir.join(file);
+        scene.save(&path).unwrap();
+        let loaded = SceneDescription::load(&path).unwrap();
+        assert_eq!(format!("{loaded:?}"), format!("{scene:?}"), "{file}");
+    }
+}
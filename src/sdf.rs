@@ -3,7 +3,7 @@ use crate::hitable::{HitRecord, Hitable};
 use crate::material::Material;
 use crate::ray::Ray;
 //use crate::setup::SDF_DETAIL_SCALE;
-use glam::Vec3A;
+use glam::{Quat, Vec3A};
 use sdfu::SDF;
 
 //use sdfu::*;
@@ -12,6 +12,73 @@ const MAX_MARCHES: u32 = 256;
 const EPSILON: f32 = 0.0001;
 //const MAX_VIS_MARCHES: u32 = 100;
 
+// Anything that can be ray marched by TracedSDF. Every sdfu SDF is a DistanceField,
+// SdfTree is one too.
+pub trait DistanceField {
+    fn distance(&self, p: Vec3A) -> f32;
+
+    // Tetrahedral estimation of the gradient, the same used by sdfu::SDF::normals_fast
+    fn normal_at(&self, p: Vec3A, eps: f32) -> Vec3A {
+        let xyy = Vec3A::new(1.0, -1.0, -1.0);
+        let yyx = Vec3A::new(-1.0, -1.0, 1.0);
+        let yxy = Vec3A::new(-1.0, 1.0, -1.0);
+        let xxx = Vec3A::ONE;
+        (xyy * self.distance(p + xyy * eps)
+            + yyx * self.distance(p + yyx * eps)
+            + yxy * self.distance(p + yxy * eps)
+            + xxx * self.distance(p + xxx * eps))
+        .normalize()
+    }
+}
+
+impl<S: SDF<f32, Vec3A>> DistanceField for S {
+    fn distance(&self, p: Vec3A) -> f32 {
+        SDF::dist(self, p)
+    }
+}
+
+// An SDF built at runtime, for example from a scene file. The sdfu combinators are
+// generic types that have to be known at compile time, this tree is evaluated node by node.
+#[derive(Debug, Clone)]
+pub enum SdfTree {
+    Sphere { radius: f32 },
+    Cuboid { half_extents: Vec3A },
+    Torus { radius: f32, thickness: f32 },
+    Union(Box<SdfTree>, Box<SdfTree>),
+    UnionSmooth(Box<SdfTree>, Box<SdfTree>, f32),
+    // the first tree minus the second one
+    Subtract(Box<SdfTree>, Box<SdfTree>),
+    Intersection(Box<SdfTree>, Box<SdfTree>),
+    Translate(Box<SdfTree>, Vec3A),
+    // rotation applied to the tree
+    Rotate(Box<SdfTree>, Quat),
+    Scale(Box<SdfTree>, f32),
+    Round(Box<SdfTree>, f32),
+}
+
+impl DistanceField for SdfTree {
+    fn distance(&self, p: Vec3A) -> f32 {
+        match self {
+            SdfTree::Sphere { radius } => sdfu::Sphere::new(*radius).dist(p),
+            SdfTree::Cuboid { half_extents } => sdfu::Box::new(*half_extents).dist(p),
+            SdfTree::Torus { radius, thickness } => sdfu::Torus::new(*radius, *thickness).dist(p),
+            SdfTree::Union(a, b) => a.distance(p).min(b.distance(p)),
+            SdfTree::UnionSmooth(a, b, softness) => {
+                // polynomial smooth min, like sdfu::SDF::union_smooth
+                let (da, db) = (a.distance(p), b.distance(p));
+                let h = (0.5 + 0.5 * (db - da) / softness).clamp(0.0, 1.0);
+                db + (da - db) * h - softness * h * (1.0 - h)
+            }
+            SdfTree::Subtract(a, b) => a.distance(p).max(-b.distance(p)),
+            SdfTree::Intersection(a, b) => a.distance(p).max(b.distance(p)),
+            SdfTree::Translate(sdf, offset) => sdf.distance(p - *offset),
+            SdfTree::Rotate(sdf, rotation) => sdf.distance(rotation.inverse() * p),
+            SdfTree::Scale(sdf, factor) => sdf.distance(p / *factor) * factor,
+            SdfTree::Round(sdf, radius) => sdf.distance(p) - radius,
+        }
+    }
+}
+
 pub struct TracedSDF<S> {
     sdf: S,
     mat: Material,
@@ -33,14 +100,14 @@ impl<S> TracedSDF<S> {
     }
 }
 
-impl<S: SDF<f32, Vec3A> + Send + Sync> Hitable for TracedSDF<S> {
+impl<S: DistanceField + Send + Sync> Hitable for TracedSDF<S> {
     fn hit(&self, ray: &Ray, _t0: f32, t1: f32) -> Option<HitRecord> {
         let mut t = 0.0;
         let mut hit = false;
 
         for _march in 0..MAX_MARCHES {
             let pos = ray.point_at_parameter(t);
-            let dist = self.sdf.dist(pos);
+            let dist = self.sdf.distance(pos);
             if dist < EPSILON {
                 hit = true;
                 break;
@@ -56,8 +123,7 @@ impl<S: SDF<f32, Vec3A> + Send + Sync> Hitable for TracedSDF<S> {
         // you had to decrease t otherwise there was a self intersection with the surface of the hit object
         t -= EPSILON;
         if hit {
-            let normals = self.sdf.normals_fast(EPSILON);
-            let normal = normals.normal_at(ray.point_at_parameter(t));
+            let normal = self.sdf.normal_at(ray.point_at_parameter(t), EPSILON);
             //let front_face = ray.direction.dot(normal) < 0.0;
             // normal: if front_face { normal } else { -normal },
 
@@ -65,9 +131,11 @@ impl<S: SDF<f32, Vec3A> + Send + Sync> Hitable for TracedSDF<S> {
                 t,
                 pos: ray.point_at_parameter(t),
                 normal,
+                geometric_normal: normal,
                 mat: &self.mat,
                 u: 0.0,
                 v: 0.0,
+                vertex_color: None,
             });
         } else {
             return None;
@@ -92,6 +160,9 @@ impl<S: SDF<f32, Vec3A> + Send + Sync> Hitable for TracedSDF<S> {
                 ),
         })
     }
+    fn material(&self) -> Option<&Material> {
+        Some(&self.mat)
+    }
 }
 
 #[test]
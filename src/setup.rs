@@ -12,6 +12,9 @@ pub const SDF_DETAIL_SCALE: f32 = 2.0;
 // use a lower (more detailed) SDF_DETAIL_SCALE.
 pub const FRACTAL_ITERATIONS: usize = 12;
 
+// Number of bounces after which the paths can be terminated by russian roulette.
+pub const RUSSIAN_ROULETTE_DEPTH: usize = 3;
+
 pub const WHITE: Color = Color {
     red: 1.0,
     green: 1.0,
@@ -0,0 +1,54 @@
+use crate::color::Color;
+use rand::rngs::SmallRng;
+use rand::Rng;
+
+// The wavelengths, in nanometers, that the camera samples carry for the dispersive
+// materials. The tails of the visible spectrum are left out, they add more noise than color.
+pub const MIN_WAVELENGTH: f32 = 400.0;
+pub const MAX_WAVELENGTH: f32 = 700.0;
+
+// Scales the channels so that the weights of the sampled wavelengths average to white
+const WHITE_BALANCE: [f32; 3] = [1.7037, 2.6011, 2.7580];
+
+pub fn sample_wavelength(rng: &mut SmallRng) -> f32 {
+    rng.gen_range(MIN_WAVELENGTH..MAX_WAVELENGTH)
+}
+
+// The weight of a path that carries only `wavelength`, sampled uniformly. The CIE 1931
+// matching functions, in the fit of Wyman, Sloan and Shirley 2013, are converted to
+// linear sRGB and the colors out of its gamut are clipped.
+pub fn wavelength_to_rgb(wavelength: f32) -> Color {
+    let lobe = |mean: f32, left: f32, right: f32| {
+        let width = if wavelength < mean { left } else { right };
+        (-0.5 * ((wavelength - mean) / width).powi(2)).exp()
+    };
+    let x = 1.056 * lobe(599.8, 37.9, 31.0) + 0.362 * lobe(442.0, 16.0, 26.7)
+        - 0.065 * lobe(501.1, 20.4, 26.2);
+    let y = 0.821 * lobe(568.8, 46.9, 40.5) + 0.286 * lobe(530.9, 16.3, 31.1);
+    let z = 1.217 * lobe(437.0, 11.8, 36.0) + 0.681 * lobe(459.0, 26.0, 13.8);
+
+    let red = 3.2406 * x - 1.5372 * y - 0.4986 * z;
+    let green = -0.9689 * x + 1.8758 * y + 0.0415 * z;
+    let blue = 0.0557 * x - 0.2040 * y + 1.0570 * z;
+    Color::new(
+        red.max(0.0) * WHITE_BALANCE[0],
+        green.max(0.0) * WHITE_BALANCE[1],
+        blue.max(0.0) * WHITE_BALANCE[2],
+    )
+}
+
+#[test]
+fn test_wavelengths_average_to_white() {
+    let n = 10000;
+    let step = (MAX_WAVELENGTH - MIN_WAVELENGTH) / n as f32;
+    let sum = (0..n)
+        .map(|i| wavelength_to_rgb(MIN_WAVELENGTH + (i as f32 + 0.5) * step))
+        .fold(Color::new(0.0, 0.0, 0.0), |sum, color| sum + color);
+    let mean = sum * (1.0 / n as f32);
+    for channel in [mean.red, mean.green, mean.blue] {
+        assert!((channel - 1.0).abs() < 1e-3, "{mean:?}");
+    }
+    // blue is at the short end of the spectrum and red at the long one
+    assert!(wavelength_to_rgb(450.0).blue > wavelength_to_rgb(450.0).red);
+    assert!(wavelength_to_rgb(650.0).red > wavelength_to_rgb(650.0).blue);
+}
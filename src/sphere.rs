@@ -2,8 +2,9 @@ use crate::aabb::Aabb;
 use crate::hitable::{HitRecord, Hitable};
 use crate::material::Material;
 use crate::ray::Ray;
-use crate::utils::u_v_from_sphere_hit_point;
+use crate::utils::{pdf_towards_sphere, random_towards_sphere, u_v_from_sphere_hit_point};
 use glam::Vec3A;
+use rand::rngs::SmallRng;
 
 #[derive(Debug)]
 pub struct Sphere {
@@ -36,9 +37,11 @@ impl Hitable for Sphere {
                         t: *root,
                         pos: p,
                         normal,
+                        geometric_normal: normal,
                         mat: &self.mat,
                         u,
                         v,
+                        vertex_color: None,
                     });
                 }
             }
@@ -56,4 +59,16 @@ impl Hitable for Sphere {
                 + Vec3A::new(self.radius.abs(), self.radius.abs(), self.radius.abs()),
         })
     }
+
+    fn material(&self) -> Option<&Material> {
+        Some(&self.mat)
+    }
+
+    fn pdf_value(&self, origin: Vec3A, direction: Vec3A) -> f32 {
+        pdf_towards_sphere(origin, direction, self.position, self.radius.abs())
+    }
+
+    fn random(&self, origin: Vec3A, rng: &mut SmallRng) -> Vec3A {
+        random_towards_sphere(rng, origin, self.position, self.radius.abs())
+    }
 }
@@ -0,0 +1,43 @@
+// Fixtures shared by the unit tests.
+
+use crate::color::Color;
+use crate::hitable::{HitRecord, Hitable};
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::setup::WHITE;
+use crate::texture::Texture;
+use glam::Vec3A;
+
+// A constant texture with the same value on the three channels.
+pub fn grey(value: f32) -> Texture {
+    Texture::constant_color(Color::new(value, value, value))
+}
+
+// A white lambertian, for the tests that only look at the geometry.
+pub fn white_material() -> Material {
+    Material::lambertian(Texture::constant_color(WHITE))
+}
+
+// Casts a ray from `origin` and returns the distance and the normal of the closest hit.
+pub fn cast(hitable: &dyn Hitable, origin: Vec3A, direction: Vec3A) -> Option<(f32, Vec3A)> {
+    let hit = hitable.hit(&Ray::new(origin, direction), 0.001, f32::MAX)?;
+    Some((hit.t, hit.normal))
+}
+
+pub fn close(a: Vec3A, b: Vec3A) -> bool {
+    (a - b).length() < 1e-4
+}
+
+// A hit at the origin of a surface facing `normal`, for the tests of the materials.
+pub fn hit_record(mat: &Material, t: f32, normal: Vec3A) -> HitRecord<'_> {
+    HitRecord {
+        t,
+        pos: Vec3A::ZERO,
+        normal,
+        geometric_normal: normal,
+        mat,
+        u: 0.0,
+        v: 0.0,
+        vertex_color: None,
+    }
+}
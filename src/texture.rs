@@ -1,5 +1,8 @@
 use crate::color::Color;
+use crate::hitable::HitRecord;
+use crate::render_error::RenderError;
 use glam::Vec3A;
+use std::path::Path;
 
 mod bitmap;
 mod checker;
@@ -7,7 +10,7 @@ mod constant_color;
 pub mod noise;
 pub mod perlin;
 
-use bitmap::Bitmap;
+pub use bitmap::Bitmap;
 use checker::Checker;
 use constant_color::ConstantColor;
 use noise::Noise;
@@ -19,6 +22,8 @@ pub enum Texture {
     Checker(Checker),
     ConstantColor(ConstantColor),
     Noise(Noise),
+    // the colors of the vertices of a mesh, the color is used on the hits without them
+    VertexColor(Color),
 }
 
 pub trait TextureObject {
@@ -26,10 +31,9 @@ pub trait TextureObject {
 }
 
 impl Texture {
-    pub fn bitmap(path: &str) -> Texture {
-        Texture::Bitmap(Bitmap {
-            bitmap: image::open(path).unwrap(),
-        })
+    // Returns an error when the image can not be opened
+    pub fn bitmap(path: &Path) -> Result<Texture, RenderError> {
+        Ok(Texture::Bitmap(Bitmap::new(image::open(path)?)))
     }
 
     pub fn checker(squares: usize, odd: Color, even: Color) -> Texture {
@@ -40,8 +44,30 @@ impl Texture {
         Texture::ConstantColor(ConstantColor { color })
     }
 
-    pub fn noise(s: f32) -> Texture {
-        Texture::Noise(Noise::new(s))
+    // the seed generates the permutations of the perlin noise
+    pub fn noise(s: f32, seed: u64) -> Texture {
+        Texture::Noise(Noise::new(s, seed))
+    }
+
+    pub fn vertex_color(fallback: Color) -> Texture {
+        Texture::VertexColor(fallback)
+    }
+
+    // The value at a hit, the vertex colors are only known there
+    pub fn hit_value(&self, hit: &HitRecord) -> Color {
+        match (self, hit.vertex_color) {
+            (Texture::VertexColor(_), Some(color)) => color,
+            _ => self.value(hit.u, hit.v, hit.pos),
+        }
+    }
+
+    // A single value at a hit, like the roughness of a material, from the red channel. The
+    // bitmaps hold data, they are not gamma decoded.
+    pub fn scalar_value(&self, hit: &HitRecord) -> f32 {
+        match self {
+            Texture::Bitmap(bitmap) => bitmap.linear_value(hit.u, hit.v).x,
+            _ => self.hit_value(hit).red,
+        }
     }
 
     pub fn value(&self, u: f32, v: f32, p: Vec3A) -> Color {
@@ -50,6 +76,7 @@ impl Texture {
             Texture::Checker(checker) => checker.value(u, v, p),
             Texture::ConstantColor(color) => color.value(u, v, p),
             Texture::Noise(noise) => noise.value(u, v, p),
+            Texture::VertexColor(fallback) => *fallback,
         }
     }
 }
@@ -7,11 +7,28 @@ use image::{DynamicImage, GenericImageView};
 #[derive(Clone)]
 pub struct Bitmap {
     pub bitmap: DynamicImage,
+    // multiplies the colors of the image, it can make them brighter than white
+    pub factor: Color,
 }
 
-impl TextureObject for Bitmap {
-    fn value(&self, u: f32, v: f32, _p: Vec3A) -> Color {
+impl Bitmap {
+    pub fn new(bitmap: DynamicImage) -> Bitmap {
+        Bitmap {
+            bitmap,
+            factor: Color::new(1.0, 1.0, 1.0),
+        }
+    }
+
+    // The channels without the gamma decoding, for data like the normal maps
+    pub fn linear_value(&self, u: f32, v: f32) -> Vec3A {
+        let pixel = self.pixel(u, v);
+        Vec3A::new(pixel[0] as f32, pixel[1] as f32, pixel[2] as f32) / 255.0
+    }
+
+    fn pixel(&self, u: f32, v: f32) -> image::Rgba<u8> {
         let (width, height) = self.bitmap.dimensions();
+        // the texture repeats outside the unit square, like in the uvs of many meshes
+        let (u, v) = (repeat(u), repeat(v));
 
         let mut i = (u * width as f32) as u32;
         let mut j = ((1.0 - v) * (height as f32) - 0.001) as u32;
@@ -19,11 +36,21 @@ impl TextureObject for Bitmap {
         i = i.min(width - 1);
         j = j.min(height - 1);
 
-        let pixel: image::Rgba<u8> = self.bitmap.get_pixel(i, j);
-        // let data = pixel.data;
+        self.bitmap.get_pixel(i, j)
+    }
+}
+
+impl TextureObject for Bitmap {
+    fn value(&self, u: f32, v: f32, _p: Vec3A) -> Color {
+        Color::from_rgba(self.pixel(u, v)) * self.factor
+    }
+}
 
-        // Color::from_u8(data[0], data[1], data[2])
-        Color::from_rgba(pixel)
+fn repeat(x: f32) -> f32 {
+    if (0.0..=1.0).contains(&x) {
+        x
+    } else {
+        x.rem_euclid(1.0)
     }
 }
 
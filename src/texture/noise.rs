@@ -2,18 +2,21 @@ use super::TextureObject;
 use crate::color::Color;
 use crate::texture::perlin::Perlin;
 use glam::Vec3A;
+use rand::rngs::SmallRng;
+use rand::SeedableRng;
 
 #[derive(Clone, Debug)]
 pub struct Noise {
     pub scale: f32,
-    pub perlin: Perlin,
+    // boxed, the tables would make every texture and material as large
+    pub perlin: Box<Perlin>,
 }
 
 impl Noise {
-    pub fn new(scale: f32) -> Self {
+    pub fn new(scale: f32, seed: u64) -> Self {
         Self {
             scale,
-            perlin: Perlin::new(),
+            perlin: Box::new(Perlin::new(&mut SmallRng::seed_from_u64(seed))),
         }
     }
 
@@ -1,5 +1,6 @@
 use glam::Vec3A;
-use rand::prelude::*;
+use rand::rngs::SmallRng;
+use rand::Rng;
 
 const SIZE: usize = 256;
 
@@ -12,12 +13,12 @@ pub struct Perlin {
 }
 
 impl Perlin {
-    pub fn new() -> Self {
+    pub fn new(rng: &mut SmallRng) -> Self {
         Perlin {
-            ranvec: Perlin::generate(),
-            perm_x: Perlin::generate_perm(),
-            perm_y: Perlin::generate_perm(),
-            perm_z: Perlin::generate_perm(),
+            ranvec: Perlin::generate(rng),
+            perm_x: Perlin::generate_perm(rng),
+            perm_y: Perlin::generate_perm(rng),
+            perm_z: Perlin::generate_perm(rng),
         }
     }
 
@@ -48,8 +49,7 @@ impl Perlin {
         Perlin::trilinear_interpolation(&c, u, v, w)
     }
 
-    fn generate() -> Vec<Vec3A> {
-        let mut rng = rand::thread_rng();
+    fn generate(rng: &mut SmallRng) -> Vec<Vec3A> {
         let mut perlin = Vec::with_capacity(SIZE);
         for _ in 0..SIZE {
             // use random unit vectors (instead of just floats) on lattice points, use dot product
@@ -65,17 +65,16 @@ impl Perlin {
         perlin
     }
 
-    fn generate_perm() -> [usize; SIZE] {
+    fn generate_perm(rng: &mut SmallRng) -> [usize; SIZE] {
         let mut perm = [0; SIZE];
         for i in 0..SIZE {
             perm[i] = i as usize;
         }
-        Perlin::permute(&mut perm);
+        Perlin::permute(&mut perm, rng);
         perm
     }
 
-    fn permute(perm: &mut [usize; SIZE]) {
-        let mut rng = rand::thread_rng();
+    fn permute(perm: &mut [usize; SIZE], rng: &mut SmallRng) {
         for i in (0..perm.len()).rev() {
             let target = (rng.gen::<f32>() * (i as f32 + 1.0)) as usize;
             perm.swap(i, target);
@@ -0,0 +1,186 @@
+use crate::color::Color;
+use image::Rgba;
+
+// Maps the linear radiance of the framebuffer to [0, 1], before the transfer curve.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ToneMapOperator {
+    // values above 1.0 are clipped
+    Clamp,
+    // Reinhard on the luminance, so that the hue of bright colors is preserved
+    Reinhard,
+    // filmic curve, fit of the ACES reference tone mapping by Krzysztof Narkowicz
+    Aces,
+}
+
+// Encoding of the tone mapped values for the display
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TransferCurve {
+    Linear,
+    Gamma(f32),
+    Srgb,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ToneMappingOpt {
+    pub exposure: f32, // in stops, 0.0 leaves the image as it is
+    pub operator: ToneMapOperator,
+    pub transfer: TransferCurve,
+    pub temperature: f32, // color temperature of the light that should look white, in Kelvin
+}
+
+impl Default for ToneMappingOpt {
+    // Hard clamp and gamma 2.2, what Color::to_rgba does.
+    fn default() -> ToneMappingOpt {
+        ToneMappingOpt {
+            exposure: 0.0,
+            operator: ToneMapOperator::Clamp,
+            transfer: TransferCurve::Gamma(2.2),
+            temperature: NEUTRAL_TEMPERATURE,
+        }
+    }
+}
+
+const NEUTRAL_TEMPERATURE: f32 = 6500.0;
+
+impl ToneMappingOpt {
+    pub fn apply(&self, linear: Color) -> Color {
+        let exposed = linear * self.white_balance() * 2f32.powf(self.exposure);
+        let mapped = match self.operator {
+            ToneMapOperator::Clamp => exposed,
+            ToneMapOperator::Reinhard => {
+                let lum = luminance(exposed);
+                if lum > 0.0 {
+                    exposed * (1.0 / (1.0 + lum))
+                } else {
+                    exposed
+                }
+            }
+            ToneMapOperator::Aces => {
+                Color::new(aces(exposed.red), aces(exposed.green), aces(exposed.blue))
+            }
+        }
+        .clamp();
+
+        Color::new(
+            self.encode(mapped.red),
+            self.encode(mapped.green),
+            self.encode(mapped.blue),
+        )
+    }
+
+    pub fn to_rgba(&self, linear: Color) -> Rgba<u8> {
+        let col = self.apply(linear);
+        image::Rgba([
+            (col.red * 255.0) as u8,
+            (col.green * 255.0) as u8,
+            (col.blue * 255.0) as u8,
+            255,
+        ])
+    }
+
+    // Gains that turn the color of a black body at `temperature` into white,
+    // normalized so that the luminance of the image does not change.
+    pub fn white_balance(&self) -> Color {
+        if self.temperature == NEUTRAL_TEMPERATURE {
+            return Color::new(1.0, 1.0, 1.0);
+        }
+        let neutral = temperature_to_rgb(NEUTRAL_TEMPERATURE);
+        let light = temperature_to_rgb(self.temperature);
+        let gains = Color::new(
+            neutral.red / light.red,
+            neutral.green / light.green,
+            neutral.blue / light.blue,
+        );
+        gains / luminance(gains)
+    }
+
+    fn encode(&self, value: f32) -> f32 {
+        match self.transfer {
+            TransferCurve::Linear => value,
+            TransferCurve::Gamma(gamma) => value.powf(1.0 / gamma),
+            TransferCurve::Srgb => {
+                if value <= 0.0031308 {
+                    value * 12.92
+                } else {
+                    1.055 * value.powf(1.0 / 2.4) - 0.055
+                }
+            }
+        }
+    }
+}
+
+fn luminance(col: Color) -> f32 {
+    0.2126 * col.red + 0.7152 * col.green + 0.0722 * col.blue
+}
+
+fn aces(x: f32) -> f32 {
+    let a = 2.51;
+    let b = 0.03;
+    let c = 2.43;
+    let d = 0.59;
+    let e = 0.14;
+    (x * (a * x + b)) / (x * (c * x + d) + e)
+}
+
+// Approximation of the color of a black body, valid between 1000K and 40000K.
+// https://tannerhelland.com/2012/09/18/convert-temperature-rgb-algorithm-code.html
+fn temperature_to_rgb(kelvin: f32) -> Color {
+    let t = kelvin.clamp(1000.0, 40000.0) / 100.0;
+    let red = if t <= 66.0 {
+        255.0
+    } else {
+        329.698_73 * (t - 60.0).powf(-0.133_204_76)
+    };
+    let green = if t <= 66.0 {
+        99.470_8 * t.ln() - 161.119_57
+    } else {
+        288.122_16 * (t - 60.0).powf(-0.075_514_85)
+    };
+    let blue = if t >= 66.0 {
+        255.0
+    } else if t <= 19.0 {
+        0.0
+    } else {
+        138.517_73 * (t - 10.0).ln() - 305.044_8
+    };
+    // keep the channels above zero, white balance divides by them
+    Color::new(
+        (red / 255.0).clamp(0.01, 1.0),
+        (green / 255.0).clamp(0.01, 1.0),
+        (blue / 255.0).clamp(0.01, 1.0),
+    )
+}
+
+#[test]
+fn test_operators_and_transfer_curves() {
+    let grey = |value: f32| Color::new(value, value, value);
+    let opt = |operator, transfer| ToneMappingOpt {
+        operator,
+        transfer,
+        ..ToneMappingOpt::default()
+    };
+    let close = |a: f32, b: f32| (a - b).abs() < 1e-4;
+
+    // the luminance of a grey is its value, Reinhard maps 1 to a half
+    let reinhard = opt(ToneMapOperator::Reinhard, TransferCurve::Linear);
+    assert!(close(reinhard.apply(grey(1.0)).green, 0.5));
+    assert!(close(reinhard.apply(grey(3.0)).green, 0.75));
+    let aces = opt(ToneMapOperator::Aces, TransferCurve::Linear);
+    assert!(close(aces.apply(grey(1.0)).green, 2.54 / 3.16));
+    assert!(close(aces.apply(grey(100.0)).green, 1.0));
+
+    // sRGB is linear in the shadows and close to a 2.4 power above them
+    let srgb = opt(ToneMapOperator::Clamp, TransferCurve::Srgb);
+    assert!(close(srgb.apply(grey(1.0)).green, 1.0));
+    assert!(close(srgb.apply(grey(0.002)).green, 0.002 * 12.92));
+    assert!(close(srgb.apply(grey(0.5)).green, 0.735_357));
+
+    for opt in [reinhard, aces, srgb] {
+        assert_eq!(opt.apply(grey(0.0)).green, 0.0, "{opt:?}");
+        let values: Vec<f32> = (0..=200)
+            .map(|i| opt.apply(grey(i as f32 * 0.05)).green)
+            .collect();
+        assert!(values.windows(2).all(|w| w[0] <= w[1]), "{opt:?}");
+        assert!(values.iter().all(|v| (0.0..=1.0).contains(v)), "{opt:?}");
+    }
+}
@@ -0,0 +1,274 @@
+use crate::aabb::Aabb;
+use crate::hitable::{HitRecord, Hitable};
+use crate::material::Material;
+use crate::ray::Ray;
+use glam::Vec3A;
+use std::f32::consts::PI;
+
+// Torus lying on the xz plane around `position`. `radius` goes from the center to the
+// middle of the tube, `thickness` is the radius of the tube.
+#[derive(Debug)]
+pub struct Torus {
+    pub position: Vec3A,
+    pub radius: f32,
+    pub thickness: f32,
+    pub mat: Material,
+}
+
+impl Hitable for Torus {
+    fn hit(&self, r: &Ray, tmin: f32, tmax: f32) -> Option<HitRecord<'_>> {
+        // the quartic loses precision quickly, it is solved in f64 and from a point
+        // close to the torus
+        let d = r.direction.as_dvec3();
+        let o = (r.origin - self.position).as_dvec3();
+        let big_r = self.radius as f64;
+        let small_r = self.thickness as f64;
+
+        let bound = big_r + small_r;
+        let dd = d.dot(d);
+        let half_b = o.dot(d);
+        let discriminant = half_b * half_b - dd * (o.dot(o) - bound * bound);
+        if discriminant < 0.0 {
+            return None;
+        }
+        let shift = ((-half_b - discriminant.sqrt()) / dd).max(0.0);
+        let o = o + shift * d;
+
+        let e = o.dot(o) - big_r * big_r - small_r * small_r;
+        let f = o.dot(d);
+        let four_r2 = 4.0 * big_r * big_r;
+        let coefficients = [
+            e * e - four_r2 * (small_r * small_r - o.y * o.y),
+            4.0 * f * e + 2.0 * four_r2 * o.y * d.y,
+            2.0 * dd * e + 4.0 * f * f + four_r2 * d.y * d.y,
+            4.0 * dd * f,
+            dd * dd,
+        ];
+
+        let t = solve_quartic(coefficients)
+            .into_iter()
+            .map(|root| polish_root(&coefficients, root) + shift)
+            .filter(|&t| t > tmin as f64 && t < tmax as f64)
+            .fold(None, |closest: Option<f64>, t| {
+                Some(closest.map_or(t, |c| c.min(t)))
+            })? as f32;
+
+        let p = r.point_at_parameter(t) - self.position;
+        let s = p.length_squared();
+        let (r2, t2) = (self.radius * self.radius, self.thickness * self.thickness);
+        let normal = Vec3A::new(
+            p.x * (s - r2 - t2),
+            p.y * (s + r2 - t2),
+            p.z * (s - r2 - t2),
+        )
+        .normalize();
+
+        // u goes around the y axis, v around the tube
+        let distance_from_axis = (p.x * p.x + p.z * p.z).sqrt();
+        let u = p.z.atan2(p.x) / (2.0 * PI) + 0.5;
+        let v = p.y.atan2(distance_from_axis - self.radius) / (2.0 * PI) + 0.5;
+        Some(HitRecord {
+            t,
+            pos: r.point_at_parameter(t),
+            normal,
+            geometric_normal: normal,
+            mat: &self.mat,
+            u,
+            v,
+            vertex_color: None,
+        })
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let outer = self.radius + self.thickness;
+        let half_size = Vec3A::new(outer, self.thickness, outer);
+        Some(Aabb {
+            min: self.position - half_size,
+            max: self.position + half_size,
+        })
+    }
+
+    fn material(&self) -> Option<&Material> {
+        Some(&self.mat)
+    }
+}
+
+const EPSILON: f64 = 1e-9;
+
+fn is_zero(x: f64) -> bool {
+    x.abs() < EPSILON
+}
+
+// The real roots of c[2] x² + c[1] x + c[0]
+fn solve_quadratic(c: [f64; 3]) -> Vec<f64> {
+    let p = c[1] / (2.0 * c[2]);
+    let q = c[0] / c[2];
+    let d = p * p - q;
+    if is_zero(d) {
+        vec![-p]
+    } else if d < 0.0 {
+        vec![]
+    } else {
+        let sqrt_d = d.sqrt();
+        vec![sqrt_d - p, -sqrt_d - p]
+    }
+}
+
+// The real roots of c[3] x³ + c[2] x² + c[1] x + c[0], with Cardano's formula
+fn solve_cubic(c: [f64; 4]) -> Vec<f64> {
+    let a = c[2] / c[3];
+    let b = c[1] / c[3];
+    let c = c[0] / c[3];
+
+    // x = y - a/3 gives y³ + 3py + 2q = 0
+    let sq_a = a * a;
+    let p = (-sq_a / 3.0 + b) / 3.0;
+    let q = (2.0 / 27.0 * a * sq_a - a * b / 3.0 + c) / 2.0;
+    let cb_p = p * p * p;
+    let d = q * q + cb_p;
+
+    let roots = if is_zero(d) {
+        if is_zero(q) {
+            vec![0.0]
+        } else {
+            let u = (-q).cbrt();
+            vec![2.0 * u, -u]
+        }
+    } else if d < 0.0 {
+        // three real roots
+        let phi = (-q / (-cb_p).sqrt()).clamp(-1.0, 1.0).acos() / 3.0;
+        let t = 2.0 * (-p).sqrt();
+        let third = std::f64::consts::PI / 3.0;
+        vec![
+            t * phi.cos(),
+            -t * (phi + third).cos(),
+            -t * (phi - third).cos(),
+        ]
+    } else {
+        let sqrt_d = d.sqrt();
+        vec![(sqrt_d - q).cbrt() - (sqrt_d + q).cbrt()]
+    };
+    roots.into_iter().map(|root| root - a / 3.0).collect()
+}
+
+// The real roots of c[4] x⁴ + c[3] x³ + c[2] x² + c[1] x + c[0], with Ferrari's method
+fn solve_quartic(c: [f64; 5]) -> Vec<f64> {
+    let a = c[3] / c[4];
+    let b = c[2] / c[4];
+    let c_ = c[1] / c[4];
+    let d = c[0] / c[4];
+
+    // x = y - a/4 gives y⁴ + py² + qy + r = 0
+    let sq_a = a * a;
+    let p = -3.0 / 8.0 * sq_a + b;
+    let q = sq_a * a / 8.0 - a * b / 2.0 + c_;
+    let r = -3.0 / 256.0 * sq_a * sq_a + sq_a * b / 16.0 - a * c_ / 4.0 + d;
+
+    let roots = if is_zero(r) {
+        let mut roots = solve_cubic([q, p, 0.0, 1.0]);
+        roots.push(0.0);
+        roots
+    } else {
+        // one root of the resolvent cubic splits the quartic in two quadratics
+        let z = solve_cubic([r * p / 2.0 - q * q / 8.0, -r, -p / 2.0, 1.0])[0];
+        let u = z * z - r;
+        let v = 2.0 * z - p;
+        let u = if is_zero(u) {
+            0.0
+        } else if u > 0.0 {
+            u.sqrt()
+        } else {
+            return vec![];
+        };
+        let v = if is_zero(v) {
+            0.0
+        } else if v > 0.0 {
+            v.sqrt()
+        } else {
+            return vec![];
+        };
+        let (v1, v2) = if q < 0.0 { (-v, v) } else { (v, -v) };
+        let mut roots = solve_quadratic([z - u, v1, 1.0]);
+        roots.extend(solve_quadratic([z + u, v2, 1.0]));
+        roots
+    };
+    roots.into_iter().map(|root| root - a / 4.0).collect()
+}
+
+// A couple of Newton steps, the closed form roots are not precise enough for a
+// smooth surface
+fn polish_root(c: &[f64; 5], mut x: f64) -> f64 {
+    for _ in 0..2 {
+        let value = (((c[4] * x + c[3]) * x + c[2]) * x + c[1]) * x + c[0];
+        let derivative = ((4.0 * c[4] * x + 3.0 * c[3]) * x + 2.0 * c[2]) * x + c[1];
+        if derivative.abs() > EPSILON {
+            x -= value / derivative;
+        }
+    }
+    x
+}
+
+#[test]
+fn test_torus_hits() {
+    use crate::test_utils::{cast, close, white_material};
+
+    let torus = Torus {
+        position: Vec3A::ZERO,
+        radius: 2.0,
+        thickness: 0.5,
+        mat: white_material(),
+    };
+    let hit = |origin, direction| cast(&torus, origin, direction);
+
+    // through the hole
+    assert!(hit(Vec3A::new(0.0, 5.0, 0.0), -Vec3A::Y).is_none());
+    // the outer side, and the inner one when the ray comes from the hole
+    let (t, normal) = hit(Vec3A::new(5.0, 0.0, 0.0), -Vec3A::X).unwrap();
+    assert!(
+        (t - 2.5).abs() < 1e-4 && close(normal, Vec3A::X),
+        "{t} {normal}"
+    );
+    let (t, normal) = hit(Vec3A::ZERO, Vec3A::Z).unwrap();
+    assert!(
+        (t - 1.5).abs() < 1e-4 && close(normal, -Vec3A::Z),
+        "{t} {normal}"
+    );
+    // down on the top of the tube
+    let (t, normal) = hit(Vec3A::new(0.0, 3.0, -2.0), -Vec3A::Y).unwrap();
+    assert!(
+        (t - 2.5).abs() < 1e-4 && close(normal, Vec3A::Y),
+        "{t} {normal}"
+    );
+    // from inside the tube
+    let (t, normal) = hit(Vec3A::new(2.0, 0.0, 0.0), Vec3A::X).unwrap();
+    assert!(
+        (t - 0.5).abs() < 1e-4 && close(normal, Vec3A::X),
+        "{t} {normal}"
+    );
+    // grazing the top of the tube, tangent at x = -2 and x = 2
+    let (t, normal) = hit(Vec3A::new(-5.0, 0.4999, 0.0), Vec3A::X).unwrap();
+    assert!((t - 3.0).abs() < 0.05 && normal.y > 0.99, "{t} {normal}");
+    assert!(hit(Vec3A::new(-5.0, 0.5001, 0.0), Vec3A::X).is_none());
+
+    let aabb = torus.bounding_box().unwrap();
+    assert!(close(aabb.min, Vec3A::new(-2.5, -0.5, -2.5)));
+    assert!(close(aabb.max, Vec3A::new(2.5, 0.5, 2.5)));
+}
+
+#[test]
+fn test_solve_quartic() {
+    // (x - 1)(x - 2)(x - 3)(x - 4), and (x² + 1)(x - 2)² with a double root
+    let mut roots = solve_quartic([24.0, -50.0, 35.0, -10.0, 1.0]);
+    roots.sort_by(f64::total_cmp);
+    assert_eq!(roots.len(), 4);
+    for (root, expected) in roots.iter().zip([1.0, 2.0, 3.0, 4.0]) {
+        assert!((root - expected).abs() < 1e-6, "{roots:?}");
+    }
+    let roots = solve_quartic([4.0, -4.0, 5.0, -4.0, 1.0]);
+    assert!(
+        !roots.is_empty() && roots.iter().all(|root| (root - 2.0).abs() < 1e-3),
+        "{roots:?}"
+    );
+    assert!(solve_quartic([1.0, 0.0, 2.0, 0.0, 1.0]).is_empty());
+    assert_eq!(solve_cubic([-6.0, 11.0, -6.0, 1.0]).len(), 3);
+}
@@ -0,0 +1,183 @@
+use crate::aabb::Aabb;
+use crate::hitable::{HitRecord, Hitable};
+use crate::material::Material;
+use crate::ray::Ray;
+use glam::{Affine3A, Mat3A, Quat, Vec3A};
+use std::sync::Arc;
+
+// A hitable moved, rotated and scaled by an affine transform. The rays are brought in
+// the space of the hitable, the hits are brought back in world space.
+//
+// To place the same object many times, wrap it in an Arc, usually an Arc<BvhTree> for
+// a mesh, and give every copy its own transform:
+//
+//   let mesh: Arc<dyn Hitable + Send + Sync> = Arc::new(BvhTree::new(hitables));
+//   let copy = Transformed::new(Arc::clone(&mesh), Affine3A::from_translation(offset));
+pub struct Transformed<H: Hitable> {
+    hitable: H,
+    transform: Affine3A,
+    inverse: Affine3A,
+    // inverse transpose of the linear part, keeps the normals perpendicular to the
+    // surface when the scale is not uniform
+    normal_matrix: Mat3A,
+    aabb: Option<Aabb>,
+    // replaces the materials of the hitable when set
+    material: Option<Material>,
+}
+
+// A copy of a shared object, see Transformed
+pub type Instance = Transformed<Arc<dyn Hitable + Send + Sync>>;
+
+impl<H: Hitable> Transformed<H> {
+    pub fn new(hitable: H, transform: Affine3A) -> Self {
+        let inverse = transform.inverse();
+        let aabb = hitable
+            .bounding_box()
+            .map(|aabb| transform_aabb(&transform, &aabb));
+        Transformed {
+            hitable,
+            transform,
+            inverse,
+            normal_matrix: inverse.matrix3.transpose(),
+            aabb,
+            material: None,
+        }
+    }
+
+    pub fn with_material(mut self, material: Material) -> Self {
+        self.material = Some(material);
+        self
+    }
+
+    pub fn transform(&self) -> Affine3A {
+        self.transform
+    }
+}
+
+impl<H: Hitable> Hitable for Transformed<H> {
+    fn hit(&self, r: &Ray, tmin: f32, tmax: f32) -> Option<HitRecord<'_>> {
+        let direction = self.inverse.transform_vector3a(r.direction);
+        // the ray in object space is normalized too, its distances are `scale` times
+        // the ones in world space
+        let scale = direction.length();
+        let object_ray = Ray::new(self.inverse.transform_point3a(r.origin), direction);
+        let hit = self.hitable.hit(&object_ray, tmin * scale, tmax * scale)?;
+        Some(HitRecord {
+            t: hit.t / scale,
+            pos: self.transform.transform_point3a(hit.pos),
+            normal: (self.normal_matrix * hit.normal).normalize(),
+            geometric_normal: (self.normal_matrix * hit.geometric_normal).normalize(),
+            mat: self.material.as_ref().unwrap_or(hit.mat),
+            u: hit.u,
+            v: hit.v,
+            vertex_color: hit.vertex_color,
+        })
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        self.aabb
+    }
+
+    fn material(&self) -> Option<&Material> {
+        self.material.as_ref().or_else(|| self.hitable.material())
+    }
+}
+
+// The shared objects of the instances
+impl<H: Hitable + Send + ?Sized> Hitable for Arc<H> {
+    fn hit(&self, r: &Ray, tmin: f32, tmax: f32) -> Option<HitRecord<'_>> {
+        self.as_ref().hit(r, tmin, tmax)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        self.as_ref().bounding_box()
+    }
+
+    fn material(&self) -> Option<&Material> {
+        self.as_ref().material()
+    }
+}
+
+// Scale, then rotation around `axis`, then translation
+pub fn affine(translation: Vec3A, axis: Vec3A, degrees: f32, scale: Vec3A) -> Affine3A {
+    let rotation = if axis.length_squared() > 0.0 {
+        Quat::from_axis_angle(axis.normalize().into(), degrees.to_radians())
+    } else {
+        Quat::IDENTITY
+    };
+    Affine3A::from_scale_rotation_translation(scale.into(), rotation, translation.into())
+}
+
+// The box containing the 8 corners of `aabb` transformed
+fn transform_aabb(transform: &Affine3A, aabb: &Aabb) -> Aabb {
+    let mut min = Vec3A::splat(f32::MAX);
+    let mut max = Vec3A::splat(f32::MIN);
+    for i in 0..8 {
+        let corner = Vec3A::new(
+            if i & 1 == 0 { aabb.min.x } else { aabb.max.x },
+            if i & 2 == 0 { aabb.min.y } else { aabb.max.y },
+            if i & 4 == 0 { aabb.min.z } else { aabb.max.z },
+        );
+        let corner = transform.transform_point3a(corner);
+        min = min.min(corner);
+        max = max.max(corner);
+    }
+    Aabb { min, max }
+}
+
+#[test]
+fn test_transformed_hits() {
+    use crate::sphere::Sphere;
+    use crate::test_utils::{cast, close, white_material};
+
+    let unit_sphere = || Sphere {
+        position: Vec3A::ZERO,
+        radius: 1.0,
+        mat: white_material(),
+    };
+
+    // stretched twice along x, the distances are the ones in world space
+    let ellipsoid = Transformed::new(
+        unit_sphere(),
+        affine(Vec3A::ZERO, Vec3A::ZERO, 0.0, Vec3A::new(2.0, 1.0, 1.0)),
+    );
+    let (t, normal) = cast(&ellipsoid, Vec3A::new(5.0, 0.0, 0.0), -Vec3A::X).unwrap();
+    assert!(
+        (t - 3.0).abs() < 1e-4 && close(normal, Vec3A::X),
+        "{t} {normal}"
+    );
+    let (t, _) = cast(&ellipsoid, Vec3A::new(0.0, 5.0, 0.0), -Vec3A::Y).unwrap();
+    assert!((t - 4.0).abs() < 1e-4, "{t}");
+    // on the slanted side the normal of x²/4 + y² = 1 is (x / 4, y), not the direction
+    // from the center
+    let point = Vec3A::new(2.0 * 0.6, 0.8, 0.0);
+    let (t, normal) = cast(&ellipsoid, Vec3A::ZERO, point).unwrap();
+    assert!((t - point.length()).abs() < 1e-4, "{t}");
+    assert!(
+        close(normal, Vec3A::new(point.x / 4.0, point.y, 0.0).normalize()),
+        "{normal}"
+    );
+
+    // two copies of the same sphere, half as big, on the two sides of the origin
+    let shared: Arc<dyn Hitable + Send + Sync> = Arc::new(unit_sphere());
+    let copies: Vec<Instance> = [10.0, -10.0]
+        .into_iter()
+        .map(|x| {
+            let transform = affine(Vec3A::new(x, 0.0, 0.0), Vec3A::Y, 90.0, Vec3A::splat(0.5));
+            Transformed::new(Arc::clone(&shared), transform)
+        })
+        .collect();
+    for (copy, x) in copies.iter().zip([10.0, -10.0]) {
+        let origin = Vec3A::new(x, 5.0, 0.0);
+        let (t, normal) = cast(copy, origin, -Vec3A::Y).unwrap();
+        assert!(
+            (t - 4.5).abs() < 1e-4 && close(normal, Vec3A::Y),
+            "{x}: {t}"
+        );
+        assert!(cast(copy, Vec3A::new(0.0, 5.0, 0.0), -Vec3A::Y).is_none());
+        let aabb = copy.bounding_box().unwrap();
+        assert!(close(aabb.min, Vec3A::new(x - 0.5, -0.5, -0.5)), "{x}");
+        assert!(close(aabb.max, Vec3A::new(x + 0.5, 0.5, 0.5)), "{x}");
+    }
+    assert_eq!(Arc::strong_count(&shared), 3);
+}
@@ -1,26 +1,50 @@
+use std::ops::{Add, Mul};
 use std::sync::Arc;
 
 use crate::aabb::Aabb;
 use crate::hitable::{HitRecord, Hitable};
 use crate::material::Material;
 use crate::ray::Ray;
-use glam::Vec3A;
+use glam::{Vec2, Vec3A};
+use rand::rngs::SmallRng;
+use rand::Rng;
 
 pub struct Triangle {
   v0: Vec3A,
   v1: Vec3A,
   v2: Vec3A,
+  // the geometric normal, given by the winding of the vertices
   normal: Vec3A,
+  // per vertex normals and texture coordinates, interpolated at the hit
+  normals: Option<[Vec3A; 3]>,
+  uvs: Option<[Vec2; 3]>,
   material: Arc<Material>
 }
 
 impl Triangle {
   pub fn new(v0: Vec3A, v1: Vec3A, v2: Vec3A, material: Arc<Material>) -> Triangle {
-    Triangle { v0, v1, v2, normal: (v1 - v0).cross(v2 - v0), material }
+    let normal = (v1 - v0).cross(v2 - v0).normalize();
+    Triangle { v0, v1, v2, normal, normals: None, uvs: None, material }
   }
 
   pub fn new_with_normal(v0: Vec3A, v1: Vec3A, v2: Vec3A, normal: Vec3A, material: Arc<Material>) -> Triangle {
-    Triangle { v0, v1, v2, normal, material }
+    Triangle { v0, v1, v2, normal: normal.normalize(), normals: None, uvs: None, material }
+  }
+
+  // Smooth shading. The geometric normal is flipped when the winding disagrees with
+  // the vertex normals, so both are on the same side of the surface.
+  pub fn with_normals(mut self, normals: [Vec3A; 3]) -> Triangle {
+    if self.normal.dot(normals[0] + normals[1] + normals[2]) < 0.0 {
+      self.normal = -self.normal;
+    }
+    self.normals = Some(normals);
+    self
+  }
+
+  // Texture coordinates, without them the hit reports the barycentric coordinates
+  pub fn with_uvs(mut self, uvs: [Vec2; 3]) -> Triangle {
+    self.uvs = Some(uvs);
+    self
   }
 }
 
@@ -38,44 +62,124 @@ impl Hitable for Triangle {
     })
   }
 
-  fn hit(&self, r: &Ray, tmin: f32, tmax: f32) -> Option<HitRecord> {
-    let v0v1 = self.v1 - self.v0;
-    let v0v2 = self.v2 - self.v0;
-    let pvec = r.direction.cross(v0v2);
-    let det = v0v1.dot(pvec);
+  fn hit(&self, r: &Ray, tmin: f32, tmax: f32) -> Option<HitRecord<'_>> {
+    let (t, u, v) = intersect(self.v0, self.v1, self.v2, r, tmin, tmax)?;
 
-    if det.abs() < 1e-4 {
-      return None
-    }
-    let inv_det = 1. / det;
+    let p = r.point_at_parameter(t);
+
+    let normal = match self.normals {
+      Some(normals) => shading_normal(normals, self.normal, u, v),
+      None => self.normal
+    };
+    let uv = match self.uvs {
+      Some(uvs) => interpolate(uvs, u, v),
+      None => Vec2::new(u, v)
+    };
 
-    let tvec = r.origin - self.v0;
-    let u = tvec.dot(pvec) * inv_det;
-    if u < 0. || u > 1. {
-      return None
+    return Some(HitRecord {
+      t,
+      pos:p,
+      normal,
+      geometric_normal: self.normal,
+      mat: &*self.material,
+      u: uv.x,
+      v: uv.y,
+      vertex_color: None,
+    })
+  }
+
+  fn material(&self) -> Option<&Material> {
+    Some(&self.material)
+  }
+
+  fn pdf_value(&self, origin: Vec3A, direction: Vec3A) -> f32 {
+    match self.hit(&Ray::new(origin, direction), 0.001, f32::MAX) {
+      Some(hit) => {
+        let cross = (self.v1 - self.v0).cross(self.v2 - self.v0);
+        let area = 0.5 * cross.length();
+        let cosine = cross.normalize().dot(direction.normalize()).abs();
+        hit.t * hit.t / (cosine * area)
+      }
+      None => 0.0
     }
+  }
 
-    let qvec = tvec.cross(v0v1);
-    let v = r.direction.dot(qvec) * inv_det;
-    if v < 0. || u + v > 1. {
-      return None
+  fn random(&self, origin: Vec3A, rng: &mut SmallRng) -> Vec3A {
+    let mut a = rng.gen::<f32>();
+    let mut b = rng.gen::<f32>();
+    if a + b > 1.0 {
+      a = 1.0 - a;
+      b = 1.0 - b;
     }
+    let point = self.v0 + a * (self.v1 - self.v0) + b * (self.v2 - self.v0);
+    point - origin
+  }
+}
 
-    let t = v0v2.dot(qvec) * inv_det;
+// Möller-Trumbore, the distance and the barycentric coordinates of v1 and v2 at the hit
+pub(crate) fn intersect(v0: Vec3A, v1: Vec3A, v2: Vec3A, r: &Ray, tmin: f32, tmax: f32) -> Option<(f32, f32, f32)> {
+  let v0v1 = v1 - v0;
+  let v0v2 = v2 - v0;
+  let pvec = r.direction.cross(v0v2);
+  let det = v0v1.dot(pvec);
 
-    if t < tmin || t > tmax {
-      return None
-    }
+  // only parallel rays, the triangles of a detailed mesh can be tiny
+  if det.abs() < 1e-10 {
+    return None
+  }
+  let inv_det = 1. / det;
 
-    let p = r.point_at_parameter(t);
+  let tvec = r.origin - v0;
+  let u = tvec.dot(pvec) * inv_det;
+  if !(0. ..=1.).contains(&u) {
+    return None
+  }
 
-    return Some(HitRecord {
-      t,
-      pos:p,
-      normal: self.normal,
-      mat: &*self.material,
-      u,
-      v,
-    })
+  let qvec = tvec.cross(v0v1);
+  let v = r.direction.dot(qvec) * inv_det;
+  if v < 0. || u + v > 1. {
+    return None
+  }
+
+  let t = v0v2.dot(qvec) * inv_det;
+
+  if t < tmin || t > tmax {
+    return None
   }
+
+  Some((t, u, v))
+}
+
+// The value at the barycentric coordinates `u` and `v`, like the ones given by intersect
+pub(crate) fn interpolate<T>(values: [T; 3], u: f32, v: f32) -> T
+where
+  T: Add<Output = T> + Mul<f32, Output = T>,
+{
+  let [a, b, c] = values;
+  a * (1. - u - v) + b * u + c * v
+}
+
+// The interpolated vertex normals, falling back to the geometric normal when they cancel out
+pub(crate) fn shading_normal(normals: [Vec3A; 3], geometric_normal: Vec3A, u: f32, v: f32) -> Vec3A {
+  let normal = interpolate(normals, u, v).normalize_or_zero();
+  if normal == Vec3A::ZERO { geometric_normal } else { normal }
+}
+
+#[test]
+fn test_interpolated_normal_and_uv() {
+  use crate::test_utils::white_material;
+
+  let normals = [Vec3A::Z, Vec3A::new(1.0, 0.0, 1.0).normalize(), Vec3A::new(0.0, 1.0, 1.0).normalize()];
+  let uvs = [Vec2::new(0.1, 0.2), Vec2::new(0.9, 0.2), Vec2::new(0.1, 0.8)];
+  let triangle = Triangle::new(Vec3A::ZERO, Vec3A::X, Vec3A::Y, Arc::new(white_material()))
+    .with_normals(normals)
+    .with_uvs(uvs);
+
+  // the barycentric coordinates of (0.25, 0.5) are 0.25, 0.25 and 0.5
+  let ray = Ray::new(Vec3A::new(0.25, 0.5, 1.0), -Vec3A::Z);
+  let hit = triangle.hit(&ray, 0.001, f32::MAX).unwrap();
+  let normal = (0.25 * normals[0] + 0.25 * normals[1] + 0.5 * normals[2]).normalize();
+  assert!((hit.normal - normal).length() < 1e-5, "{}", hit.normal);
+  assert_eq!(hit.geometric_normal, Vec3A::Z);
+  assert!((hit.u - 0.3).abs() < 1e-5 && (hit.v - 0.5).abs() < 1e-5, "{} {}", hit.u, hit.v);
 }
@@ -0,0 +1,258 @@
+use std::fmt;
+use std::sync::Arc;
+
+use crate::aabb::Aabb;
+use crate::bvh::BvhNodes;
+use crate::color::Color;
+use crate::hitable::{HitRecord, Hitable};
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::render_error::RenderError;
+use crate::texture::Bitmap;
+use crate::triangle::{interpolate, intersect, shading_normal};
+use glam::{Vec2, Vec3A};
+
+// Triangles sharing their vertices. Every face indexes the positions and, when the mesh
+// has them, the normals, the texture coordinates and the colors, which are as many as the
+// positions.
+// The mesh keeps its own BVH, in the scene it is a single hitable that can be instanced.
+pub struct TriangleMesh {
+    positions: Vec<Vec3A>,
+    normals: Vec<Vec3A>,
+    uvs: Vec<Vec2>,
+    colors: Vec<Color>,
+    // in the order of the leaves of the BVH
+    faces: Vec<[u32; 3]>,
+    bvh: BvhNodes,
+    material: Arc<Material>,
+    // tangent space normals, they need the texture coordinates
+    normal_map: Option<Bitmap>,
+}
+
+impl TriangleMesh {
+    pub fn new(positions: Vec<Vec3A>, faces: Vec<[u32; 3]>, material: Arc<Material>) -> Self {
+        let aabbs = faces
+            .iter()
+            .map(|face| {
+                let [a, b, c] = face.map(|i| positions[i as usize]);
+                Aabb {
+                    min: a.min(b).min(c),
+                    max: a.max(b).max(c),
+                }
+            })
+            .enumerate()
+            .collect();
+        let (bvh, order) = BvhNodes::new(aabbs);
+        let faces = order.into_iter().map(|index| faces[index]).collect();
+        TriangleMesh {
+            positions,
+            normals: Vec::new(),
+            uvs: Vec::new(),
+            colors: Vec::new(),
+            faces,
+            bvh,
+            material,
+            normal_map: None,
+        }
+    }
+
+    // Smooth shading, there has to be a normal for every position
+    pub fn with_normals(mut self, normals: Vec<Vec3A>) -> Result<Self, RenderError> {
+        self.check_vertex_count("normals", normals.len())?;
+        self.normals = normals;
+        Ok(self)
+    }
+
+    // Texture coordinates, a pair for every position. Without them the hits report the
+    // barycentric coordinates.
+    pub fn with_uvs(mut self, uvs: Vec<Vec2>) -> Result<Self, RenderError> {
+        self.check_vertex_count("uvs", uvs.len())?;
+        self.uvs = uvs;
+        Ok(self)
+    }
+
+    // Vertex colors, one for every position. The hits carry them to the materials
+    // textured with `Texture::VertexColor`.
+    pub fn with_colors(mut self, colors: Vec<Color>) -> Result<Self, RenderError> {
+        self.check_vertex_count("colors", colors.len())?;
+        self.colors = colors;
+        Ok(self)
+    }
+
+    // A normal map, in the tangent space given by the texture coordinates
+    pub fn with_normal_map(mut self, normal_map: Bitmap) -> Self {
+        self.normal_map = Some(normal_map);
+        self
+    }
+
+    pub fn triangle_count(&self) -> usize {
+        self.faces.len()
+    }
+
+    fn check_vertex_count(&self, what: &str, count: usize) -> Result<(), RenderError> {
+        if count == self.positions.len() {
+            return Ok(());
+        }
+        Err(RenderError {
+            kind: String::from("mesh"),
+            message: format!("{} {} for {} vertices", count, what, self.positions.len()),
+        })
+    }
+
+    fn vertices(&self, face: usize) -> [Vec3A; 3] {
+        self.faces[face].map(|i| self.positions[i as usize])
+    }
+}
+
+impl Hitable for TriangleMesh {
+    fn hit(&self, r: &Ray, tmin: f32, tmax: f32) -> Option<HitRecord<'_>> {
+        let (t, face, u, v) = self.bvh.hit(r, tmin, tmax, |leaf, mut tmax| {
+            let mut hit = None;
+            for face in leaf {
+                let [a, b, c] = self.vertices(face);
+                if let Some((t, u, v)) = intersect(a, b, c, r, tmin, tmax) {
+                    tmax = t;
+                    hit = Some((t, (t, face, u, v)));
+                }
+            }
+            hit
+        })?;
+
+        let [a, b, c] = self.vertices(face);
+        let indices = self.faces[face].map(|i| i as usize);
+        let mut geometric_normal = (b - a).cross(c - a).normalize();
+        let normal = if self.normals.is_empty() {
+            geometric_normal
+        } else {
+            let normals = indices.map(|i| self.normals[i]);
+            // the winding can disagree with the vertex normals
+            if geometric_normal.dot(normals[0] + normals[1] + normals[2]) < 0.0 {
+                geometric_normal = -geometric_normal;
+            }
+            shading_normal(normals, geometric_normal, u, v)
+        };
+        let (normal, uv) = if self.uvs.is_empty() {
+            (normal, Vec2::new(u, v))
+        } else {
+            let uvs = indices.map(|i| self.uvs[i]);
+            let uv = interpolate(uvs, u, v);
+            match &self.normal_map {
+                Some(map) => (map_normal(map, [a, b, c], uvs, uv, normal), uv),
+                None => (normal, uv),
+            }
+        };
+        let vertex_color = if self.colors.is_empty() {
+            None
+        } else {
+            Some(interpolate(indices.map(|i| self.colors[i]), u, v))
+        };
+
+        Some(HitRecord {
+            t,
+            pos: r.point_at_parameter(t),
+            normal,
+            geometric_normal,
+            mat: &self.material,
+            u: uv.x,
+            v: uv.y,
+            vertex_color,
+        })
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        self.bvh.aabb()
+    }
+
+    fn material(&self) -> Option<&Material> {
+        Some(&self.material)
+    }
+}
+
+// The normal of the map at `uv`, in the frame of the tangent and the bitangent along
+// which the texture coordinates of the triangle grow
+fn map_normal(
+    map: &Bitmap,
+    vertices: [Vec3A; 3],
+    uvs: [Vec2; 3],
+    uv: Vec2,
+    normal: Vec3A,
+) -> Vec3A {
+    let (e1, e2) = (vertices[1] - vertices[0], vertices[2] - vertices[0]);
+    let (d1, d2) = (uvs[1] - uvs[0], uvs[2] - uvs[0]);
+    let det = d1.x * d2.y - d2.x * d1.y;
+    if det.abs() < 1e-12 {
+        return normal;
+    }
+    let tangent = (e1 * d2.y - e2 * d1.y) / det;
+    let bitangent = (e2 * d1.x - e1 * d2.x) / det;
+    let tangent = (tangent - normal * normal.dot(tangent)).normalize_or_zero();
+    // mirrored texture coordinates flip the bitangent
+    let handedness = normal.cross(tangent).dot(bitangent).signum();
+    let bitangent = normal.cross(tangent) * handedness;
+
+    let n = map.linear_value(uv.x, uv.y) * 2.0 - Vec3A::ONE;
+    let mapped = (tangent * n.x + bitangent * n.y + normal * n.z).normalize_or_zero();
+    if mapped == Vec3A::ZERO {
+        normal
+    } else {
+        mapped
+    }
+}
+
+impl fmt::Display for TriangleMesh {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "mesh with {:?} triangles, {:?} vertices and {:?} BVH nodes",
+            self.faces.len(),
+            self.positions.len(),
+            self.bvh.len()
+        )
+    }
+}
+
+#[test]
+fn test_interpolated_normal_and_uv() {
+    use crate::test_utils::white_material;
+
+    // a unit square in two triangles, the normals lean out of its corners
+    let positions = vec![Vec3A::ZERO, Vec3A::X, Vec3A::new(1.0, 1.0, 0.0), Vec3A::Y];
+    let normals: Vec<Vec3A> = positions
+        .iter()
+        .map(|p| (*p - Vec3A::new(0.5, 0.5, -1.0)).normalize())
+        .collect();
+    let uvs = vec![
+        Vec2::new(0.0, 0.0),
+        Vec2::new(2.0, 0.0),
+        Vec2::new(2.0, 4.0),
+        Vec2::new(0.0, 4.0),
+    ];
+    let mesh = || {
+        TriangleMesh::new(
+            positions.clone(),
+            vec![[0, 1, 2], [0, 2, 3]],
+            Arc::new(white_material()),
+        )
+    };
+    let square = mesh()
+        .with_normals(normals.clone())
+        .unwrap()
+        .with_uvs(uvs)
+        .unwrap();
+
+    // (0.25, 0.75) is in the second triangle, at 0.25 of the vertex 2 and 0.5 of the vertex 3
+    let ray = Ray::new(Vec3A::new(0.25, 0.75, 1.0), -Vec3A::Z);
+    let hit = square.hit(&ray, 0.001, f32::MAX).unwrap();
+    let normal = (0.25 * normals[0] + 0.25 * normals[2] + 0.5 * normals[3]).normalize();
+    assert!((hit.normal - normal).length() < 1e-5, "{}", hit.normal);
+    assert_eq!(hit.geometric_normal, Vec3A::Z);
+    assert!(
+        (hit.u - 0.5).abs() < 1e-5 && (hit.v - 3.0).abs() < 1e-5,
+        "{} {}",
+        hit.u,
+        hit.v
+    );
+
+    // a normal missing is an error, not a panic
+    assert!(mesh().with_normals(normals[..3].to_vec()).is_err());
+}
@@ -1,19 +1,45 @@
+use std::fs::File;
+use std::io::BufReader;
 use std::{path::Path, sync::Arc};
 
-use glam::Vec3A;
-use rand::Rng;
-use tobj::load_obj;
+use glam::{Vec2, Vec3A};
+use rand::rngs::SmallRng;
+use rand::{Rng, SeedableRng};
+use tobj::{load_mtl, load_obj_buf, LoadError};
 use tobj::Material as TobjMaterial;
 use crate::color::Color;
 use crate::material::*;
 
 use crate::hitable::{HitableStore};
+use crate::mesh_import::mesh_error;
 use crate::render_error::RenderError;
 use crate::texture::Texture;
-use crate::triangle::Triangle;
+use crate::triangle_mesh::TriangleMesh;
 
-pub fn random_vec3(min: f32, max: f32) -> Vec3A {
-    let mut rng = rand::thread_rng();
+// A random generator for a single camera sample, derived from the seed of the render.
+// The same seed, pixel and sample always give the same numbers, whatever the order
+// and the thread in which the pixels are rendered.
+pub fn sample_rng(seed: u64, x: u32, y: u32, sample: usize) -> SmallRng {
+    let pixel = ((x as u64) << 32) | y as u64;
+    let hash = splitmix64(splitmix64(splitmix64(seed) ^ pixel) ^ sample as u64);
+    SmallRng::seed_from_u64(hash)
+}
+
+// The seed used to render a frame of an animation, so that every frame has its own noise
+// but can be rendered again on its own.
+pub fn frame_seed(seed: u64, frame: u32) -> u64 {
+    splitmix64(seed ^ splitmix64(frame as u64))
+}
+
+// http://prng.di.unimi.it/splitmix64.c
+fn splitmix64(value: u64) -> u64 {
+    let mut z = value.wrapping_add(0x9E3779B97F4A7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+    z ^ (z >> 31)
+}
+
+pub fn random_vec3(rng: &mut SmallRng, min: f32, max: f32) -> Vec3A {
     Vec3A::new(
         rng.gen_range(min..max),
         rng.gen_range(min..max),
@@ -21,15 +47,63 @@ pub fn random_vec3(min: f32, max: f32) -> Vec3A {
     )
 }
 
-pub fn random_in_unit_sphere() -> Vec3A {
+pub fn random_in_unit_sphere(rng: &mut SmallRng) -> Vec3A {
     loop {
-        let p = random_vec3(-1.0, 1.0);
+        let p = random_vec3(rng, -1.0, 1.0);
         if p.length_squared() < 1.0 {
             return p;
         }
     }
 }
 
+pub fn random_unit_vector(rng: &mut SmallRng) -> Vec3A {
+    random_in_unit_sphere(rng).normalize()
+}
+
+// Returns a random direction from `origin` inside the cone subtended by the sphere
+// with the given center and radius. If the origin is inside the sphere, any direction is valid.
+pub fn random_towards_sphere(
+    rng: &mut SmallRng,
+    origin: Vec3A,
+    center: Vec3A,
+    radius: f32,
+) -> Vec3A {
+    let direction = center - origin;
+    let distance_squared = direction.length_squared();
+    if distance_squared <= radius * radius {
+        return random_unit_vector(rng);
+    }
+    let one_minus_cos_theta_max = one_minus_cos_theta_max(radius, distance_squared);
+    let z = 1.0 - rng.gen::<f32>() * one_minus_cos_theta_max;
+    let phi = 2.0 * std::f32::consts::PI * rng.gen::<f32>();
+    let sin_theta = (1.0 - z * z).max(0.0).sqrt();
+
+    let w = direction.normalize();
+    let (u, v) = w.any_orthonormal_pair();
+    u * (phi.cos() * sin_theta) + v * (phi.sin() * sin_theta) + w * z
+}
+
+// Solid angle pdf of the directions generated by `random_towards_sphere`.
+pub fn pdf_towards_sphere(origin: Vec3A, direction: Vec3A, center: Vec3A, radius: f32) -> f32 {
+    let to_center = center - origin;
+    let distance_squared = to_center.length_squared();
+    if distance_squared <= radius * radius {
+        return 1.0 / (4.0 * std::f32::consts::PI);
+    }
+    let one_minus_cos_theta_max = one_minus_cos_theta_max(radius, distance_squared);
+    let cosine = direction.normalize().dot(to_center.normalize());
+    if cosine < 1.0 - one_minus_cos_theta_max {
+        return 0.0;
+    }
+    1.0 / (2.0 * std::f32::consts::PI * one_minus_cos_theta_max)
+}
+
+// 1 - cos(theta_max) written so that it does not lose precision for small, far away spheres.
+fn one_minus_cos_theta_max(radius: f32, distance_squared: f32) -> f32 {
+    let x = radius * radius / distance_squared;
+    x / (1.0 + (1.0 - x).sqrt())
+}
+
 pub fn near_zero(vec: &Vec3A) -> bool {
     vec.x.abs() < f32::EPSILON && vec.y.abs() < f32::EPSILON && vec.z.abs() < f32::EPSILON
 }
@@ -67,55 +141,215 @@ pub fn reflect(v: &Vec3A, n: &Vec3A) -> Vec3A {
     *v - *n * (2.0 * v.dot(*n))
 }
 
+// One triangle mesh for every model of the obj file. Polygons are triangulated, the
+// materials come from the mtl files and fall back to a grey lambertian when they are
+// missing.
 pub fn load_obj_to_hitable(path: &Path) -> Result<HitableStore, RenderError> {
     let mut hitables = HitableStore::new();
 
-    let obj_file = load_obj(&path, &tobj::LoadOptions::default())?;
-    let models = obj_file.0;
-    let mtls = obj_file.1?;
+    // a single index, so that normals and texture coordinates are indexed like the positions
+    let load_options = tobj::LoadOptions {
+        single_index: true,
+        triangulate: true,
+        ignore_points: true,
+        ignore_lines: true,
+    };
+    let file = File::open(path).map_err(|error| mesh_error("obj", path, error))?;
+    let (models, mtls) = load_obj_buf(&mut BufReader::new(file), &load_options, |mtl_path| {
+        let mtl_path = path.parent().unwrap_or(Path::new("")).join(mtl_path);
+        let (mut materials, names) = load_mtl(&mtl_path)?;
+        // the textures are relative to the mtl file
+        let mtl_dir = mtl_path.parent().unwrap_or(Path::new(""));
+        for material in materials.iter_mut() {
+            if let Some(texture) = &material.diffuse_texture {
+                material.diffuse_texture =
+                    Some(mtl_dir.join(texture_file(texture)).to_string_lossy().into());
+            }
+        }
+        Ok((materials, names))
+    })
+    .map_err(|error| mesh_error("obj", path, error))?;
+    // without its mtl file the model is still rendered, with the default material
+    let mtls = match mtls {
+        Ok(mtls) => mtls,
+        Err(LoadError::OpenFileFailed) => Vec::new(),
+        Err(error) => return Err(mesh_error("obj", path, error)),
+    };
 
     let default_mat: Arc<Material> = Arc::new(Material::lambertian(Texture::constant_color(Color::new(0.6f32, 0.6f32, 0.6f32))));
-    let materials: Vec<Arc<Material>> = mtls.iter().map(|m: &TobjMaterial| {
-      let mat: Arc<Material> = match m.illumination_model {
-        Some(7) => Arc::new(Material::dielectric(m.optical_density.unwrap())),
-        Some(5) => Arc::new(Material::metal(Texture::constant_color(Color::new(m.diffuse.unwrap()[0], m.diffuse.unwrap()[1], m.diffuse.unwrap()[2])), 1.0f32 / m.shininess.unwrap() as f32)),
-        _ => Arc::new(Material::lambertian(Texture::constant_color(Color::new(m.diffuse.unwrap()[0], m.diffuse.unwrap()[1], m.diffuse.unwrap()[2]))))
-      };
-  
-      mat
-    }).collect();
-  
+    let materials = mtls
+        .iter()
+        .map(|m| obj_material(m).map(Arc::new))
+        .collect::<Result<Vec<_>, _>>()?;
+
     for m in models.iter() {
-      let mesh = &m.mesh;
-      for f in 0..mesh.indices.len() / 3 {
-        let i0 = mesh.indices[3 * f] as usize;
-        let i1 = mesh.indices[3 * f + 1] as usize;
-        let i2 = mesh.indices[3 * f + 2] as usize;
-        let v0 = Vec3A::new(mesh.positions[i0 * 3], mesh.positions[i0 * 3 + 1], mesh.positions[i0 * 3 + 2]);
-        let v1 = Vec3A::new(mesh.positions[i1 * 3], mesh.positions[i1 * 3 + 1], mesh.positions[i1 * 3 + 2]);
-        let v2 = Vec3A::new(mesh.positions[i2 * 3], mesh.positions[i2 * 3 + 1], mesh.positions[i2 * 3 + 2]);
-  
-        let mat: Arc<Material> = match mesh.material_id {
-          Some(id) => Arc::clone(&materials[id]),
-          None => Arc::clone(&default_mat)
+        let mesh = &m.mesh;
+        if mesh.indices.is_empty() {
+            continue;
+        }
+        let mat = match mesh.material_id.and_then(|id| materials.get(id)) {
+            Some(mat) => Arc::clone(mat),
+            None => Arc::clone(&default_mat),
         };
-  
-        let tri: Triangle;
-        // TODO, this code is assuming that all the meshes are triangulated
-        // but some are not, like Blender by default exports quad meshes.
-        // Make this code more robust, so that it can work with both triangulated
-        // and not triangulated meshes
-        if mesh.normals.len() > 0 && (i0 * 3 + 2) <= mesh.normals.len(){
-          let normal = Vec3A::new(mesh.normals[i0 * 3], mesh.normals[i0 * 3 + 1], mesh.normals[i0 * 3 + 2]);
-          tri = Triangle::new_with_normal(v0, v1, v2, normal, mat)
-        } else {
-          tri = Triangle::new(v0, v1, v2, Arc::clone(&mat));
+
+        let n_vertices = mesh.positions.len() / 3;
+        let positions = mesh
+            .positions
+            .chunks_exact(3)
+            .map(|p| Vec3A::new(p[0], p[1], p[2]))
+            .collect();
+        let faces = mesh
+            .indices
+            .chunks_exact(3)
+            .map(|f| [f[0], f[1], f[2]])
+            .collect();
+        let mut triangles = TriangleMesh::new(positions, faces, mat);
+        // some files have normals or texture coordinates only on some faces
+        if mesh.normals.len() == 3 * n_vertices {
+            triangles = triangles.with_normals(
+                mesh.normals
+                    .chunks_exact(3)
+                    .map(|n| Vec3A::new(n[0], n[1], n[2]))
+                    .collect(),
+            )?;
         }
-  
-        hitables.push(tri);
-        
-      }
+        if mesh.texcoords.len() == 2 * n_vertices {
+            triangles = triangles.with_uvs(
+                mesh.texcoords
+                    .chunks_exact(2)
+                    .map(|uv| Vec2::new(uv[0], uv[1]))
+                    .collect(),
+            )?;
+        }
+        hitables.push(triangles);
     }
     Ok(hitables)
+}
+
+// Emitters have a Ke color, transparent materials a glass illumination model or a
+// dissolve below 1 (`d`, or `Tr` that is 1 - d), the mirrors a reflective illumination
+// model, a metallic microfacet one. Everything else is lambertian, textured by map_Kd.
+fn obj_material(m: &TobjMaterial) -> Result<Material, RenderError> {
+    let color = |values: [f32; 3]| Color::new(values[0], values[1], values[2]);
+    let emission = m.unknown_param.get("Ke").and_then(|ke| parse_color(ke));
+    if let Some(emission) = emission.filter(|ke| ke.iter().any(|c| *c > 0.0)) {
+        return Ok(Material::diffuse_light(Texture::constant_color(color(
+            emission,
+        ))));
+    }
+
+    let transmission = m
+        .unknown_param
+        .get("Tr")
+        .and_then(|tr| tr.trim().parse::<f32>().ok());
+    let dissolve = m
+        .dissolve
+        .or(transmission.map(|tr| 1.0 - tr))
+        .unwrap_or(1.0);
+    let index_of_refraction = m.optical_density.filter(|ni| *ni >= 1.0).unwrap_or(1.5);
+    if matches!(m.illumination_model, Some(4 | 6 | 7 | 9)) || dissolve < 1.0 {
+        // the transmission filter `Tf` tints the glass
+        let tint = m
+            .unknown_param
+            .get("Tf")
+            .and_then(|tf| parse_color(tf))
+            .unwrap_or([1.0; 3]);
+        return Ok(Material::Dielectric(
+            Dielectric::new(index_of_refraction).with_tint(Texture::constant_color(color(tint))),
+        ));
+    }
 
-  }
+    let albedo = match &m.diffuse_texture {
+        Some(path) => Texture::bitmap(Path::new(path)).map_err(|error| RenderError {
+            kind: error.kind,
+            message: format!("{}: {}", path, error.message),
+        })?,
+        None => Texture::constant_color(color(m.diffuse.unwrap_or([0.6, 0.6, 0.6]))),
+    };
+    match m.illumination_model {
+        Some(3 | 5) => {
+            // the exponent of the highlight goes up to 1000 for a perfect mirror, a Phong
+            // exponent n has about the width of a GGX alpha of sqrt(2 / (n + 2))
+            let roughness = m
+                .shininess
+                .map_or(0.0, |ns| (2.0 / (ns.max(0.0) + 2.0)).powf(0.25));
+            let constant = |value: f32| Texture::constant_color(Color::new(value, value, value));
+            Ok(Material::microfacet(
+                albedo,
+                constant(1.0),
+                constant(roughness),
+                constant(0.5),
+            ))
+        }
+        _ => Ok(Material::lambertian(albedo)),
+    }
+}
+
+fn parse_color(text: &str) -> Option<[f32; 3]> {
+    let mut values = text
+        .split_whitespace()
+        .map(|value| value.parse::<f32>().ok());
+    Some([values.next()??, values.next()??, values.next()??])
+}
+
+// The file of a texture statement, after options like `-bm 1.0`
+fn texture_file(statement: &str) -> &str {
+    if statement.starts_with('-') {
+        statement.split_whitespace().last().unwrap_or(statement)
+    } else {
+        statement
+    }
+}
+
+#[test]
+fn test_load_obj_polygons_and_materials() {
+    let dir = std::env::temp_dir().join("tuot_obj_test");
+    std::fs::create_dir_all(dir.join("materials")).unwrap();
+    image::RgbImage::new(2, 2)
+        .save(dir.join("materials/checker.png"))
+        .unwrap();
+    std::fs::write(
+        dir.join("materials/test.mtl"),
+        "newmtl bare\nillum 2\n\
+         newmtl textured\nmap_Kd -bm 1.0 checker.png\n\
+         newmtl lamp\nKe 4 4 4\n\
+         newmtl glass\nTr 0.8\n",
+    )
+    .unwrap();
+    // a quad, a pentagon, a triangle and a face without material
+    std::fs::write(
+        dir.join("test.obj"),
+        "mtllib materials/test.mtl\n\
+         v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\nv 0.5 1.5 0\n\
+         o a\nusemtl bare\nf 1 2 3 4\n\
+         o b\nusemtl textured\nf 1 2 3 5 4\n\
+         o c\nusemtl lamp\nf 1 2 3\n\
+         o d\nusemtl glass\nf 1 2 3\n\
+         o e\nf 1 2 3\n",
+    )
+    .unwrap();
+
+    let hitables = load_obj_to_hitable(&dir.join("test.obj")).unwrap();
+    let materials: Vec<_> = hitables.iter().map(|h| h.material().unwrap()).collect();
+    assert_eq!(materials.len(), 5);
+    // points covered only by the second triangle of the quad and by the tip of the pentagon
+    let towards_xy = |x, y| crate::ray::Ray::new(Vec3A::new(x, y, 1.0), Vec3A::new(0.0, 0.0, -1.0));
+    assert!(hitables[0]
+        .hit(&towards_xy(0.2, 0.8), 0.001, 10.0)
+        .is_some());
+    assert!(hitables[1]
+        .hit(&towards_xy(0.5, 1.3), 0.001, 10.0)
+        .is_some());
+    assert!(matches!(
+        materials[1],
+        Material::Lambertian(Lambertian {
+            albedo: Texture::Bitmap(_)
+        })
+    ));
+    assert!(materials[2].is_emitter());
+    assert!(matches!(materials[3], Material::Dielectric(_)));
+
+    // a missing texture is an error, not a panic
+    std::fs::remove_file(dir.join("materials/checker.png")).unwrap();
+    assert!(load_obj_to_hitable(&dir.join("test.obj")).is_err());
+}